@@ -0,0 +1,270 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use sha1::{Digest, Sha1};
+use zip::ZipArchive;
+use crate::dirs::{self, paths};
+use crate::game;
+use crate::info;
+use crate::output;
+use crate::ArchiveAction;
+
+/// Prefix of the version strings identifying archives,
+/// distinguishing them from revisions in the game repository.
+pub const ARCHIVE_VERSION_PREFIX: &str = "archive:";
+
+/// A registered `.love` file or zip archive of the game, run without a Git repository.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GameArchive {
+    pub name: String,
+    /// Absolute path to the archive
+    pub path: String,
+}
+
+impl GameArchive {
+    /// The identifier used in place of a version, e.g. `archive:0.17.21`.
+    pub fn id(&self) -> String {
+        return format!("{ARCHIVE_VERSION_PREFIX}{}", self.name);
+    }
+
+    /// Where the archive is extracted to.
+    pub fn get_extract_path(&self) -> PathBuf {
+        return paths::get_archives_cache_path().join(&self.name);
+    }
+
+    /// Records which archive contents are in the extract directory.
+    fn get_hash_path(&self) -> PathBuf {
+        return paths::get_archives_cache_path().join(format!("{}.sha1", self.name));
+    }
+}
+
+pub fn is_archive_version(version: &str) -> bool {
+    return version.starts_with(ARCHIVE_VERSION_PREFIX);
+}
+
+/// Finds the directory inside the archive holding `conf.lua`.
+/// Release `.love` files have it at the root, while source archives
+/// downloaded from GitHub wrap everything in a single directory.
+fn find_game_root<'a>(file_names: impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut roots: Vec<String> = file_names
+        .filter_map(|name| name.strip_suffix("conf.lua"))
+        .filter(|prefix| prefix.is_empty() || (prefix.matches('/').count() == 1 && prefix.ends_with('/')))
+        .map(|prefix| prefix.to_string())
+        .collect();
+
+    roots.sort();
+
+    // A root-level conf.lua wins over a nested one
+    return roots.into_iter().next();
+}
+
+#[test]
+fn test_find_game_root() {
+    let release = ["main.lua", "conf.lua", "parts/conf.lua"];
+    assert_eq!(find_game_root(release.into_iter()), Some("".to_string()));
+
+    let source = ["Techmino-0.17.21/", "Techmino-0.17.21/conf.lua", "Techmino-0.17.21/main.lua"];
+    assert_eq!(find_game_root(source.into_iter()), Some("Techmino-0.17.21/".to_string()));
+
+    let deeply_nested = ["a/b/conf.lua", "main.lua"];
+    assert_eq!(find_game_root(deeply_nested.into_iter()), None);
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<fs::File>, Box<dyn std::error::Error + Send + Sync>> {
+    return Ok(ZipArchive::new(fs::File::open(path)?)?);
+}
+
+/// Loads the registered archives.
+/// A missing or unreadable registry is treated as empty.
+pub fn load_archives() -> Vec<GameArchive> {
+    let path = paths::get_archives_registry_path();
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![]
+    };
+
+    return serde_json::from_str(&contents)
+        .unwrap_or_else(|e| {
+            eprintln!("Ignoring invalid archive registry at {path:?}: {e}");
+            vec![]
+        });
+}
+
+fn save_archives(archives: &[GameArchive]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = paths::get_archives_registry_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, serde_json::to_string_pretty(archives)?)?;
+
+    return Ok(());
+}
+
+pub fn find(id: &str) -> Option<GameArchive> {
+    return load_archives()
+        .into_iter()
+        .find(|archive| archive.id() == id);
+}
+
+/// Registers an archive, replacing any archive with the same name.
+/// The name defaults to the file name without its extension.
+pub fn register(path: &str, name: Option<&str>) -> Result<GameArchive, Box<dyn std::error::Error + Send + Sync>> {
+    let path = fs::canonicalize(path)
+        .map_err(|e| format!("Cannot read '{path}': {e}"))?;
+
+    let name = match name {
+        Some(name) => name.trim().to_string(),
+        None => path.file_stem()
+            .map(|stem| stem.to_string_lossy().replace(' ', "_"))
+            .unwrap_or_default()
+    };
+
+    dirs::validate_file_name("archive name", &name)?;
+
+    let archive = open_archive(&path)
+        .map_err(|e| format!("'{}' is not a .love file or zip archive: {e}", path.to_string_lossy()))?;
+
+    if find_game_root(archive.file_names()).is_none() {
+        return Err(format!("'{}' does not contain the game's conf.lua file", path.to_string_lossy()).into());
+    }
+
+    let archive = GameArchive {
+        name,
+        path: path.to_string_lossy().to_string(),
+    };
+
+    let mut archives = load_archives();
+    archives.retain(|other| other.name != archive.name);
+    archives.push(archive.clone());
+
+    save_archives(&archives)?;
+
+    // The archive may have been replaced with different contents
+    remove_extracted(&archive);
+
+    return Ok(archive);
+}
+
+fn remove_extracted(archive: &GameArchive) {
+    let _ = fs::remove_dir_all(archive.get_extract_path());
+    let _ = fs::remove_file(archive.get_hash_path());
+}
+
+/// Removes an archive from the registry, along with its extracted files.
+/// The archive itself is left untouched.
+/// Returns whether an archive with that ID was registered.
+pub fn unregister(id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut archives = load_archives();
+
+    let archive = match archives.iter().find(|archive| archive.id() == id) {
+        Some(archive) => archive.clone(),
+        None => return Ok(false)
+    };
+
+    archives.retain(|other| other.id() != id);
+    save_archives(&archives)?;
+
+    remove_extracted(&archive);
+
+    return Ok(true);
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    return Ok(format!("{:x}", hasher.finalize()));
+}
+
+/// Extracts the game files of an archive, unless they are already extracted and untouched.
+/// Returns the directory holding `conf.lua`.
+pub fn extract(archive: &GameArchive) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let extract_path = archive.get_extract_path();
+    let hash_path = archive.get_hash_path();
+
+    let hash = hash_file(Path::new(&archive.path))
+        .map_err(|e| format!("Cannot read archive '{}': {e}", archive.path))?;
+
+    let conf_lua = fs::read_to_string(extract_path.join("conf.lua")).ok();
+    let intact = conf_lua.is_some_and(|conf_lua| !game::is_injected(&conf_lua));
+
+    if intact && fs::read_to_string(&hash_path).ok().as_deref() == Some(hash.as_str()) {
+        return Ok(extract_path);
+    }
+
+    info!("Extracting {}...", archive.path);
+
+    remove_extracted(archive);
+
+    let mut zip_archive = open_archive(Path::new(&archive.path))?;
+    let root = find_game_root(zip_archive.file_names())
+        .ok_or(format!("'{}' does not contain the game's conf.lua file", archive.path))?;
+
+    let temp_path = extract_path.with_file_name(format!("~{}", archive.name));
+    let _ = fs::remove_dir_all(&temp_path);
+    fs::create_dir_all(&temp_path)?;
+
+    zip_archive.extract(&temp_path)?;
+    fs::rename(temp_path.join(&root), &extract_path)?;
+    let _ = fs::remove_dir_all(&temp_path);
+
+    fs::write(hash_path, hash)?;
+
+    return Ok(extract_path);
+}
+
+/// Runs a `boxedmino archive` subcommand, returning the exit code.
+pub fn run(action: &ArchiveAction) -> i32 {
+    match action {
+        ArchiveAction::Add { path, name } => {
+            match register(path, name.as_deref()) {
+                Ok(archive) if output::is_json() => {
+                    output::print_json(&archive);
+                    return 0;
+                }
+                Ok(archive) => {
+                    println!("Registered archive '{}'", archive.id());
+                    info!("Run it with `boxedmino run --version {}`.", archive.id());
+                    return 0;
+                }
+                Err(e) => {
+                    eprintln!("Failed to register archive: {e}");
+                    return 1;
+                }
+            }
+        }
+        ArchiveAction::List => {
+            if output::is_json() {
+                output::print_json(&load_archives());
+                return 0;
+            }
+
+            for archive in load_archives() {
+                println!("{}\t{}", archive.id(), archive.path);
+            }
+            return 0;
+        }
+        ArchiveAction::Remove { id } => {
+            match unregister(id) {
+                Ok(true) if output::is_json() => {
+                    output::print_json(&serde_json::json!({ "removed": id }));
+                    return 0;
+                }
+                Ok(true) => {
+                    println!("Unregistered archive '{id}'");
+                    return 0;
+                }
+                Ok(false) => {
+                    eprintln!("No archive with ID '{id}' is registered.");
+                    return 1;
+                }
+                Err(e) => {
+                    eprintln!("Failed to unregister archive: {e}");
+                    return 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use crate::cold_clear;
+use crate::dirs::{self, paths};
+use crate::git;
+use crate::info;
+use crate::output;
+use crate::progress::ProgressStyle;
+use crate::transfer::{self, CloneMode};
+
+/// Names of the files inside an offline bundle.
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const GIT_BUNDLE_FILE_NAME: &str = "techmino.bundle";
+const COLD_CLEAR_DIR_NAME: &str = "cold_clear";
+
+/// Describes the contents of an offline bundle.
+///
+/// A bundle is a zip archive holding this manifest, a `git bundle` of the
+/// game repository and the cached Cold Clear release archives, so a machine
+/// without internet access can be set up and kept up to date.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manifest {
+    pub boxedmino_version: String,
+    /// When the bundle was made, in RFC 3339 format
+    pub created_at: String,
+    pub tags: Vec<String>,
+    pub cold_clear_versions: Vec<String>,
+}
+
+/// What an import added.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ImportReport {
+    pub new_tags: Vec<String>,
+    pub new_cold_clear_versions: Vec<String>,
+}
+
+/// A scratch path for the git bundle while it is written or read.
+fn get_temp_git_bundle_path() -> PathBuf {
+    return std::env::temp_dir()
+        .join(format!("boxedmino_{}_{}", std::process::id(), GIT_BUNDLE_FILE_NAME));
+}
+
+fn write_bundle(
+    output_path: &Path,
+    manifest: &Manifest,
+    git_bundle_path: &Path
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut zip = ZipWriter::new(fs::File::create(output_path)?);
+
+    // Both the git bundle and the Cold Clear archives are already compressed
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+
+    zip.start_file(MANIFEST_FILE_NAME, options)?;
+    serde_json::to_writer_pretty(&mut zip, manifest)?;
+
+    zip.start_file(GIT_BUNDLE_FILE_NAME, options)?;
+    io::copy(&mut fs::File::open(git_bundle_path)?, &mut zip)?;
+
+    for version in &manifest.cold_clear_versions {
+        let archive_path = paths::get_cold_clear_download_path(version);
+
+        zip.start_file(format!("{COLD_CLEAR_DIR_NAME}/{version}.zip"), options)?;
+        io::copy(&mut fs::File::open(archive_path)?, &mut zip)?;
+    }
+
+    zip.finish()?;
+
+    return Ok(());
+}
+
+/// Writes a bundle of the game repository's tags and branches,
+/// along with the given downloaded Cold Clear releases.
+pub fn export(repo_path: &str, output_path: &Path, cold_clear_versions: &[String]) -> Result<Manifest, String> {
+    let manifest = Manifest {
+        boxedmino_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: chrono::Local::now().to_rfc3339(),
+        tags: git::tags(repo_path),
+        cold_clear_versions: cold_clear_versions.to_vec(),
+    };
+
+    let git_bundle_path = get_temp_git_bundle_path();
+
+    info!("Bundling the game repository...");
+
+    let result = git::run_checked(repo_path, &[
+        "bundle", "create", "--quiet",
+        &git_bundle_path.to_string_lossy(),
+        "HEAD", "--branches", "--tags",
+    ])
+        .map_err(|e| format!("Failed to bundle the game repository: {e}"))
+        .and_then(|_| {
+            info!("Writing {}...", output_path.to_string_lossy());
+            return write_bundle(output_path, &manifest, &git_bundle_path)
+                .map_err(|e| format!("Failed to write {}: {e}", output_path.to_string_lossy()));
+        });
+
+    let _ = fs::remove_file(&git_bundle_path);
+
+    result?;
+
+    return Ok(manifest);
+}
+
+/// Reads an offline bundle, writing its git bundle to a scratch file.
+/// Cold Clear releases that are not cached yet are copied to the cache.
+fn unpack(bundle_path: &Path, report: &mut ImportReport) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let mut zip = ZipArchive::new(fs::File::open(bundle_path)?)
+        .map_err(|e| format!("'{}' is not a Boxedmino bundle: {e}", bundle_path.to_string_lossy()))?;
+
+    let manifest: Manifest = serde_json::from_reader(
+        zip.by_name(MANIFEST_FILE_NAME)
+            .map_err(|_| format!("'{}' is not a Boxedmino bundle", bundle_path.to_string_lossy()))?
+    )?;
+
+    info!("Bundle made on {} with {} tags", manifest.created_at, manifest.tags.len());
+
+    // The manifest comes from an untrusted file, and the versions name files in the cache
+    for version in &manifest.cold_clear_versions {
+        dirs::validate_file_name("Cold Clear version", version)?;
+    }
+
+    for version in &manifest.cold_clear_versions {
+        let archive_path = paths::get_cold_clear_download_path(version);
+
+        if archive_path.exists() {
+            continue;
+        }
+
+        if let Some(parent) = archive_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut entry = zip.by_name(&format!("{COLD_CLEAR_DIR_NAME}/{version}.zip"))?;
+        io::copy(&mut entry, &mut fs::File::create(&archive_path)?)?;
+
+        report.new_cold_clear_versions.push(version.clone());
+    }
+
+    let git_bundle_path = get_temp_git_bundle_path();
+
+    let mut entry = zip.by_name(GIT_BUNDLE_FILE_NAME)?;
+    io::copy(&mut entry, &mut fs::File::create(&git_bundle_path)?)?;
+
+    return Ok(git_bundle_path);
+}
+
+/// Sets up the game repository in an empty directory from a bundle, instead of cloning it.
+/// The `origin` remote still points to GitHub, for when the machine goes online.
+///
+/// Like a clone, this can be cancelled from the progress window,
+/// in which case [`transfer::CANCELLED_MESSAGE`] is returned.
+pub fn init_repo(bundle_path: &Path, repo_path: &str, style: ProgressStyle) -> Result<ImportReport, String> {
+    let mut report = ImportReport::default();
+
+    let git_bundle_path = unpack(bundle_path, &mut report)
+        .map_err(|e| e.to_string())?;
+
+    let target = Path::new(repo_path);
+
+    info!("Setting up the game repository in {repo_path}...");
+
+    let result = transfer::clone_from(&git_bundle_path.to_string_lossy(), target, CloneMode::Full, style)
+        .and_then(|_| git::run_checked(repo_path, &["remote", "set-url", "origin", git::REPO_LINK]).map(|_| ()));
+
+    let _ = fs::remove_file(&git_bundle_path);
+
+    match result {
+        Err(e) if e == transfer::CANCELLED_MESSAGE => return Err(e),
+        result => result.map_err(|e| format!("Failed to set up the game repository from the bundle: {e}"))?,
+    }
+
+    report.new_tags = git::tags(repo_path);
+
+    return Ok(report);
+}
+
+/// Adds the tags and branches of a newer bundle to an existing game repository.
+/// Branches go to `origin/*`, as if they were fetched, so local branches are left alone.
+pub fn import(bundle_path: &Path, repo_path: &str) -> Result<ImportReport, String> {
+    let mut report = ImportReport::default();
+
+    let git_bundle_path = unpack(bundle_path, &mut report)
+        .map_err(|e| e.to_string())?;
+    let git_bundle = git_bundle_path.to_string_lossy().to_string();
+
+    let old_tags = git::tags(repo_path);
+
+    info!("Importing into {repo_path}...");
+
+    // Fails if the bundle builds on commits the repository does not have
+    let result = git::run_checked(repo_path, &["bundle", "verify", "--quiet", &git_bundle])
+        .and_then(|_| git::run_checked(repo_path, &[
+            "fetch", "--quiet", &git_bundle,
+            "refs/tags/*:refs/tags/*",
+            "+refs/heads/*:refs/remotes/origin/*",
+        ]));
+
+    let _ = fs::remove_file(&git_bundle_path);
+
+    result.map_err(|e| format!("Failed to import the bundle: {e}"))?;
+
+    report.new_tags = git::tags(repo_path)
+        .into_iter()
+        .filter(|tag| !old_tags.contains(tag))
+        .collect();
+
+    return Ok(report);
+}
+
+#[test]
+fn test_export_and_import() {
+    let scratch = git::ScratchRepo::new("bundle");
+    let root = &scratch.root;
+    let source_path = scratch.path_str();
+
+    scratch.git(&["commit", "--quiet", "--allow-empty", "--message", "first"]);
+    scratch.git(&["tag", "0.1.0"]);
+
+    let first_bundle = root.join("first.zip");
+    export(&source_path, &first_bundle, &[]).unwrap();
+
+    let copy_path = root.join("copy").to_string_lossy().to_string();
+    let report = init_repo(&first_bundle, &copy_path, ProgressStyle::Quiet).unwrap();
+    assert_eq!(report.new_tags, vec!["0.1.0"]);
+    assert_eq!(git::remote_url(&copy_path, "origin").as_deref(), Some(git::REPO_LINK));
+
+    scratch.git(&["commit", "--quiet", "--allow-empty", "--message", "second"]);
+    scratch.git(&["tag", "0.2.0"]);
+
+    let second_bundle = root.join("second.zip");
+    export(&source_path, &second_bundle, &[]).unwrap();
+
+    let report = import(&second_bundle, &copy_path).unwrap();
+    assert_eq!(report.new_tags, vec!["0.2.0"]);
+    assert!(git::resolve_commit(&copy_path, "origin/main").is_some());
+}
+
+fn print_import_report(report: &ImportReport) {
+    if output::is_json() {
+        output::print_json(report);
+        return;
+    }
+
+    match report.new_tags.is_empty() {
+        true => println!("No new tags"),
+        false => println!("New tags: {}", report.new_tags.join(", ")),
+    }
+
+    if !report.new_cold_clear_versions.is_empty() {
+        println!("New Cold Clear versions: {}", report.new_cold_clear_versions.join(", "));
+    }
+}
+
+/// Runs `boxedmino repo export-bundle`, returning the exit code.
+pub fn run_export(repo_path: &str, output_path: &str) -> i32 {
+    let cold_clear_versions = cold_clear::get_available_offline_versions();
+
+    match export(repo_path, Path::new(output_path), &cold_clear_versions) {
+        Ok(manifest) if output::is_json() => {
+            output::print_json(&manifest);
+            return 0;
+        }
+        Ok(manifest) => {
+            println!(
+                "Exported {} tags and {} Cold Clear versions to {output_path}",
+                manifest.tags.len(),
+                manifest.cold_clear_versions.len()
+            );
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    }
+}
+
+/// Runs `boxedmino repo import-bundle`, returning the exit code.
+pub fn run_import(repo_path: &str, file: &str) -> i32 {
+    match import(Path::new(file), repo_path) {
+        Ok(report) => {
+            print_import_report(&report);
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    }
+}
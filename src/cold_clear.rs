@@ -1,415 +1,249 @@
 use std::ffi::OsString;
 use std::io::Write;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use std::thread;
 use std::time::{Instant, Duration};
-use std::sync::{mpsc, Arc, Mutex};
-use slint::SharedString;
-use slint::ComponentHandle;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use slint::{SharedString, ComponentHandle, Weak};
 use tokio::runtime::Runtime;
 use crate::dirs::paths;
+use crate::info;
+use crate::cold_clear::install::InstallMarker;
+use crate::progress::{
+    format_bytes,
+    format_time,
+    ProgressReporter,
+    ProgressStyle,
+    QuietProgress,
+    TerminalProgress
+};
 use crate::slint_types::ColdClearWaitWindow;
 use zip::ZipArchive;
 
-enum LoadingIPCMessage {
-    AdvanceTo(
-        /// The amount of bytes that have been downloaded.
-        i32,
-        /// The overall download rate in bytes per second
-        i32,
-        /// The ETA for the download
-        SharedString
-    ),
-    SetTotal(i32),
-    SetDeterminacy(bool),
-    Finish,
-    Error(reqwest::Error)
-}
+pub mod assets;
+pub mod cli;
+pub mod custom;
+pub mod install;
+pub mod releases;
 
-fn format_bytes(bytes: i32) -> SharedString {
-    let bytes = bytes as f64;
-    if bytes < 1e3 {
-        return format!("{bytes:.0} bytes").into();
-    } else if bytes < 1e6 {
-        return format!("{:.2} KB", bytes / 1e3).into();
-    } else if bytes < 1e9 {
-        return format!("{:.2} MB", bytes / 1e6).into();
-    } else {
-        return format!("{:.2} GB", bytes / 1e9).into();
-    }
-}
+/// Gets the download URL of the asset matching this platform
+/// in the given Cold Clear release.
+async fn get_download_url(version: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let release = releases::find_release(version).await?;
+    let asset = assets::select_asset(&release)?;
 
-#[test]
-fn test_format_bytes() {
-    let cases = [
-        (0, "0 bytes"),
-        (999, "999 bytes"),
-        (1000, "1.00 KB"),
-        (1024, "1.02 KB"),
-        (1048575, "1.05 MB"),
-        (1048576, "1.05 MB"),
-        (1073741823, "1.07 GB"),
-        (1073741824, "1.07 GB"),
-        (2147483647, "2.15 GB"),
-    ];
-
-    for (input, expected) in cases {
-        assert_eq!(format_bytes(input), expected);
-    }
-}
+    info!("Selected ColdClear asset '{}' from release '{version}'", asset.name);
 
-fn format_time(secs: i32) -> String {
-    if secs < 60 {
-        return format!("{secs:.0} seconds");
-    } else if secs < 3600 {
-        return format!("{:.0}:{:02.0}", secs / 60, secs % 60)
-    } else {
-        return format!(
-            "{:.0}:{:02.0}:{:02.0}",
-            secs / 3600,
-            secs % 3600 / 60,
-            secs % 60
-        );
-    }
-}
-
-#[test]
-fn test_format_time() {
-    let cases = [
-        (0, "0 seconds"),
-        (59, "59 seconds"),
-        (60, "1:00"),
-        (61, "1:01"),
-        (3599, "59:59"),
-        (3600, "1:00:00"),
-        (3661, "1:01:01"),
-        (86399, "23:59:59"),
-        (86400, "24:00:00"),
-    ];
-
-    for (input, expected) in cases {
-        assert_eq!(format_time(input), expected);
-    }
+    return Ok(asset.browser_download_url.clone());
 }
 
-pub fn download_cold_clear(version: &str) -> Result<(), reqwest::Error> {
-    let (tx, rx) = mpsc::channel::<LoadingIPCMessage>();
-
-    let window = ColdClearWaitWindow::new()
-        .expect("Failed to open ColdClear loading window");
-
-    window.on_format_bytes(format_bytes);
-
-    let window_weak = window.as_weak();
-    let version = version.to_owned();
-    thread::spawn(move || {
-        let rt = Runtime::new()
-            .expect("Failed to create Tokio runtime");
+/// Downloads the given Cold Clear version to its cache path,
+/// reporting progress to `progress` along the way.
+async fn download(
+    version: &str,
+    progress: &mut dyn ProgressReporter
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let url = get_download_url(version).await?;
+    let save_path = paths::get_cold_clear_download_path(version);
 
-        let version = version.as_str();
-        let url = paths::get_cold_clear_download_url(version);
-        let save_path = paths::get_cold_clear_download_path(version);
+    let begin_time = Instant::now();
 
-        rt.block_on(async move {
-            let begin_time = Instant::now();
+    let client = reqwest::Client::new();
+    let mut response = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?;
 
-            let client = reqwest::Client::new();
-            let response = client
-                .get(url)
-                .send()
-                .await;
+    let total_size = response
+        .content_length()
+        .unwrap_or(0);
 
-            if let Err(e) = response {
-                tx.send(LoadingIPCMessage::Error(e))
-                    .expect("Failed to send IPC message");
-                return;
-            }
+    let total_size = TryInto::<i32>::try_into(total_size)
+        .expect("Failed to convert u64 to i32");
 
-            let mut response = response.unwrap();
+    progress.set_total(
+        if total_size != 0 { Some(total_size) } else { None }
+    );
 
-            let total_size = response
-                .content_length()
-                .unwrap_or(0);
+    let mut data = Vec::new();
+    let mut downloaded_size = 0_i32;
+    let mut last_report: Option<Instant> = None;
 
-            let total_size = TryInto::<i32>::try_into(total_size)
-                .expect("Failed to convert u64 to i32");
+    while let Some(chunk) = response.chunk().await? {
+        if progress.is_interrupted() {
+            info!("ColdClear download interrupted!");
+            return Err("The Cold Clear download was cancelled.".into());
+        }
 
-            tx.send(LoadingIPCMessage::SetTotal(total_size))
-                .expect("Failed to send IPC message");
+        downloaded_size += chunk.len() as i32;
+        data.extend_from_slice(&chunk);
 
-            tx.send(LoadingIPCMessage::SetDeterminacy(total_size != 0))
-                .expect("Failed to send IPC message");
+        let reported_recently = last_report
+            .is_some_and(|time| time.elapsed() < PROGRESS_REPORT_INTERVAL);
 
-            let mut data = Vec::new();
-            let mut downloaded_size = 0 as i32;
+        if reported_recently {
+            continue;
+        }
 
-            let interrupted = Arc::new(Mutex::new(false));
+        last_report = Some(Instant::now());
 
-            loop {
-                let interrupted_clone = interrupted.clone();
-                window_weak.upgrade_in_event_loop(move |window| {
-                    let mut interrupted = interrupted_clone.lock().unwrap();
+        let elapsed = begin_time.elapsed();
 
-                    *interrupted = window.get_interrupted();
-                }).expect("Failed to upgrade weak ref on interrupt check");
+        let dl_rate = (
+            downloaded_size as f64 /
+            elapsed.as_secs_f64()
+        ) as i32;
 
-                if interrupted.lock().unwrap().clone() {
-                    tx.send(LoadingIPCMessage::Finish)
-                        .expect("Failed to send IPC message");
-                    println!("ColdClear download interrupted!");
-                    return;
-                }
-                
-                // poll the `response` and get its progress
-                let chunk = response.chunk().await;
-
-                if let Err(e) = chunk {
-                    tx.send(LoadingIPCMessage::Error(e))
-                        .expect("Failed to send IPC message");
-                    return;
-                }
+        let eta_secs = if total_size != 0 && dl_rate != 0 {
+            Some((total_size - downloaded_size) / dl_rate)
+        } else {
+            None
+        };
 
-                let chunk = chunk.unwrap();
+        progress.advance_to(downloaded_size, dl_rate, eta_secs);
+    }
 
-                if chunk.is_none() {
-                    break;
-                }
+    fs::create_dir_all(save_path.parent().unwrap())?;
 
-                let chunk = chunk.unwrap();
+    let mut file = std::fs::File::create(save_path)?;
 
-                downloaded_size += chunk.len() as i32;
-                data.extend_from_slice(&chunk);
+    info!("Writing {} bytes to ColdClear path", data.len());
 
-                let elapsed = begin_time.elapsed();
+    file.write_all(data.as_ref())?;
 
-                let dl_rate = (
-                    downloaded_size as f64 /
-                    elapsed.as_secs_f64()
-                ) as i32;
-                
-                let remaining_size = total_size - downloaded_size;
-                let eta_secs = remaining_size / dl_rate;
+    progress.finish();
 
-                let advance_message = LoadingIPCMessage::AdvanceTo(
-                    downloaded_size,
-                    dl_rate,
-                    format_time(eta_secs).into()
-                );
+    return Ok(());
+}
 
-                tx.send(advance_message)
-                    .expect("Failed to send IPC message");
+/// How often download progress is reported.
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_millis(200);
 
-                thread::sleep(Duration::from_millis(200));
-            }
+/// Shows download progress in a `ColdClearWaitWindow`.
+struct WindowProgress {
+    window: Weak<ColdClearWaitWindow>,
+    interrupted: Arc<AtomicBool>,
+}
 
-            tx.send(LoadingIPCMessage::SetDeterminacy(false))
-                .expect("Failed to send IPC message");
+impl ProgressReporter for WindowProgress {
+    fn set_total(&mut self, total: Option<i32>) {
+        self.window.upgrade_in_event_loop(move |window| {
+            window.set_bytes_total(total.unwrap_or(0));
+            window.set_indeterminate(total.is_none());
+        }).expect("Error upgrading weak ref on event loop while setting total");
+    }
 
-            fs::create_dir_all(save_path.parent().unwrap())
-                .expect("Failed to create ColdClear download directory");
+    fn advance_to(&mut self, done: i32, rate: i32, eta: Option<i32>) {
+        let eta: SharedString = match eta {
+            Some(secs) => format_time(secs).into(),
+            None => "Unknown".into()
+        };
 
-            let mut file = std::fs::File::create(save_path)
-                .expect("Failed to create ColdClear download file");
+        self.window.upgrade_in_event_loop(move |window| {
+            window.set_bytes_done(done);
+            window.set_dl_rate(rate);
+            window.set_dl_eta(eta);
+        }).expect("Error upgrading weak ref on event loop while setting progress");
+    }
 
-            println!("Writing {} bytes to ColdClear path", data.len());
+    fn finish(&mut self) {
+        self.window.upgrade_in_event_loop(|window| {
+            window.set_indeterminate(true);
+            window.set_finished(true);
+        }).expect("Error upgrading weak ref on event loop while finishing");
+    }
 
-            file.write_all(data.as_ref())
-                .expect("Failed to write ColdClear download file");
+    fn is_interrupted(&self) -> bool {
+        return self.interrupted.load(Ordering::Relaxed);
+    }
+}
 
-            tx.send(LoadingIPCMessage::Finish)
-                .expect("Failed to send IPC message");
-        });
-    });
+fn download_with_window(version: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let window = ColdClearWaitWindow::new()
+        .expect("Failed to open ColdClear loading window");
 
-    let window_weak = window.as_weak();
-    let window_thread = thread::spawn(move || {
-        loop {
-            let val = rx.recv();
-
-            if let Err(_) = val {
-                // Stream ended because transmitter no longer exists
-                window_weak.upgrade_in_event_loop(|window| {
-                    window.set_finished(true);
-                    window.hide().expect("Failed to hide ColdClear loading window");
-                }).expect("Error upgrading weak ref on event loop while breaking out");
-                break;
-            }
-            
-            let val = val.unwrap();
-
-            match val {
-                LoadingIPCMessage::AdvanceTo(bytes, rate, eta) => {
-                    window_weak.upgrade_in_event_loop(move |window| {
-                        window.set_bytes_done(bytes);
-                        window.set_dl_rate(rate);
-                        window.set_dl_eta(eta);
-                    }).expect("Error upgrading weak ref on event loop while setting progress");
-                }
-                LoadingIPCMessage::SetTotal(bytes) => {
-                    window_weak.upgrade_in_event_loop(move |window| {
-                        window.set_bytes_total(bytes);
-                    }).expect("Error upgrading weak ref on event loop while setting total");
-                }
-                LoadingIPCMessage::SetDeterminacy(determinate) => {
-                    window_weak.upgrade_in_event_loop(move |window| {
-                        window.set_indeterminate(!determinate)
-                    }).expect("Error upgrading weak ref on event loop while setting determinacy");
-                }
-                LoadingIPCMessage::Finish => {
-                    window_weak.upgrade_in_event_loop(|window| {
-                        window.set_finished(true);
-                        window.hide().expect("Failed to hide ColdClear loading window");
-                    }).expect("Error upgrading weak ref on event loop while finishing");
-                    break;
-                }
-                LoadingIPCMessage::Error(e) => {
-                    return Err(e);
-                }
-            }
-        }
+    window.on_format_bytes(format_bytes);
 
-        return Ok(());
-    });
+    let interrupted = Arc::new(AtomicBool::new(false));
 
+    let interrupted_clone = interrupted.clone();
     let window_weak = window.as_weak();
     window.on_interrupt(move || {
+        interrupted_clone.store(true, Ordering::Relaxed);
         window_weak
             .unwrap().window().hide()
             .expect("Failed to hide ColdClear loading window");
     });
 
-    window.run().expect("Failed to show ColdClear loading window");
-
-    window.set_interrupted(true);
-
-    return window_thread.join().expect("Failed to join window thread");
-}
-
-fn get_path_score(path: &str) -> i8 {
-    #[cfg(target_arch = "x86_64")]
-    {
-        let pos_keywords = [
-            "x64",
-            "amd64",
-            "x86_64"
-        ];
-
-        for keyword in pos_keywords.iter() {
-            if path.contains(keyword) {
-                return 1;
-            }
-        }
-
-        let neg_keywords = [
-            "x86",
-            "i386",
-            "i686"
-        ];
-
-        for keyword in neg_keywords.iter() {
-            if path.contains(keyword) {
-                return -1;
-            }
-        }
-
-        return 0;
-    }
-
-    #[cfg(target_arch = "x86")]
-    {
-        let neg_keywords = [
-            "x64",
-            "amd64",
-            "x86_64"
-        ];
-
-        for keyword in neg_keywords.iter() {
-            if path.contains(keyword) {
-                return -1;
-            }
-        }
+    let mut progress = WindowProgress {
+        window: window.as_weak(),
+        interrupted: interrupted.clone(),
+    };
 
-        let pos_keywords = [
-            "x86",
-            "i386",
-            "i686"
-        ];
+    let window_weak = window.as_weak();
+    let version = version.to_owned();
+    let download_thread = thread::spawn(move || {
+        let rt = Runtime::new()
+            .expect("Failed to create Tokio runtime");
 
-        for keyword in pos_keywords.iter() {
-            if path.contains(keyword) {
-                return 1;
-            }
-        }
+        let result = rt.block_on(download(&version, &mut progress));
 
-        return 0;
-    }
+        window_weak.upgrade_in_event_loop(|window| {
+            window.set_finished(true);
+            window.hide().expect("Failed to hide ColdClear loading window");
+        }).expect("Error upgrading weak ref on event loop while finishing");
 
-    #[cfg(target_arch = "aarch64")]
-    {
-        let pos_keywords = [
-            "arm64",
-            "aarch64"
-        ];
+        return result;
+    });
 
-        for keyword in pos_keywords.iter() {
-            if path.contains(keyword) {
-                return 1;
-            }
-        }
+    window.run().expect("Failed to show ColdClear loading window");
 
-        let neg_keywords = [
-            "arm",
-            "armhf",
-            "armv7"
-        ];
+    // The window may also have been closed by the user
+    interrupted.store(true, Ordering::Relaxed);
 
-        for keyword in neg_keywords.iter() {
-            if path.contains(keyword) {
-                return -1;
-            }
-        }
+    return download_thread.join().expect("Failed to join download thread");
+}
 
-        return 0;
+/// Downloads the given Cold Clear version to its cache path,
+/// showing progress in the given style.
+pub fn download_cold_clear(
+    version: &str,
+    style: ProgressStyle
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if style == ProgressStyle::Window {
+        return download_with_window(version);
     }
 
-    #[cfg(target_arch = "arm")]
-    {
-        let pos_keywords = [
-            "armeabi-v7a",
-            "armv7",
-            "arm32"
-        ];
-
-        for keyword in pos_keywords.iter() {
-            if path.contains(keyword) {
-                return 1;
-            }
-        }
+    let mut progress: Box<dyn ProgressReporter> = match style {
+        ProgressStyle::Quiet => Box::new(QuietProgress),
+        _ => Box::new(TerminalProgress::new(
+            &format!("Downloading Cold Clear {version}")
+        ))
+    };
 
-        let neg_keywords = [
-            "arm64",
-            "aarch64"
-        ];
+    let rt = Runtime::new()
+        .expect("Failed to create Tokio runtime");
 
-        for keyword in neg_keywords.iter() {
-            if path.contains(keyword) {
-                return -1;
-            }
-        }
-
-        return 0;
-    }
+    return rt.block_on(download(version, progress.as_mut()));
+}
 
-    #[allow(unreachable_code)]{
-        return 0;
-    }
+/// Scores a file path in the Cold Clear archive by the architecture it names.
+fn get_path_score(path: &str) -> i8 {
+    return match assets::Arch::parse(path) {
+        Some(arch) if Some(arch) == assets::Arch::current() => 1,
+        Some(_) => -1,
+        None => 0
+    };
 }
 
-fn pick_files_to_move(path: &PathBuf) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+fn pick_files_to_move(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
     // Traverse through the directories and pick files for flattenning
     // If identical filename, choose one with higher path score
+    let root = path;
     let path = path.to_path_buf();
 
     fn traverse(path: PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
@@ -449,8 +283,9 @@ fn pick_files_to_move(path: &PathBuf) -> Result<Vec<PathBuf>, Box<dyn std::error
         if file_map.contains_key(name) {
             let other_path = file_map.get(name).unwrap();
 
-            let path_str = path.to_str();
-            let other_path_str = other_path.to_str();
+            // Only score the part of the path inside the archive
+            let path_str = path.strip_prefix(root).unwrap_or(&path).to_str();
+            let other_path_str = other_path.strip_prefix(root).unwrap_or(other_path).to_str();
 
             if path_str.is_none() {
                 return Err(
@@ -483,50 +318,176 @@ fn pick_files_to_move(path: &PathBuf) -> Result<Vec<PathBuf>, Box<dyn std::error
     );
 }
 
-pub fn unpack_cold_clear(version: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Opens the cached zip archive of an official Cold Clear release,
+/// downloading it first if it is missing or invalid.
+fn open_official_archive(
+    version: &str,
+    style: ProgressStyle
+) -> Result<ZipArchive<fs::File>, Box<dyn std::error::Error + Send + Sync>> {
     let zip_path = paths::get_cold_clear_download_path(version);
     let zip_path = zip_path.as_path();
 
     if !zip_path.exists() {
-        download_cold_clear(version)?;
+        download_cold_clear(version, style)?;
     }
 
-    let zip_file = std::fs::File::open(zip_path)?;
-
-    let mut zip_archive = ZipArchive::new(&zip_file);
+    let zip_archive = ZipArchive::new(fs::File::open(zip_path)?);
 
-    if let Err(_) = zip_archive {
+    if zip_archive.is_err() {
         eprintln!("ColdClear zip archive at '{zip_path:#?}' seems to be invalid. Redownloading.");
 
         fs::remove_file(zip_path)?;
 
-        download_cold_clear(version)?;
+        download_cold_clear(version, style)?;
 
-        zip_archive = ZipArchive::new(&zip_file);
+        return Ok(ZipArchive::new(fs::File::open(zip_path)?)?);
     }
 
-    let mut zip_archive = zip_archive?;
+    return Ok(zip_archive?);
+}
 
-    let lib_path = paths::get_sandboxed_save_path().join("lib");
-    let temp_lib_path = paths::get_sandboxed_save_path().join("~lib");
+/// Flattens the library files in `source_path` into `lib_path`
+/// and records them in an install marker under `version`.
+///
+/// Files are moved if `copy` is false, e.g. when `source_path` is a
+/// temporary extraction directory, and copied otherwise.
+fn install_files(
+    version: &str,
+    source_path: &Path,
+    lib_path: &Path,
+    copy: bool
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(lib_path)?;
+
+    let files_to_move = pick_files_to_move(source_path)?;
+    let mut installed_files: Vec<String> = Vec::new();
 
-    fs::create_dir_all(&lib_path)?;
-    fs::create_dir_all(&temp_lib_path)?;
+    for path in files_to_move {
+        let file_name = path.file_name().unwrap();
+        let dest = lib_path.join(file_name);
 
-    zip_archive.extract(&temp_lib_path)?;
+        if copy {
+            fs::copy(&path, dest)?;
+        } else {
+            fs::rename(&path, dest)?;
+        }
 
-    let files_to_move = pick_files_to_move(&temp_lib_path)?;
+        installed_files.push(file_name.to_string_lossy().to_string());
+    }
 
-    for path in files_to_move {
-        let dest = lib_path.join(path.file_name().unwrap());
-        fs::rename(path, dest)?;
+    InstallMarker::create(version, lib_path, &installed_files)?
+        .save(lib_path)?;
+
+    return Ok(());
+}
+
+/// Installs the given Cold Clear version into the sandbox `lib` directory.
+/// `version` may be an official release tag or the ID of a custom library.
+///
+/// Does nothing if that version is already installed and intact,
+/// except for custom libraries in a directory, which may have been rebuilt.
+/// Otherwise, the files of the previously installed version are removed
+/// before the new version is installed.
+pub fn unpack_cold_clear(
+    version: &str,
+    style: ProgressStyle
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    return unpack_cold_clear_to(version, &paths::get_sandboxed_lib_path(), style);
+}
+
+/// Installs the given Cold Clear version into `lib_path`,
+/// as [`unpack_cold_clear`] does for the sandbox.
+pub fn unpack_cold_clear_to(
+    version: &str,
+    lib_path: &Path,
+    style: ProgressStyle
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let custom_library = custom::find(version);
+
+    if custom::is_custom_version(version) && custom_library.is_none() {
+        return Err(format!(
+            "The AI library '{version}' is not registered. Register it again or pick another version."
+        ).into());
     }
 
+    if let Some(library) = &custom_library {
+        library.check_platform()?;
+    }
+
+    let from_directory = custom_library
+        .as_ref()
+        .is_some_and(|library| library.is_directory());
+
+    let marker = InstallMarker::load(lib_path);
+
+    if let Some(marker) = &marker {
+        if marker.version == version && !from_directory {
+            match marker.verify(lib_path) {
+                Ok(()) => {
+                    info!("ColdClear version '{version}' is already installed");
+                    return Ok(());
+                }
+                Err(problem) => {
+                    info!("Reinstalling ColdClear version '{version}': {problem}");
+                }
+            }
+        } else {
+            info!("Replacing ColdClear version '{}' with '{version}'", marker.version);
+        }
+
+        marker.uninstall(lib_path)?;
+    }
+
+    if from_directory {
+        let library = custom_library.unwrap();
+        return install_files(version, Path::new(&library.path), lib_path, true);
+    }
+
+    let mut zip_archive = match &custom_library {
+        Some(library) => ZipArchive::new(fs::File::open(&library.path)?)?,
+        None => open_official_archive(version, style)?
+    };
+
+    let temp_lib_path = lib_path.with_file_name("~lib");
+
+    fs::create_dir_all(&temp_lib_path)?;
+
+    zip_archive.extract(&temp_lib_path)?;
+
+    install_files(version, &temp_lib_path, lib_path, false)?;
+
     fs::remove_dir_all(temp_lib_path)?;
-    
+
     return Ok(());
 }
 
+#[test]
+fn test_unpack_after_clear() {
+    let sandbox_path = std::env::temp_dir()
+        .join(format!("boxedmino_test_unpack_after_clear_{}", std::process::id()));
+    let lib_path = sandbox_path.join("lib");
+
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("CCloader.so"), "library").unwrap();
+    fs::write(sandbox_path.join("conf.dat"), "settings").unwrap();
+    InstallMarker::create("11.4.2", &lib_path, &["CCloader.so".to_string()])
+        .unwrap()
+        .save(&lib_path)
+        .unwrap();
+
+    crate::dirs::clear_sandbox_dir(&sandbox_path);
+
+    assert!(!sandbox_path.join("conf.dat").exists());
+
+    // The installed version is still intact, so nothing is downloaded
+    unpack_cold_clear_to("11.4.2", &lib_path, ProgressStyle::Quiet).unwrap();
+
+    assert_eq!(fs::read_to_string(lib_path.join("CCloader.so")).unwrap(), "library");
+    assert!(InstallMarker::load(&lib_path).is_some());
+
+    fs::remove_dir_all(&sandbox_path).unwrap();
+}
+
 pub fn get_available_offline_versions() -> Vec<String> {
     let path = paths::get_cold_clear_download_path("");
     let path = path
@@ -567,60 +528,24 @@ pub fn get_available_offline_versions() -> Vec<String> {
     return versions;
 }
 
-pub async fn get_available_online_versions() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let api_url = paths::COLD_CLEAR_RELEASES_API_URL;
-
-    // let response = reqwest::blocking::get(api_url)?;
-    let client = reqwest::Client::new();
-    let request = client
-        .get(api_url)
-        .header("Accept", "application/vnd.github+json")
-        .header("X-GitHub-Api-Version", "2022-11-28")
-        .header("User-Agent", "boxedmino")
-        .build()
-        .expect("Failed to build request");
-
-    let response = client.execute(request).await?;
-
-    let json = response.text().await?;
-
-    let json: serde_json::Value = serde_json::from_str(&json)?;
-
-    let releases = match json {
-        serde_json::Value::Array(arr) => arr,
-        _ => return Err("Expected JSON array from GitHub API".into())
-    };
-
-    let mut versions: Vec<String> = Vec::new();
-
-    for release in releases {
-        let version = match release["tag_name"].as_str() {
-            Some(version) => version,
-            None => continue
-        };
-
-        versions.push(version.to_owned());
-    }
-
-    return Ok(versions);
-}
-
-pub async fn get_available_versions() -> Vec<String> {
-    let mut versions = get_available_offline_versions();
-    let online_versions = get_available_online_versions().await;
+/// Lists registered custom libraries, then downloaded and online Cold Clear versions.
+pub fn get_available_versions(cache: &releases::ReleaseCache) -> Vec<String> {
+    let mut versions: Vec<String> = custom::load_libraries()
+        .iter()
+        .map(|library| library.id())
+        .collect();
 
-    if let Err(e) = online_versions {
-        eprintln!("Failed to get ColdClear online version list: {:#?}", e);
-        return versions;
+    for offline_version in get_available_offline_versions() {
+        if !versions.contains(&offline_version) {
+            versions.push(offline_version);
+        }
     }
 
-    let online_versions = online_versions.unwrap();
-
-    for online_version in online_versions {
+    for online_version in cache.tag_names() {
         if !versions.contains(&online_version) {
             versions.push(online_version);
         }
     }
 
     return versions;
-}
\ No newline at end of file
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use crate::cold_clear::releases::{Release, ReleaseAsset};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Os {
+    Windows,
+    MacOS,
+    Linux,
+    Android,
+    Ios,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    X86,
+    Aarch64,
+    Arm,
+}
+
+/// Keywords identifying an operating system in an asset name.
+/// Checked in order, so that longer keywords win over their substrings.
+const OS_KEYWORDS: [(&str, Os); 10] = [
+    ("windows", Os::Windows),
+    ("win64", Os::Windows),
+    ("win32", Os::Windows),
+    ("macos", Os::MacOS),
+    ("darwin", Os::MacOS),
+    ("osx", Os::MacOS),
+    ("android", Os::Android),
+    ("linux", Os::Linux),
+    ("iphone", Os::Ios),
+    ("ios", Os::Ios),
+];
+
+/// Keywords identifying a CPU architecture in an asset name or path.
+/// Checked in order, so that e.g. `x86_64` is not mistaken for `x86`.
+const ARCH_KEYWORDS: [(&str, Arch); 15] = [
+    ("x86_64", Arch::X86_64),
+    ("amd64", Arch::X86_64),
+    ("x64", Arch::X86_64),
+    ("win64", Arch::X86_64),
+    ("aarch64", Arch::Aarch64),
+    ("arm64", Arch::Aarch64),
+    ("armeabi-v7a", Arch::Arm),
+    ("armv7", Arch::Arm),
+    ("armhf", Arch::Arm),
+    ("arm32", Arch::Arm),
+    ("i386", Arch::X86),
+    ("i686", Arch::X86),
+    ("x86", Arch::X86),
+    ("win32", Arch::X86),
+    ("arm", Arch::Arm),
+];
+
+impl Os {
+    /// The running OS, if Cold Clear is built for it.
+    pub fn current() -> Option<Self> {
+        return match std::env::consts::OS {
+            "windows" => Some(Os::Windows),
+            "macos" => Some(Os::MacOS),
+            "linux" => Some(Os::Linux),
+            "android" => Some(Os::Android),
+            "ios" => Some(Os::Ios),
+            _ => None
+        };
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+
+        return OS_KEYWORDS
+            .iter()
+            .find(|(keyword, _)| name.contains(keyword))
+            .map(|(_, os)| *os);
+    }
+}
+
+impl Arch {
+    pub fn current() -> Option<Self> {
+        return match std::env::consts::ARCH {
+            "x86_64" => Some(Arch::X86_64),
+            "x86" => Some(Arch::X86),
+            "aarch64" => Some(Arch::Aarch64),
+            "arm" => Some(Arch::Arm),
+            _ => None
+        };
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+
+        return ARCH_KEYWORDS
+            .iter()
+            .find(|(keyword, _)| name.contains(keyword))
+            .map(|(_, arch)| *arch);
+    }
+}
+
+impl fmt::Display for Os {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Os::Windows => "Windows",
+            Os::MacOS => "macOS",
+            Os::Linux => "Linux",
+            Os::Android => "Android",
+            Os::Ios => "iOS",
+        };
+        return write!(f, "{name}");
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Arch::X86_64 => "x86_64",
+            Arch::X86 => "x86",
+            Arch::Aarch64 => "aarch64",
+            Arch::Arm => "arm",
+        };
+        return write!(f, "{name}");
+    }
+}
+
+/// The platform an asset was built for, as inferred from its name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AssetTarget {
+    pub os: Option<Os>,
+    pub arch: Option<Arch>,
+}
+
+impl AssetTarget {
+    pub fn parse(name: &str) -> Self {
+        return Self {
+            os: Os::parse(name),
+            arch: Arch::parse(name),
+        };
+    }
+
+    /// Scores how well this target fits the given platform.
+    ///
+    /// - `None` if the asset is for another OS or architecture,
+    ///   or does not name an OS at all
+    /// - `Some(2)` if both the OS and the architecture match
+    /// - `Some(1)` if the OS matches and no architecture is named
+    pub fn score(&self, os: Os, arch: Option<Arch>) -> Option<u8> {
+        if self.os != Some(os) {
+            return None;
+        }
+
+        return match (self.arch, arch) {
+            (None, _) => Some(1),
+            (Some(a), Some(b)) if a == b => Some(2),
+            _ => None
+        };
+    }
+}
+
+impl fmt::Display for AssetTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let os = self.os
+            .map(|os| os.to_string())
+            .unwrap_or("unknown OS".to_string());
+
+        return match self.arch {
+            Some(arch) => write!(f, "{os}, {arch}"),
+            None => write!(f, "{os}, any architecture")
+        };
+    }
+}
+
+/// Picks the zip asset of a release that best fits the given platform.
+pub fn select_asset_for(
+    release: &Release,
+    os: Os,
+    arch: Option<Arch>
+) -> Result<&ReleaseAsset, String> {
+    let best = release.assets
+        .iter()
+        .filter(|asset| asset.name.to_lowercase().ends_with(".zip"))
+        .filter_map(|asset| {
+            let score = AssetTarget::parse(&asset.name).score(os, arch)?;
+            Some((score, asset))
+        })
+        // `max_by_key` returns the last maximum; prefer the first listed asset
+        .rev()
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, asset)| asset);
+
+    if let Some(asset) = best {
+        return Ok(asset);
+    }
+
+    let platform = match arch {
+        Some(arch) => format!("{os} ({arch})"),
+        None => os.to_string()
+    };
+
+    if release.assets.is_empty() {
+        return Err(format!(
+            "Cold Clear release '{}' has no downloadable assets.",
+            release.tag_name
+        ));
+    }
+
+    let mut message = format!(
+        "Cold Clear release '{}' has no asset for {platform}. Available assets:",
+        release.tag_name
+    );
+
+    for asset in &release.assets {
+        message.push_str(&format!(
+            "\n- {} ({})",
+            asset.name,
+            AssetTarget::parse(&asset.name)
+        ));
+    }
+
+    return Err(message);
+}
+
+/// Picks the zip asset of a release that fits the running platform.
+pub fn select_asset(release: &Release) -> Result<&ReleaseAsset, String> {
+    let os = Os::current()
+        .ok_or(format!("Cold Clear is not available for {}.", std::env::consts::OS))?;
+
+    return select_asset_for(release, os, Arch::current());
+}
+
+#[test]
+fn test_asset_target_parse() {
+    let cases = [
+        ("Windows.zip", Some(Os::Windows), None),
+        ("macOS.zip", Some(Os::MacOS), None),
+        ("Linux.zip", Some(Os::Linux), None),
+        ("Android.zip", Some(Os::Android), None),
+        ("iOS.zip", Some(Os::Ios), None),
+        ("Windows-x64.zip", Some(Os::Windows), Some(Arch::X86_64)),
+        ("windows_x86.zip", Some(Os::Windows), Some(Arch::X86)),
+        ("linux-x86_64.zip", Some(Os::Linux), Some(Arch::X86_64)),
+        ("Linux-aarch64.zip", Some(Os::Linux), Some(Arch::Aarch64)),
+        ("android-armeabi-v7a.zip", Some(Os::Android), Some(Arch::Arm)),
+        ("linux-arm.zip", Some(Os::Linux), Some(Arch::Arm)),
+        ("cold_clear-win32.zip", Some(Os::Windows), Some(Arch::X86)),
+        ("source.tar.gz", None, None),
+    ];
+
+    for (name, os, arch) in cases {
+        assert_eq!(AssetTarget::parse(name), AssetTarget { os, arch }, "{name}");
+    }
+}
+
+#[test]
+fn test_select_asset_for() {
+    fn asset(name: &str) -> ReleaseAsset {
+        return ReleaseAsset {
+            name: name.to_string(),
+            size: 0,
+            browser_download_url: format!("https://example.com/{name}"),
+        };
+    }
+
+    let release = Release {
+        tag_name: "11.4.2".to_string(),
+        published_at: None,
+        assets: vec![
+            asset("Windows.zip"),
+            asset("Windows-x86.zip"),
+            asset("Linux-aarch64.zip"),
+            asset("Linux-arm.zip"),
+            asset("Linux.zip"),
+            asset("macOS.zip"),
+        ],
+    };
+
+    let cases = [
+        (Os::Windows, Some(Arch::X86_64), Some("Windows.zip")),
+        (Os::Windows, Some(Arch::X86), Some("Windows-x86.zip")),
+        (Os::Linux, Some(Arch::Aarch64), Some("Linux-aarch64.zip")),
+        (Os::Linux, Some(Arch::X86_64), Some("Linux.zip")),
+        (Os::Linux, Some(Arch::Arm), Some("Linux-arm.zip")),
+        (Os::MacOS, None, Some("macOS.zip")),
+        (Os::Android, Some(Arch::Aarch64), None),
+    ];
+
+    for (os, arch, expected) in cases {
+        let selected = select_asset_for(&release, os, arch)
+            .ok()
+            .map(|asset| asset.name.as_str());
+        assert_eq!(selected, expected, "{os} {arch:?}");
+    }
+
+    let error = select_asset_for(&release, Os::Android, None).unwrap_err();
+    assert!(error.contains("- Linux-aarch64.zip (Linux, aarch64)"), "{error}");
+}
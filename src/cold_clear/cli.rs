@@ -0,0 +1,117 @@
+use serde::Serialize;
+use crate::cold_clear::install::InstallMarker;
+use crate::cold_clear::releases::{self, ReleaseCache};
+use crate::cold_clear::{custom, get_available_offline_versions};
+use crate::dirs::paths;
+use crate::info;
+use crate::output;
+
+/// Where a Cold Clear version can be taken from.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum VersionSource {
+    /// A library registered with `boxedmino ai-library add`
+    Custom,
+    /// A release archive that was already downloaded
+    Downloaded,
+    /// A release that would be downloaded when selected
+    Online,
+}
+
+#[derive(Serialize, Debug)]
+struct VersionEntry {
+    version: String,
+    source: VersionSource,
+    /// Whether this version is unpacked in the sandbox
+    installed: bool,
+    published_at: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct VersionList {
+    /// When the online release list was last fetched, in RFC 3339 format
+    refreshed_at: Option<String>,
+    versions: Vec<VersionEntry>,
+}
+
+fn list(cache: &ReleaseCache) -> VersionList {
+    let installed = InstallMarker::load(&paths::get_sandboxed_lib_path())
+        .map(|marker| marker.version);
+
+    let mut versions: Vec<VersionEntry> = Vec::new();
+
+    let mut push = |version: String, source: VersionSource| {
+        if versions.iter().any(|entry| entry.version == version) {
+            return;
+        }
+
+        versions.push(VersionEntry {
+            installed: installed.as_ref() == Some(&version),
+            published_at: cache.find(&version)
+                .and_then(|release| release.published_at.clone()),
+            version,
+            source,
+        });
+    };
+
+    for library in custom::load_libraries() {
+        push(library.id(), VersionSource::Custom);
+    }
+
+    for version in get_available_offline_versions() {
+        push(version, VersionSource::Downloaded);
+    }
+
+    for version in cache.tag_names() {
+        push(version, VersionSource::Online);
+    }
+
+    return VersionList {
+        refreshed_at: cache.refreshed_at
+            .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+            .map(|time| time.to_rfc3339()),
+        versions,
+    };
+}
+
+/// Runs `boxedmino list-cc-versions`, returning the exit code.
+///
+/// Unless `refresh` is set, only the cached release list is used.
+pub fn list_versions(refresh: bool) -> i32 {
+    let cache = if refresh {
+        let rt = tokio::runtime::Runtime::new()
+            .expect("Failed to create Tokio runtime");
+
+        match rt.block_on(releases::refresh()) {
+            Ok(cache) => cache,
+            Err(e) => {
+                eprintln!("Failed to refresh ColdClear release list: {e}");
+                return 1;
+            }
+        }
+    } else {
+        ReleaseCache::load()
+    };
+
+    let list = list(&cache);
+
+    if output::is_json() {
+        output::print_json(&list);
+        return 0;
+    }
+
+    info!("Release list last refreshed: {}", cache.format_refreshed_at());
+
+    for entry in &list.versions {
+        let source = match entry.source {
+            VersionSource::Custom => "custom",
+            VersionSource::Downloaded => "downloaded",
+            VersionSource::Online => "online",
+        };
+        let installed = if entry.installed { "\tinstalled" } else { "" };
+
+        println!("{}\t{source}{installed}", entry.version);
+    }
+
+    return 0;
+}
@@ -0,0 +1,176 @@
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::cold_clear::assets::{Arch, Os};
+use crate::dirs::paths;
+
+/// Prefix of the version strings identifying custom libraries,
+/// distinguishing them from official Cold Clear release tags.
+pub const CUSTOM_VERSION_PREFIX: &str = "custom:";
+
+/// A user-registered AI library, used in place of an official Cold Clear release.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CustomLibrary {
+    pub name: String,
+    pub version: String,
+    /// A directory containing the library files, or a zip archive of them
+    pub path: String,
+    /// The OS the library was built for, or `None` if unspecified
+    #[serde(default)]
+    pub os: Option<String>,
+    /// The CPU architecture the library was built for, or `None` if unspecified
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+impl CustomLibrary {
+    /// The identifier used in place of a Cold Clear version,
+    /// e.g. in the config file and the version dropdown.
+    pub fn id(&self) -> String {
+        return format!("{CUSTOM_VERSION_PREFIX}{}@{}", self.name, self.version);
+    }
+
+    pub fn is_directory(&self) -> bool {
+        return Path::new(&self.path).is_dir();
+    }
+
+    /// Checks that the library was built for the running platform.
+    pub fn check_platform(&self) -> Result<(), String> {
+        let os = self.os.as_deref().and_then(Os::parse);
+
+        if let Some(os) = os.filter(|os| Some(*os) != Os::current()) {
+            return Err(format!(
+                "AI library '{}' was built for {os}, not {}.",
+                self.id(),
+                std::env::consts::OS
+            ));
+        }
+
+        let arch = self.arch.as_deref().and_then(Arch::parse);
+
+        if let Some(arch) = arch.filter(|arch| Some(*arch) != Arch::current()) {
+            return Err(format!(
+                "AI library '{}' was built for {arch}, not {}.",
+                self.id(),
+                std::env::consts::ARCH
+            ));
+        }
+
+        return Ok(());
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("The library name cannot be empty.".to_string());
+        }
+
+        if self.version.trim().is_empty() {
+            return Err("The library version cannot be empty.".to_string());
+        }
+
+        if self.name.contains('@') {
+            return Err("The library name cannot contain '@'.".to_string());
+        }
+
+        let path = Path::new(&self.path);
+        let is_zip = path.is_file() && self.path.to_lowercase().ends_with(".zip");
+
+        if !path.is_dir() && !is_zip {
+            return Err(format!(
+                "'{}' is neither a directory nor a zip archive.",
+                self.path
+            ));
+        }
+
+        if let Some(os) = &self.os {
+            if Os::parse(os).is_none() {
+                return Err(format!("Unknown operating system: '{os}'"));
+            }
+        }
+
+        if let Some(arch) = &self.arch {
+            if Arch::parse(arch).is_none() {
+                return Err(format!("Unknown architecture: '{arch}'"));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+pub fn is_custom_version(version: &str) -> bool {
+    return version.starts_with(CUSTOM_VERSION_PREFIX);
+}
+
+/// Loads the registered libraries.
+/// A missing or unreadable registry is treated as empty.
+pub fn load_libraries() -> Vec<CustomLibrary> {
+    let path = paths::get_custom_ai_libraries_path();
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![]
+    };
+
+    return serde_json::from_str(&contents)
+        .unwrap_or_else(|e| {
+            eprintln!("Ignoring invalid AI library registry at {path:?}: {e}");
+            vec![]
+        });
+}
+
+fn save_libraries(libraries: &[CustomLibrary]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = paths::get_custom_ai_libraries_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, serde_json::to_string_pretty(libraries)?)?;
+
+    return Ok(());
+}
+
+pub fn find(id: &str) -> Option<CustomLibrary> {
+    return load_libraries()
+        .into_iter()
+        .find(|library| library.id() == id);
+}
+
+/// Registers a library, replacing any library with the same name and version.
+pub fn register(mut library: CustomLibrary) -> Result<CustomLibrary, Box<dyn std::error::Error + Send + Sync>> {
+    library.name = library.name.trim().to_string();
+    library.version = library.version.trim().to_string();
+
+    library.validate()?;
+
+    // Store an absolute path, so the registry does not depend on the working directory
+    library.path = fs::canonicalize(&library.path)?
+        .to_string_lossy()
+        .to_string();
+
+    let mut libraries = load_libraries();
+    libraries.retain(|other| other.id() != library.id());
+    libraries.push(library.clone());
+
+    save_libraries(&libraries)?;
+
+    return Ok(library);
+}
+
+/// Removes a library from the registry.
+/// Returns whether a library with that ID was registered.
+pub fn unregister(id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut libraries = load_libraries();
+    let count = libraries.len();
+
+    libraries.retain(|library| library.id() != id);
+
+    if libraries.len() == count {
+        return Ok(false);
+    }
+
+    save_libraries(&libraries)?;
+
+    return Ok(true);
+}
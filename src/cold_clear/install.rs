@@ -0,0 +1,129 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use sha1::{Digest, Sha1};
+
+/// Name of the marker file kept in the sandbox `lib` directory.
+pub const MARKER_FILE_NAME: &str = ".boxedmino_cold_clear.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InstalledFile {
+    pub name: String,
+    pub sha1: String,
+}
+
+/// Records which Cold Clear version is installed in a `lib` directory,
+/// and which files belong to it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InstallMarker {
+    pub version: String,
+    pub files: Vec<InstalledFile>,
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let contents = fs::read(path)?;
+    return Ok(format!("{:x}", Sha1::digest(contents)));
+}
+
+impl InstallMarker {
+    /// Hashes the given files in `lib_path` and records them under `version`.
+    pub fn create(version: &str, lib_path: &Path, file_names: &[String]) -> io::Result<Self> {
+        let mut files: Vec<InstalledFile> = Vec::new();
+
+        for name in file_names {
+            files.push(InstalledFile {
+                name: name.clone(),
+                sha1: hash_file(&lib_path.join(name))?,
+            });
+        }
+
+        return Ok(Self {
+            version: version.to_string(),
+            files,
+        });
+    }
+
+    /// Loads the marker from `lib_path`, if there is a readable one.
+    pub fn load(lib_path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(lib_path.join(MARKER_FILE_NAME)).ok()?;
+        return serde_json::from_str(&contents).ok();
+    }
+
+    pub fn save(&self, lib_path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .expect("Failed to serialize Cold Clear install marker");
+        return fs::write(lib_path.join(MARKER_FILE_NAME), contents);
+    }
+
+    /// Checks that every recorded file is still present and unchanged.
+    /// Returns a description of the first problem found.
+    pub fn verify(&self, lib_path: &Path) -> Result<(), String> {
+        for file in &self.files {
+            let path = lib_path.join(&file.name);
+
+            let hash = match hash_file(&path) {
+                Ok(hash) => hash,
+                Err(_) => return Err(format!("'{}' is missing or unreadable", file.name))
+            };
+
+            if hash != file.sha1 {
+                return Err(format!("'{}' has been modified or damaged", file.name));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Removes the recorded files and the marker itself,
+    /// leaving any other files in `lib_path` alone.
+    pub fn uninstall(&self, lib_path: &Path) -> io::Result<()> {
+        for file in &self.files {
+            let path = lib_path.join(&file.name);
+
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e);
+                }
+            }
+        }
+
+        let marker_path = lib_path.join(MARKER_FILE_NAME);
+
+        if marker_path.exists() {
+            fs::remove_file(marker_path)?;
+        }
+
+        return Ok(());
+    }
+}
+
+#[test]
+fn test_install_marker_verify() {
+    let lib_path = std::env::temp_dir()
+        .join(format!("boxedmino_test_install_marker_{}", std::process::id()));
+
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("CCloader.so"), "library").unwrap();
+    fs::write(lib_path.join("unrelated.txt"), "keep me").unwrap();
+
+    let marker = InstallMarker::create(
+        "11.4.2",
+        &lib_path,
+        &["CCloader.so".to_string()]
+    ).unwrap();
+    marker.save(&lib_path).unwrap();
+
+    assert_eq!(InstallMarker::load(&lib_path), Some(marker.clone()));
+    assert_eq!(marker.verify(&lib_path), Ok(()));
+
+    fs::write(lib_path.join("CCloader.so"), "damaged").unwrap();
+    assert!(marker.verify(&lib_path).is_err());
+
+    marker.uninstall(&lib_path).unwrap();
+    assert!(marker.verify(&lib_path).is_err());
+    assert!(InstallMarker::load(&lib_path).is_none());
+    assert!(lib_path.join("unrelated.txt").exists());
+
+    fs::remove_dir_all(&lib_path).unwrap();
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use serde::{Serialize, Deserialize};
+use crate::dirs::paths;
+use crate::info;
+
+/// The amount of releases requested per page of the GitHub API.
+/// 100 is the maximum GitHub allows.
+const RELEASES_PER_PAGE: u32 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub size: u64,
+    pub browser_download_url: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// The Cold Clear release list, as last fetched from the GitHub API.
+///
+/// The cache is used as-is when offline, and revalidated
+/// with a conditional request otherwise.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReleaseCache {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix timestamp of the last successful fetch or revalidation
+    pub refreshed_at: Option<i64>,
+    pub releases: Vec<Release>,
+}
+
+impl ReleaseCache {
+    /// Loads the cache from disk.
+    /// A missing or unreadable cache is treated as empty.
+    pub fn load() -> Self {
+        let path = paths::get_cold_clear_release_cache_path();
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default()
+        };
+
+        return serde_json::from_str(&contents)
+            .unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid ColdClear release cache at {path:?}: {e}");
+                Self::default()
+            });
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let path = paths::get_cold_clear_release_cache_path();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        return Ok(());
+    }
+
+    pub fn find(&self, tag_name: &str) -> Option<&Release> {
+        return self.releases
+            .iter()
+            .find(|release| release.tag_name == tag_name);
+    }
+
+    pub fn tag_names(&self) -> Vec<String> {
+        return self.releases
+            .iter()
+            .map(|release| release.tag_name.clone())
+            .collect();
+    }
+
+    /// Formats the time of the last refresh in the local timezone,
+    /// or "Never" if the list was never fetched.
+    pub fn format_refreshed_at(&self) -> String {
+        let time = self.refreshed_at
+            .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0));
+
+        return match time {
+            Some(time) => time
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "Never".to_string()
+        };
+    }
+}
+
+fn get_unix_time() -> i64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+}
+
+fn get_header(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    return headers
+        .get(name)?
+        .to_str()
+        .ok()
+        .map(|s| s.to_string());
+}
+
+/// Gets the URL marked with `rel="next"` in a GitHub `Link` header.
+fn parse_next_link(link_header: &str) -> Option<String> {
+    for link in link_header.split(',') {
+        let mut parts = link.split(';');
+
+        let url = parts.next()?
+            .trim()
+            .strip_prefix('<')?
+            .strip_suffix('>')?;
+
+        let is_next = parts.any(|param| {
+            param.trim().replace(' ', "") == "rel=\"next\""
+        });
+
+        if is_next {
+            return Some(url.to_string());
+        }
+    }
+
+    return None;
+}
+
+#[test]
+fn test_parse_next_link() {
+    let cases = [
+        (
+            r#"<https://api.github.com/x?page=2>; rel="next", <https://api.github.com/x?page=5>; rel="last""#,
+            Some("https://api.github.com/x?page=2")
+        ),
+        (
+            r#"<https://api.github.com/x?page=1>; rel="prev", <https://api.github.com/x?page=3>; rel="next""#,
+            Some("https://api.github.com/x?page=3")
+        ),
+        (
+            r#"<https://api.github.com/x?page=1>; rel="first", <https://api.github.com/x?page=4>; rel="prev""#,
+            None
+        ),
+        ("", None),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(parse_next_link(input).as_deref(), expected);
+    }
+}
+
+fn build_request(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    return client
+        .get(url)
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header("User-Agent", "boxedmino");
+}
+
+/// Revalidates the cached release list against the GitHub API,
+/// following pagination, and writes the result back to disk.
+///
+/// If the server reports that the list has not changed,
+/// only the refresh timestamp is updated.
+pub async fn refresh() -> Result<ReleaseCache, Box<dyn std::error::Error + Send + Sync>> {
+    let mut cache = ReleaseCache::load();
+
+    let client = reqwest::Client::new();
+    let first_url = format!(
+        "{}?per_page={RELEASES_PER_PAGE}",
+        paths::COLD_CLEAR_RELEASES_API_URL
+    );
+
+    let mut request = build_request(&client, &first_url);
+
+    // Only revalidate if there is something to fall back on
+    if !cache.releases.is_empty() {
+        if let Some(etag) = &cache.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cache.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        info!("ColdClear release list not modified since last refresh");
+        cache.refreshed_at = Some(get_unix_time());
+        cache.save()?;
+        return Ok(cache);
+    }
+
+    let response = response.error_for_status()?;
+
+    let etag = get_header(response.headers(), header::ETAG);
+    let last_modified = get_header(response.headers(), header::LAST_MODIFIED);
+
+    let mut releases: Vec<Release> = Vec::new();
+    let mut response = response;
+
+    loop {
+        let next_url = get_header(response.headers(), header::LINK)
+            .and_then(|link| parse_next_link(&link));
+
+        let json = response.text().await?;
+        let mut page: Vec<Release> = serde_json::from_str(&json)?;
+        releases.append(&mut page);
+
+        let next_url = match next_url {
+            Some(url) => url,
+            None => break
+        };
+
+        response = build_request(&client, &next_url)
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
+    cache = ReleaseCache {
+        etag,
+        last_modified,
+        refreshed_at: Some(get_unix_time()),
+        releases,
+    };
+
+    cache.save()?;
+
+    return Ok(cache);
+}
+
+/// Looks up a release by its tag name in the cache,
+/// refreshing the cache first if the tag is not in it.
+pub async fn find_release(tag_name: &str) -> Result<Release, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(release) = ReleaseCache::load().find(tag_name) {
+        return Ok(release.clone());
+    }
+
+    let cache = refresh().await?;
+
+    return match cache.find(tag_name) {
+        Some(release) => Ok(release.clone()),
+        None => Err(format!(
+            "Cold Clear version '{tag_name}' was not found in the release list."
+        ).into())
+    };
+}
@@ -1,57 +1,52 @@
-use std::fs;
-use crate::{dirs::paths, CliInstruction};
-use crate::{git, INSTRUCTION};
 use crate::slint_types::Settings;
 use serde::{Serialize, Deserialize};
 
+pub mod cli;
+pub mod keys;
+pub mod layers;
+pub mod schema;
+
+use schema::CONFIG_VERSION;
+
+// Missing keys fall back to their value in `Config::new()`
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct Config {
     // Editing this struct?
     // Don't forget to also update the Slint model!
     // See the `Settings` struct in /ui/main.slint
+    // If older config files would be misread, also add a migration.
+    // See `MIGRATIONS` in /src/conf/schema.rs
 
+    pub config_version: u32,
     pub sandboxed: bool,
     pub clear_temp_dir: bool,
     pub import_save_on_play: bool,
+    /// The save directory to import from, or empty to detect it
+    pub source_save_path: String,
+    /// Comma-separated save categories to import, see [`crate::save::import::parse_categories`]
+    pub import_categories: String,
+    /// Comma-separated names of patches applied on top of the version, see [`crate::patches`]
+    pub patches: String,
     pub repo_initialized: bool,
     pub game_repo_path: String,
+    /// Decided on every launch, so it is never saved
+    #[serde(skip)]
     pub use_gui: bool,
     pub use_cold_clear: bool,
     pub cold_clear_version: String,
 }
 
-/// [See definition for flags](crate::CliInstruction::Run::flags)
-fn get_cli_config_flags() -> Option<&'static str> {
-    let instruction = INSTRUCTION.get()?;
-
-    let instruction = instruction.as_ref()?;
-
-    return match instruction {
-        CliInstruction::Run { flags, .. } =>
-            Some(flags.as_ref()?.as_str()),
-        _ => None
-    };
-}
-
-fn get_cli_repo_path() -> Option<&'static str> {
-    let instruction = INSTRUCTION.get()?;
-
-    let instruction = instruction.as_ref()?;
-
-    return match instruction {
-        CliInstruction::Run { repo_path, .. } =>
-            Some(repo_path.as_ref()?.as_str()),
-        CliInstruction::ListVersions { repo_path } =>
-            Some(repo_path.as_ref()?.as_str()),
-    };
-}
-
 impl Config {
     pub fn new() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             sandboxed: true,
             clear_temp_dir: true,
             import_save_on_play: false,
+            source_save_path: "".to_string(),
+            import_categories: "all".to_string(),
+            patches: "".to_string(),
             repo_initialized: false,
             game_repo_path: "".to_string(),
             use_gui: true,
@@ -59,84 +54,37 @@ impl Config {
             cold_clear_version: "11.4.1".to_string(),
         }
     }
-    pub fn load_from_file() -> Self {
-        let config_path = paths::get_config_path();
-        let config = fs::read_to_string(config_path);
-        match config {
-            Ok(config) => {
-                let mut config: Config = serde_json::from_str(&config)
-                    .unwrap_or_default();
-                config.use_gui = true;
-                return config;
-            }
-            Err(_) => {
-                let config = Config::new();
-                config.save();
-                return config;
-            }
-        }
-    }
+    /// Loads the effective config from every layer.
+    /// See [`layers::load`].
     pub fn load() -> Self {
-        let mut cfg = Self::load_from_file();
-
-        cfg.use_gui = INSTRUCTION.get()
-            .unwrap_or(&None)
-            .is_none();
-
-        if let Some(flags) = get_cli_config_flags() {
-            for char in flags.trim().chars() {
-                match char {
-                    's' => cfg.sandboxed = false,
-                    'S' => cfg.sandboxed = true,
-                    'c' => cfg.clear_temp_dir = false,
-                    'C' => cfg.clear_temp_dir = true,
-                    'i' => cfg.import_save_on_play = false,
-                    'I' => cfg.import_save_on_play = true,
-                    'a' => cfg.use_cold_clear = false,
-                    'A' => cfg.use_cold_clear = true,
-                    _ => {
-                        eprintln!("Invalid config flag: {:?}", char);
-                        std::process::exit(1);
-                    }
-                }
-            }
-        }
-
-        if let Some(path) = get_cli_repo_path() {
-            if git::is_repo_valid(path) {
-                cfg.repo_initialized = true;
-            } else {
-                eprintln!("Invalid repository path: {path:?}\n{}",
-                    "Make sure the directory exists and contains a main.lua file and a .git folder."
-                );
-                std::process::exit(1);
-            }
-
-            cfg.game_repo_path = path.to_string();
-        }
-
-        return cfg;
+        return layers::load(true).config;
     }
-    pub fn save(&self) {
-        let mut config: Self = self.clone();
-        config.use_gui = true;
-        let config_path = paths::get_config_path();
-        let config = serde_json::to_string(&config)
-            .expect("Failed to serialize config");
-
-        if let Some(parent) = config_path.parent() {
-            fs::create_dir_all(parent)
-                .expect(format!(
-                    "Failed to create directory {}",
-                    parent.to_str().unwrap_or("(invalid path)")
-                ).as_str());
+    /// Saves the values changed from `original`, usually the config
+    /// the changes were made on, into the user config file.
+    /// Values set by higher layers that weren't changed are left out.
+    pub fn save_changes(&self, original: &Config) {
+        let below_user = layers::load_below_user(false).config;
+        let values = layers::merge_into_user_layer(
+            self,
+            original,
+            &below_user,
+            layers::read_user_layer(false)
+        );
+
+        layers::write_user_layer(&values);
+    }
+    /// Saves only the given keys into the user config file,
+    /// for changes made on top of the effective config.
+    pub fn save_keys(&self, keys: &[&str]) {
+        let mut values = layers::read_user_layer(false);
+
+        for key in keys {
+            let value = self.get_key(key)
+                .expect("Unknown config key");
+            values.insert(key.to_string(), value);
         }
 
-        fs::write(&config_path, config)
-            .expect(format!(
-                "Failed to write config to {}",
-                config_path.to_str().unwrap_or("(invalid path)")
-            ).as_str());
+        layers::write_user_layer(&values);
     }
 }
 
@@ -149,9 +97,13 @@ impl Default for Config {
 impl From<Settings> for Config {
     fn from(settings: Settings) -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             sandboxed: settings.sandboxed,
             clear_temp_dir: settings.clear_temp_dir,
             import_save_on_play: settings.import_save_on_play,
+            source_save_path: settings.source_save_path.as_str().to_string(),
+            import_categories: settings.import_categories.as_str().to_string(),
+            patches: settings.patches.as_str().to_string(),
             repo_initialized: settings.repo_initialized,
             game_repo_path: settings.game_repo_path.as_str().to_string(),
             use_gui: true,
@@ -167,6 +119,9 @@ impl From<Config> for Settings {
             sandboxed: cfg.sandboxed,
             clear_temp_dir: cfg.clear_temp_dir,
             import_save_on_play: cfg.import_save_on_play,
+            source_save_path: cfg.source_save_path.clone().into(),
+            import_categories: cfg.import_categories.clone().into(),
+            patches: cfg.patches.clone().into(),
             game_repo_path: cfg.game_repo_path.clone().into(),
             repo_initialized: cfg.repo_initialized,
             use_cold_clear: cfg.use_cold_clear,
@@ -0,0 +1,222 @@
+use std::fs;
+use std::process::Command;
+use serde_json::{Map, Value};
+use crate::conf::layers::{self, Layer};
+use crate::conf::{schema, Config};
+use crate::dirs::paths;
+use crate::info;
+use crate::output;
+use crate::ConfigAction;
+
+/// Formats a value the way it is accepted by `boxedmino config set`.
+fn format_value(value: &Value) -> String {
+    return match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string()
+    };
+}
+
+fn get_editor() -> String {
+    for var in ["VISUAL", "EDITOR"] {
+        if let Ok(editor) = std::env::var(var) {
+            if !editor.trim().is_empty() {
+                return editor;
+            }
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    return "notepad".to_string();
+
+    #[cfg(not(target_os = "windows"))]
+    return "vi".to_string();
+}
+
+/// Opens the config file in the user's editor, then reports any problems in it.
+fn edit() -> i32 {
+    let config_path = paths::get_config_path();
+
+    if !config_path.exists() {
+        layers::write_user_layer(&Map::new());
+    }
+
+    let editor = get_editor();
+
+    // The editor may come with arguments, e.g. `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&config_path)
+        .status();
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("Editor `{editor}` exited with {status}");
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Failed to run editor `{editor}`: {e}\nSet $EDITOR to choose another editor.");
+            return 1;
+        }
+    }
+
+    let contents = match fs::read_to_string(&config_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read config file after editing: {e}");
+            return 1;
+        }
+    };
+
+    // Validate the file on its own, as other layers may hide its mistakes
+    let parsed = match schema::parse(&contents) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    if let Some(version) = parsed.migrated_from {
+        info!("The config file has the outdated version {version}; it will be migrated the next time it is loaded");
+    }
+
+    let issues: Vec<String> = parsed.issues
+        .iter()
+        .chain(schema::validate(&parsed.config).iter())
+        .map(|issue| issue.to_string())
+        .collect();
+
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+
+    return if issues.is_empty() { 0 } else { 1 };
+}
+
+/// Runs a `boxedmino config` subcommand, returning the exit code.
+pub fn run(action: &ConfigAction) -> i32 {
+    if let ConfigAction::Path = action {
+        let path = paths::get_config_path().to_string_lossy().to_string();
+
+        if output::is_json() {
+            output::print_json(&serde_json::json!({ "path": path }));
+        } else {
+            println!("{path}");
+        }
+        return 0;
+    }
+
+    if let ConfigAction::Edit = action {
+        return edit();
+    }
+
+    let layered = layers::load(true);
+
+    match action {
+        ConfigAction::Get { key } => {
+            match layered.config.get_key(key) {
+                Ok(value) if output::is_json() => {
+                    output::print_json(&value);
+                    return 0;
+                }
+                Ok(value) => {
+                    println!("{}", format_value(&value));
+                    return 0;
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    return 1;
+                }
+            }
+        }
+        ConfigAction::List { origin } if output::is_json() => {
+            let mut object = Map::new();
+
+            for key in Config::keys() {
+                let value = layered.config.get_key(&key)
+                    .expect("Listed key not found in config");
+
+                let entry = if *origin {
+                    serde_json::json!({
+                        "value": value,
+                        "origin": layered.origin(&key).to_string(),
+                    })
+                } else {
+                    value
+                };
+
+                object.insert(key, entry);
+            }
+
+            output::print_json(&object);
+            return 0;
+        }
+        ConfigAction::List { origin } => {
+            for key in Config::keys() {
+                let value = layered.config.get_key(&key)
+                    .expect("Listed key not found in config");
+
+                if *origin {
+                    println!("{key} = {} ({})", format_value(&value), layered.origin(&key));
+                } else {
+                    println!("{key} = {}", format_value(&value));
+                }
+            }
+            return 0;
+        }
+        _ => {}
+    }
+
+    // Changes only ever go to the user config file
+    let mut values = layers::read_user_layer(false);
+
+    match action {
+        ConfigAction::Set { key, value } => {
+            let mut config = layered.config.clone();
+
+            if let Err(e) = config.set_key(key, value) {
+                eprintln!("{e}");
+                return 1;
+            }
+
+            let value = config.get_key(key)
+                .expect("Key not found after setting it");
+            values.insert(key.clone(), value);
+        }
+        ConfigAction::Reset { key: Some(key) } => {
+            if let Err(e) = Config::default().get_key(key) {
+                eprintln!("{e}");
+                return 1;
+            }
+
+            values.remove(key);
+        }
+        ConfigAction::Reset { key: None } => {
+            values.clear();
+        }
+        _ => unreachable!()
+    }
+
+    layers::write_user_layer(&values);
+
+    // Tell the user if their change is hidden by a higher layer
+    let updated = layers::load(false);
+    let changed_keys: Vec<String> = match action {
+        ConfigAction::Set { key, .. } | ConfigAction::Reset { key: Some(key) } => vec![key.clone()],
+        _ => Config::keys()
+    };
+
+    for key in changed_keys {
+        let origin = updated.origin(&key);
+
+        if origin > Layer::User {
+            eprintln!("Note: `{key}` is overridden by the {origin} layer");
+        }
+    }
+
+    return 0;
+}
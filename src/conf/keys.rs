@@ -0,0 +1,164 @@
+use serde_json::{Map, Value};
+use crate::conf::Config;
+use crate::conf::schema;
+
+/// Keys that are managed by Boxedmino itself and cannot be set by hand.
+const READ_ONLY_KEYS: [&str; 1] = ["config_version"];
+
+/// Parses a command-line value into the JSON type of `default`.
+pub fn parse_value(default: &Value, input: &str) -> Result<Value, String> {
+    return match default {
+        Value::Bool(_) => match input.trim().to_lowercase().as_str() {
+            "true" | "on" | "yes" | "1" => Ok(Value::Bool(true)),
+            "false" | "off" | "no" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("expected true or false, found {input:?}"))
+        },
+        Value::Number(_) => input.trim()
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| format!("expected a non-negative integer, found {input:?}")),
+        Value::String(_) => Ok(Value::String(input.to_string())),
+        _ => Err("this key cannot be set from the command line".to_string())
+    };
+}
+
+#[test]
+fn test_parse_value() {
+    let cases = [
+        (Value::Bool(false), "on", Ok(Value::Bool(true))),
+        (Value::Bool(true), "No", Ok(Value::Bool(false))),
+        (Value::Bool(true), "maybe", Err(())),
+        (Value::from(1), "2", Ok(Value::from(2))),
+        (Value::from(1), "-2", Err(())),
+        (Value::from("a"), "11.4.1", Ok(Value::from("11.4.1"))),
+    ];
+
+    for (default, input, expected) in cases {
+        assert_eq!(parse_value(&default, input).map_err(|_| ()), expected, "{input}");
+    }
+}
+
+impl Config {
+    pub fn to_object(&self) -> Map<String, Value> {
+        return match serde_json::to_value(self) {
+            Ok(Value::Object(object)) => object,
+            _ => panic!("Failed to serialize config")
+        };
+    }
+
+    /// Returns a copy of this config with the given keys overwritten.
+    /// The values must have been checked beforehand.
+    pub fn with_values(&self, values: &Map<String, Value>) -> Config {
+        let mut object = self.to_object();
+
+        for (key, value) in values {
+            object.insert(key.clone(), value.clone());
+        }
+
+        let config: Config = serde_json::from_value(Value::Object(object))
+            .expect("Failed to apply config values");
+
+        return Config {
+            use_gui: self.use_gui,
+            ..config
+        };
+    }
+
+    /// Names of all keys in the config file.
+    pub fn keys() -> Vec<String> {
+        return Config::default()
+            .to_object()
+            .keys()
+            .cloned()
+            .collect();
+    }
+
+    pub fn get_key(&self, key: &str) -> Result<Value, String> {
+        return self.to_object()
+            .remove(key)
+            .ok_or_else(|| unknown_key_message(key));
+    }
+
+    /// Sets a key from its command-line representation.
+    ///
+    /// The value is parsed according to the key's type, and rejected
+    /// if it would make the config invalid.
+    pub fn set_key(&mut self, key: &str, input: &str) -> Result<(), String> {
+        if READ_ONLY_KEYS.contains(&key) {
+            return Err(format!("`{key}` is managed by Boxedmino and cannot be set"));
+        }
+
+        let mut object = self.to_object();
+
+        let default = object
+            .get(key)
+            .ok_or_else(|| unknown_key_message(key))?;
+
+        let value = parse_value(default, input)
+            .map_err(|e| format!("`{key}`: {e}"))?;
+
+        object.insert(key.to_string(), value);
+
+        let updated: Config = serde_json::from_value(Value::Object(object))
+            .map_err(|e| format!("`{key}`: {e}"))?;
+
+        let existing_issues = schema::validate(self);
+        let new_issue = schema::validate(&updated)
+            .into_iter()
+            .find(|issue| !existing_issues.contains(issue));
+
+        if let Some(issue) = new_issue {
+            return Err(issue.to_string());
+        }
+
+        *self = Config {
+            use_gui: self.use_gui,
+            ..updated
+        };
+
+        return Ok(());
+    }
+
+    /// Resets a key to its default value.
+    pub fn reset_key(&mut self, key: &str) -> Result<(), String> {
+        let default = Config::default().get_key(key)?;
+
+        let mut object = self.to_object();
+        object.insert(key.to_string(), default);
+
+        *self = Config {
+            use_gui: self.use_gui,
+            ..serde_json::from_value(Value::Object(object))
+                .expect("Failed to restore default config value")
+        };
+
+        return Ok(());
+    }
+}
+
+fn unknown_key_message(key: &str) -> String {
+    return format!(
+        "Unknown key `{key}`. Valid keys are: {}",
+        Config::keys().join(", ")
+    );
+}
+
+#[test]
+fn test_set_key() {
+    let mut config = Config::default();
+
+    config.set_key("sandboxed", "off").unwrap();
+    assert!(!config.sandboxed);
+
+    config.set_key("cold_clear_version", "11.4.2").unwrap();
+    assert_eq!(config.cold_clear_version, "11.4.2");
+
+    assert!(config.set_key("cold_clear_version", "latest").is_err());
+    assert!(config.set_key("sandboxed", "maybe").is_err());
+    assert!(config.set_key("config_version", "0").is_err());
+    assert!(config.set_key("sandbox", "true").is_err());
+    assert_eq!(config.cold_clear_version, "11.4.2");
+
+    config.reset_key("sandboxed").unwrap();
+    assert_eq!(config.sandboxed, Config::default().sandboxed);
+}
@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde_json::{Map, Value};
+use crate::conf::schema::{self, ConfigIssue, ParsedLayer, CONFIG_VERSION};
+use crate::conf::{keys, Config};
+use crate::dirs::paths;
+use crate::{git, info, patches, recipe, save, CliInstruction, INSTRUCTION};
+
+/// Prefix of the environment variables overriding config keys,
+/// e.g. `BOXEDMINO_SANDBOXED=false`.
+pub const ENV_PREFIX: &str = "BOXEDMINO_";
+
+/// Name of the project-local config file, kept in the game repository.
+pub const PROJECT_CONFIG_FILE_NAME: &str = "boxedmino.toml";
+
+/// Keys that describe where the game repository is,
+/// which a file inside that repository cannot sensibly change.
+const PROJECT_IGNORED_KEYS: [&str; 2] = ["game_repo_path", "repo_initialized"];
+
+/// A source of config values, from lowest to highest precedence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Default,
+    System,
+    User,
+    Project,
+    Environment,
+    CommandLine,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Layer::Default => "default",
+            Layer::System => "system",
+            Layer::User => "user",
+            Layer::Project => "project",
+            Layer::Environment => "environment",
+            Layer::CommandLine => "command line",
+        };
+
+        return write!(f, "{name}");
+    }
+}
+
+/// The effective config, and the layer each of its values came from.
+#[derive(Clone, Debug)]
+pub struct LayeredConfig {
+    pub config: Config,
+    origins: BTreeMap<String, Layer>,
+}
+
+impl LayeredConfig {
+    fn new() -> Self {
+        return Self {
+            config: Config::new(),
+            origins: Config::keys()
+                .into_iter()
+                .map(|key| (key, Layer::Default))
+                .collect(),
+        };
+    }
+
+    /// Overrides the values present in `values` with those of a higher layer.
+    fn apply(&mut self, layer: Layer, values: &Map<String, Value>) {
+        self.config = self.config.with_values(values);
+        self.config.game_repo_path = paths::resolve_portable_path(&self.config.game_repo_path);
+
+        for key in values.keys() {
+            self.origins.insert(key.clone(), layer);
+        }
+    }
+
+    pub fn origin(&self, key: &str) -> Layer {
+        return self.origins
+            .get(key)
+            .copied()
+            .unwrap_or(Layer::Default);
+    }
+}
+
+fn report_issues(path: &Path, issues: &[ConfigIssue]) {
+    for issue in issues {
+        eprintln!("Config file {}: {issue}", path.to_string_lossy());
+    }
+}
+
+/// Reads an optional config file of a layer other than the user's.
+/// A missing file is the same as an empty one.
+fn read_file_layer(
+    path: &Path,
+    parse: fn(&str) -> Result<ParsedLayer, schema::ConfigError>,
+    report: bool
+) -> Map<String, Value> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return Map::new()
+    };
+
+    return match parse(&contents) {
+        Ok(parsed) => {
+            if report {
+                report_issues(path, &parsed.issues);
+            }
+            parsed.values
+        }
+        Err(e) => {
+            if report {
+                eprintln!("Ignoring config file {}: {e}", path.to_string_lossy());
+            }
+            Map::new()
+        }
+    };
+}
+
+/// Reads the user config file, migrating it to the current layout if needed.
+pub fn read_user_layer(report: bool) -> Map<String, Value> {
+    let config_path = paths::get_config_path();
+
+    let contents = match fs::read_to_string(&config_path) {
+        Ok(contents) => contents,
+        Err(_) => return Map::new()
+    };
+
+    let parsed = match schema::parse_layer(&contents) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            // Keep the unreadable file around, as it will be
+            // overwritten the next time the settings are saved
+            let backup_path = paths::get_config_backup_path("invalid");
+            eprintln!(
+                "Failed to read config file at {}: {e}\nUsing default settings. The file was backed up to {}",
+                config_path.to_string_lossy(),
+                backup_path.to_string_lossy()
+            );
+            if let Err(e) = fs::copy(&config_path, &backup_path) {
+                eprintln!("Failed to back up config file: {e}");
+            }
+            return Map::new();
+        }
+    };
+
+    if report {
+        report_issues(&config_path, &parsed.issues);
+    }
+
+    if let Some(version) = parsed.migrated_from {
+        let backup_path = paths::get_config_backup_path(&format!("v{version}"));
+
+        info!(
+            "Migrating config file from version {version} to {CONFIG_VERSION}; the old file is backed up at {}",
+            backup_path.to_string_lossy()
+        );
+
+        match fs::copy(&config_path, &backup_path) {
+            Ok(_) => write_user_layer(&parsed.values),
+            Err(e) => eprintln!("Failed to back up config file, not saving the migrated config: {e}")
+        }
+    }
+
+    return parsed.values;
+}
+
+/// Replaces the contents of the user config file.
+pub fn write_user_layer(values: &Map<String, Value>) {
+    let config_path = paths::get_config_path();
+
+    let mut object = values.clone();
+    object.insert("config_version".to_string(), Value::from(CONFIG_VERSION));
+
+    if let Some(Value::String(path)) = object.get_mut("game_repo_path") {
+        *path = paths::to_portable_path(path);
+    }
+
+    let config = serde_json::to_string(&object)
+        .expect("Failed to serialize config");
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .unwrap_or_else(|_| panic!(
+                "Failed to create directory {}",
+                parent.to_str().unwrap_or("(invalid path)")
+            ));
+    }
+
+    fs::write(&config_path, config)
+        .unwrap_or_else(|_| panic!(
+            "Failed to write config to {}",
+            config_path.to_str().unwrap_or("(invalid path)")
+        ));
+}
+
+/// Reads `BOXEDMINO_<KEY>` variables, e.g. `BOXEDMINO_COLD_CLEAR_VERSION`.
+fn read_env_layer(
+    get_var: impl Fn(&str) -> Option<String>,
+    report: bool
+) -> Map<String, Value> {
+    let defaults = Config::default().to_object();
+    let mut values = Map::new();
+
+    for (key, default) in defaults {
+        if key == "config_version" {
+            continue;
+        }
+
+        let name = format!("{ENV_PREFIX}{}", key.to_uppercase());
+
+        let input = match get_var(&name) {
+            Some(input) => input,
+            None => continue
+        };
+
+        match keys::parse_value(&default, &input) {
+            Ok(value) => {
+                values.insert(key, value);
+            }
+            Err(e) => if report {
+                eprintln!("Ignoring environment variable {name}: {e}");
+            }
+        }
+    }
+
+    return values;
+}
+
+/// [See definition for flags](crate::CliInstruction::Run::flags)
+fn get_cli_config_flags() -> Option<&'static str> {
+    let instruction = INSTRUCTION.get()?;
+
+    let instruction = instruction.as_ref()?;
+
+    return match instruction {
+        CliInstruction::Run { flags, .. } =>
+            Some(flags.as_ref()?.as_str()),
+        _ => None
+    };
+}
+
+fn get_cli_import_categories() -> Option<&'static str> {
+    let instruction = INSTRUCTION.get()?;
+
+    let instruction = instruction.as_ref()?;
+
+    return match instruction {
+        CliInstruction::Run { import, .. } =>
+            Some(import.as_ref()?.as_str()),
+        _ => None
+    };
+}
+
+fn get_cli_patches() -> Option<&'static str> {
+    let instruction = INSTRUCTION.get()?;
+
+    let instruction = instruction.as_ref()?;
+
+    return match instruction {
+        CliInstruction::Run { patches, .. } =>
+            Some(patches.as_ref()?.as_str()),
+        _ => None
+    };
+}
+
+fn get_cli_repo_path() -> Option<&'static str> {
+    let instruction = INSTRUCTION.get()?;
+
+    let instruction = instruction.as_ref()?;
+
+    return match instruction {
+        CliInstruction::Run { repo_path, .. } =>
+            Some(repo_path.as_ref()?.as_str()),
+        CliInstruction::ListVersions { repo_path } =>
+            Some(repo_path.as_ref()?.as_str()),
+        _ => None
+    };
+}
+
+/// Reads the recipe, config flags, save categories, patches and repository path given on the command line.
+/// Exits if either of them is invalid.
+fn read_cli_layer() -> Map<String, Value> {
+    let mut values = Map::new();
+
+    // Explicit options take precedence over the recipe
+    if let Some(recipe) = recipe::get_cli_recipe() {
+        values.extend(recipe.settings.clone());
+    }
+
+    if let Some(flags) = get_cli_config_flags() {
+        for char in flags.trim().chars() {
+            let (key, value) = match char {
+                's' => ("sandboxed", false),
+                'S' => ("sandboxed", true),
+                'c' => ("clear_temp_dir", false),
+                'C' => ("clear_temp_dir", true),
+                'i' => ("import_save_on_play", false),
+                'I' => ("import_save_on_play", true),
+                'a' => ("use_cold_clear", false),
+                'A' => ("use_cold_clear", true),
+                _ => {
+                    eprintln!("Invalid config flag: {:?}", char);
+                    std::process::exit(1);
+                }
+            };
+
+            values.insert(key.to_string(), Value::Bool(value));
+        }
+    }
+
+    if let Some(categories) = get_cli_import_categories() {
+        if let Err(e) = save::import::parse_categories(categories) {
+            eprintln!("Invalid --import value: {e}");
+            std::process::exit(1);
+        }
+
+        values.insert("import_save_on_play".to_string(), Value::Bool(true));
+        values.insert("import_categories".to_string(), Value::String(categories.to_string()));
+    }
+
+    if let Some(names) = get_cli_patches() {
+        for name in patches::parse_list(names) {
+            if !patches::get_path(&name).is_file() {
+                eprintln!("Invalid --patches value: there is no patch named {name:?}");
+                std::process::exit(1);
+            }
+        }
+
+        values.insert("patches".to_string(), Value::String(names.to_string()));
+    }
+
+    if let Some(path) = get_cli_repo_path() {
+        if !git::is_repo_valid(path) {
+            eprintln!("Invalid repository path: {path:?}\n\
+                Make sure the directory exists and contains a main.lua file and a .git folder."
+            );
+            std::process::exit(1);
+        }
+
+        values.insert("repo_initialized".to_string(), Value::Bool(true));
+        values.insert("game_repo_path".to_string(), Value::String(path.to_string()));
+    }
+
+    return values;
+}
+
+/// Reads `boxedmino.toml` from the game repository.
+fn read_project_layer(repo_path: &str, report: bool) -> Map<String, Value> {
+    if repo_path.is_empty() {
+        return Map::new();
+    }
+
+    let path = Path::new(repo_path).join(PROJECT_CONFIG_FILE_NAME);
+    let mut values = read_file_layer(&path, schema::parse_toml_layer, report);
+
+    for key in PROJECT_IGNORED_KEYS {
+        if values.remove(key).is_some() && report {
+            eprintln!(
+                "Config file {}: `{key}`: cannot be set in a project config file; ignoring this key",
+                path.to_string_lossy()
+            );
+        }
+    }
+
+    return values;
+}
+
+/// Merges the layers the user config file overrides,
+/// i.e. the built-in defaults and the system config file.
+pub fn load_below_user(report: bool) -> LayeredConfig {
+    let mut layered = LayeredConfig::new();
+
+    if let Some(path) = paths::get_system_config_path() {
+        layered.apply(Layer::System, &read_file_layer(&path, schema::parse_layer, report));
+    }
+
+    return layered;
+}
+
+/// Updates the contents of the user config file with the values
+/// that were changed from `original` to `config`.
+/// Changed values matching the layers below the user config are left out,
+/// so they keep following them.
+pub fn merge_into_user_layer(
+    config: &Config,
+    original: &Config,
+    below_user: &Config,
+    mut values: Map<String, Value>
+) -> Map<String, Value> {
+    let original = original.to_object();
+    let below_user = below_user.to_object();
+
+    for (key, value) in config.to_object() {
+        if key == "config_version" || original.get(&key) == Some(&value) {
+            continue;
+        }
+
+        if below_user.get(&key) == Some(&value) {
+            values.remove(&key);
+        } else {
+            values.insert(key, value);
+        }
+    }
+
+    return values;
+}
+
+/// Merges every config layer, from the built-in defaults
+/// up to the command-line arguments.
+///
+/// Problems in the config files are printed if `report` is set.
+pub fn load(report: bool) -> LayeredConfig {
+    let mut layered = load_below_user(report);
+
+    layered.apply(Layer::User, &read_user_layer(report));
+
+    let env = read_env_layer(|name| std::env::var(name).ok(), report);
+    let cli = read_cli_layer();
+
+    // The project file is found through the repository path,
+    // which the environment and command line may override
+    let repo_path = cli.get("game_repo_path")
+        .or(env.get("game_repo_path"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .unwrap_or(layered.config.game_repo_path.clone());
+
+    layered.apply(Layer::Project, &read_project_layer(&repo_path, report));
+    layered.apply(Layer::Environment, &env);
+    layered.apply(Layer::CommandLine, &cli);
+
+    layered.config.use_gui = INSTRUCTION.get()
+        .unwrap_or(&None)
+        .is_none();
+
+    if report {
+        for issue in schema::validate(&layered.config) {
+            eprintln!("Config ({} layer): {issue}", layered.origin(&issue.key));
+        }
+    }
+
+    return layered;
+}
+
+#[test]
+fn test_layer_precedence() {
+    let mut layered = LayeredConfig::new();
+
+    let system = read_env_layer(|name| match name {
+        "BOXEDMINO_SANDBOXED" => Some("no".to_string()),
+        "BOXEDMINO_COLD_CLEAR_VERSION" => Some("11.4.2".to_string()),
+        _ => None
+    }, false);
+
+    let env = read_env_layer(|name| match name {
+        "BOXEDMINO_SANDBOXED" => Some("yes".to_string()),
+        "BOXEDMINO_CLEAR_TEMP_DIR" => Some("maybe".to_string()),
+        _ => None
+    }, false);
+
+    assert_eq!(env.len(), 1);
+
+    layered.apply(Layer::System, &system);
+    layered.apply(Layer::Environment, &env);
+
+    assert!(layered.config.sandboxed);
+    assert_eq!(layered.origin("sandboxed"), Layer::Environment);
+    assert_eq!(layered.config.cold_clear_version, "11.4.2");
+    assert_eq!(layered.origin("cold_clear_version"), Layer::System);
+    assert_eq!(layered.origin("clear_temp_dir"), Layer::Default);
+}
+
+#[test]
+fn test_merge_into_user_layer() {
+    let below_user = Config::new();
+
+    let mut existing = Map::new();
+    existing.insert("sandboxed".to_string(), Value::Bool(false));
+    existing.insert("clear_temp_dir".to_string(), Value::Bool(false));
+
+    // As if `BOXEDMINO_COLD_CLEAR_VERSION=11.4.2` was set, so the window showed that version
+    let mut original = Config::new();
+    original.sandboxed = false;
+    original.clear_temp_dir = false;
+    original.cold_clear_version = "11.4.2".to_string();
+
+    let mut config = original.clone();
+    config.clear_temp_dir = true;
+    config.import_save_on_play = !config.import_save_on_play;
+
+    let values = merge_into_user_layer(&config, &original, &below_user, existing);
+
+    assert_eq!(values.get("sandboxed"), Some(&Value::Bool(false)));
+    assert_eq!(values.get("import_save_on_play"), Some(&Value::Bool(config.import_save_on_play)));
+    // Not edited, so the environment's value isn't saved
+    assert!(!values.contains_key("cold_clear_version"));
+    // Set back to the default
+    assert!(!values.contains_key("clear_temp_dir"));
+    assert!(!values.contains_key("config_version"));
+}
@@ -0,0 +1,385 @@
+use std::fmt;
+use serde_json::{Map, Value};
+use crate::cold_clear::custom;
+use crate::conf::Config;
+
+/// The current version of the config file layout.
+///
+/// Bump this and add a migration to [`MIGRATIONS`]
+/// whenever a change to [`Config`] would misread older files.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades a config object from version `n` to `n + 1`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [
+    migrate_v0_to_v1,
+];
+
+/// Version 0 is every config written before `config_version` existed,
+/// by Boxedmino 0.3.0 and older.
+///
+/// `use_gui` used to be saved even though it is decided on every launch.
+fn migrate_v0_to_v1(object: &mut Map<String, Value>) {
+    object.remove("use_gui");
+}
+
+/// A problem with a single key of the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "`{}`: {}", self.key, self.message);
+    }
+}
+
+/// A problem that prevents the config file from being read at all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The file is not a JSON object or TOML table
+    Syntax(String),
+    /// The file was written by a newer version of Boxedmino
+    TooNew(u64),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ConfigError::Syntax(message) =>
+                write!(f, "The config file could not be parsed: {message}"),
+            ConfigError::TooNew(version) =>
+                write!(
+                    f,
+                    "The config file has version {version}, but this Boxedmino only supports up to version {CONFIG_VERSION}. Update Boxedmino to use it."
+                ),
+        };
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The keys read from one config file.
+#[derive(Debug)]
+pub struct ParsedLayer {
+    /// Valid keys and their values, in the current layout.
+    /// `config_version` is not included.
+    pub values: Map<String, Value>,
+    /// Keys that were ignored
+    pub issues: Vec<ConfigIssue>,
+    /// The version the file was migrated from, if it was outdated
+    pub migrated_from: Option<u32>,
+}
+
+/// The result of reading a config file.
+#[derive(Debug)]
+pub struct ParsedConfig {
+    pub config: Config,
+    /// Keys that were ignored and replaced with their defaults
+    pub issues: Vec<ConfigIssue>,
+    /// The version the file was migrated from, if it was outdated
+    pub migrated_from: Option<u32>,
+}
+
+/// Migrates a config object to the current layout and drops invalid keys.
+///
+/// `assumed_version` is used if the object has no `config_version` key.
+fn check_object(
+    mut object: Map<String, Value>,
+    assumed_version: u32
+) -> Result<ParsedLayer, ConfigError> {
+    let mut issues: Vec<ConfigIssue> = Vec::new();
+
+    let version = match object.remove("config_version") {
+        None => assumed_version as u64,
+        Some(Value::Number(n)) if n.as_u64().is_some() => n.as_u64().unwrap(),
+        Some(other) => {
+            issues.push(ConfigIssue {
+                key: "config_version".to_string(),
+                message: format!("expected a non-negative integer, found {other}; assuming version {assumed_version}"),
+            });
+            assumed_version as u64
+        }
+    };
+
+    if version > CONFIG_VERSION as u64 {
+        return Err(ConfigError::TooNew(version));
+    }
+
+    let version = version as u32;
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut object);
+    }
+
+    let keys = Config::keys();
+    let names: Vec<String> = object.keys().cloned().collect();
+
+    for key in names {
+        if !keys.contains(&key) {
+            object.remove(&key);
+            issues.push(ConfigIssue {
+                message: match suggest_key(&key, keys.iter()) {
+                    Some(suggestion) => format!("unknown key, did you mean `{suggestion}`?"),
+                    None => "unknown key".to_string()
+                },
+                key,
+            });
+            continue;
+        }
+
+        // Deserialize each key on its own to find out which one is invalid
+        let mut single = Map::new();
+        single.insert(key.clone(), object[&key].clone());
+
+        if let Err(e) = serde_json::from_value::<Config>(Value::Object(single)) {
+            object.remove(&key);
+            issues.push(ConfigIssue {
+                key,
+                message: format!("{e}; ignoring this key"),
+            });
+        }
+    }
+
+    return Ok(ParsedLayer {
+        values: object,
+        issues,
+        migrated_from: if version < CONFIG_VERSION { Some(version) } else { None },
+    });
+}
+
+/// Reads the keys of a JSON config file of any known version.
+///
+/// Keys that are unknown or have invalid values are reported
+/// and left out, rather than discarding the whole file.
+pub fn parse_layer(contents: &str) -> Result<ParsedLayer, ConfigError> {
+    let value: Value = serde_json::from_str(contents)
+        .map_err(|e| ConfigError::Syntax(e.to_string()))?;
+
+    return match value {
+        // Files without a version predate versioning
+        Value::Object(object) => check_object(object, 0),
+        _ => Err(ConfigError::Syntax("expected an object at the top level".to_string()))
+    };
+}
+
+/// Reads the keys of a TOML config file, such as a project-local `boxedmino.toml`.
+///
+/// TOML config files were introduced with the current layout,
+/// so a missing `config_version` means the current version.
+pub fn parse_toml_layer(contents: &str) -> Result<ParsedLayer, ConfigError> {
+    let object: Map<String, Value> = toml::from_str(contents)
+        .map_err(|e| ConfigError::Syntax(e.to_string()))?;
+
+    return check_values(object);
+}
+
+/// Checks config values that come from somewhere other than a config file,
+/// such as a launch recipe. They are expected to be in the current layout.
+pub fn check_values(values: Map<String, Value>) -> Result<ParsedLayer, ConfigError> {
+    return check_object(values, CONFIG_VERSION);
+}
+
+/// Reads a JSON config file of any known version, migrating it to the current layout.
+/// Keys that are missing, unknown or invalid fall back to their defaults.
+pub fn parse(contents: &str) -> Result<ParsedConfig, ConfigError> {
+    let layer = parse_layer(contents)?;
+
+    return Ok(ParsedConfig {
+        config: Config::default().with_values(&layer.values),
+        issues: layer.issues,
+        migrated_from: layer.migrated_from,
+    });
+}
+
+/// Finds a known key that is only a small typo away from `key`.
+fn suggest_key<'a>(key: &str, known: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    return known
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate);
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut prev_diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev_diagonal + if a_char == *b_char { 0 } else { 1 };
+            prev_diagonal = row[j + 1];
+            row[j + 1] = substitution
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+        }
+    }
+
+    return row[b.len()];
+}
+
+/// Whether the string looks like a Cold Clear release tag, e.g. `11.4.1`,
+/// or names a registered custom AI library.
+pub fn is_valid_cold_clear_version(version: &str) -> bool {
+    if custom::is_custom_version(version) {
+        return version.len() > custom::CUSTOM_VERSION_PREFIX.len();
+    }
+
+    let version = version.strip_prefix('v').unwrap_or(version);
+
+    return version.starts_with(|c: char| c.is_ascii_digit()) &&
+        version.chars().all(|c| c.is_ascii_alphanumeric() || ".-_+".contains(c));
+}
+
+/// Checks the values of a config for problems that would show up later,
+/// such as paths that do not exist.
+pub fn validate(config: &Config) -> Vec<ConfigIssue> {
+    let mut issues: Vec<ConfigIssue> = Vec::new();
+
+    if config.repo_initialized && !crate::git::is_repo_valid(&config.game_repo_path) {
+        issues.push(ConfigIssue {
+            key: "game_repo_path".to_string(),
+            message: format!(
+                "{:?} is not a valid game repository (it needs a conf.lua file and a .git folder)",
+                config.game_repo_path
+            ),
+        });
+    }
+
+    if !config.source_save_path.is_empty() && !std::path::Path::new(&config.source_save_path).is_dir() {
+        issues.push(ConfigIssue {
+            key: "source_save_path".to_string(),
+            message: format!("{:?} is not a directory", config.source_save_path),
+        });
+    }
+
+    if let Err(e) = crate::save::import::parse_categories(&config.import_categories) {
+        issues.push(ConfigIssue {
+            key: "import_categories".to_string(),
+            message: e,
+        });
+    }
+
+    for name in crate::patches::parse_list(&config.patches) {
+        if !crate::patches::get_path(&name).is_file() {
+            issues.push(ConfigIssue {
+                key: "patches".to_string(),
+                message: format!("There is no patch named {name:?}"),
+            });
+        }
+    }
+
+    if !is_valid_cold_clear_version(&config.cold_clear_version) {
+        issues.push(ConfigIssue {
+            key: "cold_clear_version".to_string(),
+            message: format!(
+                "{:?} is not a valid Cold Clear version (expected e.g. \"11.4.1\")",
+                config.cold_clear_version
+            ),
+        });
+    }
+
+    return issues;
+}
+
+#[test]
+fn test_parse_v0_1_0_fixture() {
+    let parsed = parse(include_str!("fixtures/config_v0.1.0.json")).unwrap();
+
+    assert_eq!(parsed.migrated_from, Some(0));
+    assert_eq!(parsed.issues, vec![]);
+    assert_eq!(parsed.config.config_version, CONFIG_VERSION);
+    assert!(!parsed.config.sandboxed);
+    assert!(parsed.config.import_save_on_play);
+    assert_eq!(parsed.config.game_repo_path, "/home/user/Techmino");
+    // Added in 0.3.0
+    assert_eq!(parsed.config.cold_clear_version, Config::default().cold_clear_version);
+}
+
+#[test]
+fn test_parse_v0_3_0_fixture() {
+    let parsed = parse(include_str!("fixtures/config_v0.3.0.json")).unwrap();
+
+    assert_eq!(parsed.migrated_from, Some(0));
+    assert_eq!(parsed.issues, vec![]);
+    assert!(!parsed.config.use_cold_clear);
+    assert_eq!(parsed.config.cold_clear_version, "11.4.2");
+    assert_eq!(parsed.config.game_repo_path, "C:\\Users\\user\\Techmino");
+}
+
+#[test]
+fn test_parse_v1_fixture() {
+    let parsed = parse(include_str!("fixtures/config_v1.json")).unwrap();
+
+    assert_eq!(parsed.migrated_from, None);
+    assert_eq!(parsed.issues, vec![]);
+    assert!(parsed.config.clear_temp_dir);
+    assert_eq!(parsed.config.cold_clear_version, "custom:my_cc@1.0");
+}
+
+#[test]
+fn test_parse_reports_invalid_keys() {
+    let parsed = parse(r#"{
+        "config_version": 1,
+        "sandboxd": false,
+        "clear_temp_dir": "yes",
+        "game_repo_path": "/games/Techmino"
+    }"#).unwrap();
+
+    let keys: Vec<&str> = parsed.issues
+        .iter()
+        .map(|issue| issue.key.as_str())
+        .collect();
+
+    // Keys are reported in alphabetical order
+    assert_eq!(keys, vec!["clear_temp_dir", "sandboxd"]);
+    assert!(parsed.issues[1].message.contains("did you mean `sandboxed`?"));
+    assert_eq!(parsed.config.clear_temp_dir, Config::default().clear_temp_dir);
+    assert_eq!(parsed.config.game_repo_path, "/games/Techmino");
+}
+
+#[test]
+fn test_parse_rejects_unreadable_files() {
+    assert!(matches!(parse("{ \"sandboxed\": "), Err(ConfigError::Syntax(_))));
+    assert!(matches!(parse("[]"), Err(ConfigError::Syntax(_))));
+    assert_eq!(
+        parse(&format!("{{ \"config_version\": {} }}", CONFIG_VERSION + 1)).unwrap_err(),
+        ConfigError::TooNew(CONFIG_VERSION as u64 + 1)
+    );
+}
+
+#[test]
+fn test_is_valid_cold_clear_version() {
+    let cases = [
+        ("11.4.1", true),
+        ("v11.4.2", true),
+        ("12.0.0-beta.1", true),
+        ("custom:my_cc@1.0", true),
+        ("custom:", false),
+        ("", false),
+        ("latest", false),
+        ("11.4.1/../../etc", false),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(is_valid_cold_clear_version(input), expected, "{input}");
+    }
+}
+
+#[test]
+fn test_parse_toml_layer() {
+    let layer = parse_toml_layer(r#"
+        sandboxed = false
+        cold_clear_version = "11.4.2"
+        colour = "blue"
+    "#).unwrap();
+
+    assert_eq!(layer.migrated_from, None);
+    assert_eq!(layer.values.len(), 2);
+    assert_eq!(layer.values["sandboxed"], Value::Bool(false));
+    assert_eq!(layer.issues[0].key, "colour");
+}
@@ -1,9 +1,17 @@
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::info;
 
 pub fn clear_temp_dir() {
-    let path = crate::dirs::paths::get_sandboxed_save_path();
+    clear_sandbox_dir(&crate::dirs::paths::get_sandboxed_save_path());
+}
 
-    println!("Dangerous operation: Clearing temporary directory at {}", path.to_string_lossy());
+/// Removes everything in the sandbox save directory at `path`
+/// except the `lib` directory, so that Cold Clear isn't unpacked again
+/// on every launch.
+pub fn clear_sandbox_dir(path: &Path) {
+    info!("Dangerous operation: Clearing temporary directory at {}", path.to_string_lossy());
 
     if !path.exists() {
         return;
@@ -11,7 +19,7 @@ pub fn clear_temp_dir() {
 
     let entries = fs::read_dir(path);
 
-    if let Err(_) = entries {
+    if entries.is_err() {
         return;
     }
 
@@ -19,33 +27,33 @@ pub fn clear_temp_dir() {
 
     for entry in entries {
         let entry = entry.expect("Failed to read entry");
+        if entry.file_name() == "lib" {
+            continue;
+        }
+
         let path = entry.path();
         if path.is_dir() {
             fs::remove_dir_all(&path)
-                .expect(
-                    format!(
-                        "Failed to remove directory {}",
-                        path.to_string_lossy()
-                    ).as_str()
-                )
+                .unwrap_or_else(|_| panic!(
+                    "Failed to remove directory {}",
+                    path.to_string_lossy()
+                ))
         } else {
             fs::remove_file(&path)
-                .expect(
-                    format!(
-                        "Failed to remove file {}",
-                        path.to_string_lossy()
-                    ).as_str()
-                )
+                .unwrap_or_else(|_| panic!(
+                    "Failed to remove file {}",
+                    path.to_string_lossy()
+                ))
         }
     }
 
-    println!("Cleared temporary directory");
+    info!("Cleared temporary directory");
 }
 
 
 pub fn is_dir_empty(path: &str) -> bool {
     let files = fs::read_dir(path);
-    if let Err(_) = files {
+    if files.is_err() {
         return false;
     }
 
@@ -53,13 +61,302 @@ pub fn is_dir_empty(path: &str) -> bool {
     return files.count() == 0;
 }
 
+/// Checks that a name from the user or an untrusted file can be used as a file name
+/// and in comma-separated lists. `kind` describes the name in the error, e.g. "patch name".
+pub fn validate_file_name(kind: &str, name: &str) -> Result<(), String> {
+    let valid = !name.is_empty() &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) &&
+        !name.starts_with('.');
+
+    if !valid {
+        return Err(format!(
+            "Invalid {kind} {name:?}: use only letters, digits, '-', '_' and '.'"
+        ));
+    }
+
+    return Ok(());
+}
+
+#[test]
+fn test_validate_file_name() {
+    assert!(validate_file_name("patch name", "fix-crash_2.1").is_ok());
+    assert!(validate_file_name("patch name", "../secrets").is_err());
+    assert!(validate_file_name("patch name", "two words").is_err());
+    assert!(validate_file_name("patch name", "..").is_err());
+    assert!(validate_file_name("patch name", "a/b").is_err());
+    assert!(validate_file_name("patch name", "").is_err());
+}
+
+/// Lists the files in `dir` and its subdirectories, relative to `dir`.
+fn list_files(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &relative, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Where a file from the legacy directory belongs now.
+fn get_legacy_file_destination(relative: &Path) -> PathBuf {
+    let is_cache = relative.starts_with("cold_clear") &&
+        relative != Path::new("cold_clear/custom_libraries.json");
+
+    return if is_cache {
+        paths::get_cache_dir_path().join(relative)
+    } else {
+        paths::get_conf_dir_path().join(relative)
+    };
+}
+
+#[test]
+fn test_legacy_file_destination() {
+    let cases = [
+        ("config.json", paths::get_conf_dir_path()),
+        ("config.v0.backup.json", paths::get_conf_dir_path()),
+        ("cold_clear/custom_libraries.json", paths::get_conf_dir_path()),
+        ("cold_clear/11.4.1.zip", paths::get_cache_dir_path()),
+        ("cold_clear/releases.json", paths::get_cache_dir_path()),
+    ];
+
+    for (relative, base) in cases {
+        assert_eq!(get_legacy_file_destination(Path::new(relative)), base.join(relative));
+    }
+}
+
+fn move_file(source: &Path, destination: &Path) -> io::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Renaming fails across file systems, so fall back to copying
+    if fs::rename(source, destination).is_err() {
+        fs::copy(source, destination)?;
+        fs::remove_file(source)?;
+    }
+
+    return Ok(());
+}
+
+/// Moves the files of Boxedmino 0.3.0 and older, which were all kept in one directory,
+/// to the config and cache directories.
+///
+/// This only happens once, and never when the data directory is overridden.
+pub fn migrate_legacy_data() {
+    if paths::get_data_dir_override().is_some() {
+        return;
+    }
+
+    let marker_path = paths::get_legacy_migration_marker_path();
+
+    if marker_path.exists() {
+        return;
+    }
+
+    let legacy_path = paths::get_legacy_dir_path();
+    let mut files: Vec<PathBuf> = Vec::new();
+
+    if legacy_path.is_dir() {
+        if let Err(e) = list_files(&legacy_path, Path::new(""), &mut files) {
+            eprintln!("Failed to read old data directory {}: {e}", legacy_path.to_string_lossy());
+            return;
+        }
+    }
+
+    let mut failed = false;
+
+    for relative in files {
+        let source = legacy_path.join(&relative);
+        let destination = get_legacy_file_destination(&relative);
+
+        if source == destination {
+            continue;
+        }
+
+        if destination.exists() {
+            eprintln!(
+                "Not moving {}, as {} already exists",
+                source.to_string_lossy(),
+                destination.to_string_lossy()
+            );
+            continue;
+        }
+
+        match move_file(&source, &destination) {
+            Ok(_) => info!(
+                "Moved {} to {}",
+                source.to_string_lossy(),
+                destination.to_string_lossy()
+            ),
+            Err(e) => {
+                eprintln!("Failed to move {}: {e}", source.to_string_lossy());
+                failed = true;
+            }
+        }
+    }
+
+    // Only empty directories are removed, so this is safe to try
+    let _ = fs::remove_dir(legacy_path.join("cold_clear"));
+    let _ = fs::remove_dir(&legacy_path);
+
+    // Try again next time if anything is left behind
+    if failed {
+        return;
+    }
+
+    if let Some(parent) = marker_path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return;
+        }
+    }
+
+    if let Err(e) = fs::write(&marker_path, "") {
+        eprintln!("Failed to write {}: {e}", marker_path.to_string_lossy());
+    }
+}
+
 pub mod paths {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::sync::Once;
+    use once_cell::sync::Lazy;
 
     #[cfg(not(target_os = "windows"))]
     use home::home_dir;
 
+    /// Placing a file with this name next to the executable turns on portable mode,
+    /// where everything is kept next to the executable instead of on the host machine.
+    pub const PORTABLE_MARKER_FILE_NAME: &str = "boxedmino.portable";
+
+    static PORTABLE_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+        let exe_path = std::env::current_exe().ok()?;
+        let exe_dir = exe_path.parent()?;
+
+        if exe_dir.join(PORTABLE_MARKER_FILE_NAME).is_file() {
+            return Some(exe_dir.to_path_buf());
+        }
+
+        return None;
+    });
+
+    /// The directory of the executable, if running in portable mode.
+    pub fn get_portable_dir_path() -> Option<&'static Path> {
+        return PORTABLE_DIR.as_deref();
+    }
+
+    /// The directory given with `--data-dir`, the `data` directory in portable mode,
+    /// or the directory given with `BOXEDMINO_HOME`, which holds all Boxedmino files
+    /// instead of the platform's usual locations.
+    ///
+    /// The portable marker wins over `BOXEDMINO_HOME`, which may be left over
+    /// on the host machine, so that nothing is written outside the portable directory.
+    pub fn get_data_dir_override() -> Option<PathBuf> {
+        if let Some(dir) = crate::get_data_dir_arg() {
+            return Some(dir.clone());
+        }
+
+        let env_dir = std::env::var_os("BOXEDMINO_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from);
+
+        if let Some(dir) = get_portable_dir_path() {
+            if let Some(env_dir) = &env_dir {
+                static WARNING: Once = Once::new();
+                WARNING.call_once(|| eprintln!(
+                    "Ignoring BOXEDMINO_HOME={} in portable mode",
+                    env_dir.to_string_lossy()
+                ));
+            }
+
+            return Some(dir.join("data"));
+        }
+
+        return env_dir;
+    }
+
+    /// Resolves a path stored in the config relative to the executable in portable mode,
+    /// as the drive may be mounted somewhere else on every machine.
+    pub fn resolve_portable_path(path: &str) -> String {
+        return match get_portable_dir_path() {
+            Some(dir) if !path.is_empty() && Path::new(path).is_relative() =>
+                dir.join(path).to_string_lossy().to_string(),
+            _ => path.to_string()
+        };
+    }
+
+    /// The inverse of [`resolve_portable_path`]: paths inside the portable directory
+    /// are made relative to it before being stored.
+    pub fn to_portable_path(path: &str) -> String {
+        let dir = match get_portable_dir_path() {
+            Some(dir) => dir,
+            None => return path.to_string()
+        };
+
+        return match Path::new(path).strip_prefix(dir) {
+            Ok(relative) => relative.to_string_lossy().to_string(),
+            Err(_) => path.to_string()
+        };
+    }
+
+    /// In portable mode, where LÖVE keeps the save directory of every identity.
+    /// The injected code moves them there, see `injected_portable.lua`.
+    pub fn get_portable_love_save_root() -> Option<PathBuf> {
+        get_portable_dir_path()?;
+
+        return Some(get_data_dir_override()?.join("saves"));
+    }
+
+    /// The LÖVE executable to run. In portable mode, a copy of LÖVE
+    /// next to the executable is preferred over the one on the host machine.
+    pub fn get_love_command() -> PathBuf {
+        #[cfg(target_os = "windows")]
+        let candidates = ["love/love.exe", "love.exe"];
+
+        #[cfg(target_os = "macos")]
+        let candidates = ["love.app/Contents/MacOS/love", "love/love"];
+
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let candidates = ["love/love", "love.AppImage", "love"];
+
+        if let Some(dir) = get_portable_dir_path() {
+            for candidate in candidates {
+                let path = dir.join(candidate);
+                if path.is_file() {
+                    return path;
+                }
+            }
+        }
+
+        return PathBuf::from("love");
+    }
+
+    /// An XDG base directory, e.g. `$XDG_CONFIG_HOME`, falling back to
+    /// `fallback` in the home directory if the variable is unset or not absolute.
+    #[cfg(target_os = "linux")]
+    fn get_xdg_dir_path(var: &str, fallback: &str) -> PathBuf {
+        if let Some(dir) = std::env::var_os(var) {
+            let dir = PathBuf::from(dir);
+            if dir.is_absolute() {
+                return dir;
+            }
+        }
+
+        return home_dir()
+            .expect("Could not find home directory")
+            .join(fallback);
+    }
+
+    /// Where config files live, such as the config and the AI library registry.
     pub fn get_conf_dir_path() -> PathBuf {
+        if let Some(dir) = get_data_dir_override() {
+            return dir.join("config");
+        }
+
         #[cfg(target_os = "windows")] {
             let appdata = std::env::var("APPDATA").expect("AppData directory not found");
             return PathBuf::from(appdata)
@@ -74,112 +371,254 @@ pub mod paths {
         }
 
         #[cfg(target_os = "linux")]
+        {
+            return get_xdg_dir_path("XDG_CONFIG_HOME", ".config")
+                .join("boxedmino");
+        }
+
+        #[cfg(target_os = "android")]
+        {
+            return PathBuf::from("/data/data/org.f26_studio.Boxedmino");
+        }
+    }
+
+    /// Where downloaded files that can be fetched again live, such as Cold Clear archives.
+    pub fn get_cache_dir_path() -> PathBuf {
+        if let Some(dir) = get_data_dir_override() {
+            return dir.join("cache");
+        }
+
+        #[cfg(target_os = "windows")] {
+            let local_appdata = std::env::var("LOCALAPPDATA").expect("LocalAppData directory not found");
+            return PathBuf::from(local_appdata)
+                .join("Boxedmino")
+                .join("Cache")
+        }
+
+        #[cfg(target_os = "macos")]
         {
             return home_dir()
                 .expect("Could not find home directory")
-                .join(".local/share/Boxedmino");
+                .join("Library/Caches/Boxedmino");
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            return get_xdg_dir_path("XDG_CACHE_HOME", ".cache")
+                .join("boxedmino");
         }
 
         #[cfg(target_os = "android")]
         {
-            return PathBuf::from("/data/data/org.f26_studio.Boxedmino");
+            return PathBuf::from("/data/data/org.f26_studio.Boxedmino/cache");
         }
     }
 
-    pub fn get_sandboxed_save_path() -> PathBuf {
-        #[cfg(target_os = "windows")]
+    /// Where data that is neither config nor cache lives, such as history and logs.
+    pub fn get_state_dir_path() -> PathBuf {
+        if let Some(dir) = get_data_dir_override() {
+            return dir.join("state");
+        }
+
+        #[cfg(target_os = "windows")] {
+            let local_appdata = std::env::var("LOCALAPPDATA").expect("LocalAppData directory not found");
+            return PathBuf::from(local_appdata)
+                .join("Boxedmino")
+                .join("State")
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            return home_dir()
+                .expect("Could not find home directory")
+                .join("Library/Application Support/Boxedmino/State");
+        }
+
+        #[cfg(target_os = "linux")]
         {
+            return get_xdg_dir_path("XDG_STATE_HOME", ".local/state")
+                .join("boxedmino");
+        }
+
+        #[cfg(target_os = "android")]
+        {
+            return PathBuf::from("/data/data/org.f26_studio.Boxedmino/state");
+        }
+    }
+
+    /// The single directory Boxedmino 0.3.0 and older kept all of its files in.
+    pub fn get_legacy_dir_path() -> PathBuf {
+        #[cfg(target_os = "windows")] {
             let appdata = std::env::var("APPDATA").expect("AppData directory not found");
-            let path = appdata + "LOVE\\_tmp_boxedmino";
-            let path = path.as_str();
-            return PathBuf::from(path);
+            return PathBuf::from(appdata)
+                .join("Boxedmino")
         }
 
         #[cfg(target_os = "macos")]
         {
             return home_dir()
                 .expect("Could not find home directory")
-                .join("Library/Application Support/LOVE/_tmp_boxedmino");
+                .join("Library/Application Support/Boxedmino");
         }
 
         #[cfg(target_os = "linux")]
         {
             return home_dir()
                 .expect("Could not find home directory")
-                .join(".local/share/love/_tmp_boxedmino");
+                .join(".local/share/Boxedmino");
         }
 
         #[cfg(target_os = "android")]
         {
-            return PathBuf::from("/data/data/org.love2d.android/_tmp_boxedmino");
+            return PathBuf::from("/data/data/org.f26_studio.Boxedmino");
         }
     }
 
-    pub fn get_normal_save_path() -> PathBuf {
+    /// Marks that files from [`get_legacy_dir_path`] were moved to their current locations.
+    pub fn get_legacy_migration_marker_path() -> PathBuf {
+        return get_state_dir_path().join("legacy_migrated");
+    }
+
+    /// Records every launch of the game, one JSON object per line.
+    pub fn get_history_path() -> PathBuf {
+        return get_state_dir_path().join("history.jsonl");
+    }
+
+    /// Records what the game repository had checked out while another version runs.
+    pub fn get_original_head_path() -> PathBuf {
+        return get_state_dir_path().join("original_head.json");
+    }
+
+    pub fn get_sandboxed_save_path() -> PathBuf {
+        if let Some(root) = get_portable_love_save_root() {
+            return root.join("_tmp_boxedmino");
+        }
+
+        return get_love_save_root_path().join("_tmp_boxedmino");
+    }
+
+    /// The directory in the sandbox where native libraries such as Cold Clear live.
+    pub fn get_sandboxed_lib_path() -> PathBuf {
+        return get_sandboxed_save_path().join("lib");
+    }
+
+    /// The directory fused LÖVE games save to directly, each in a directory
+    /// named after their identity.
+    pub fn get_love_appdata_path() -> PathBuf {
         #[cfg(target_os = "windows")]
         {
             let appdata = std::env::var("APPDATA").expect("AppData directory not found");
-
-            // TODO: Check for fused directory
-
-            let path = appdata + "LOVE\\Techmino";
-            let path = path.as_str();
-            return PathBuf::from(path);
+            return PathBuf::from(appdata);
         }
 
         #[cfg(target_os = "macos")]
         {
             return home_dir()
                 .expect("Could not find home directory")
-                .join("Library/Application Support/LOVE/Techmino");
+                .join("Library/Application Support");
         }
 
         #[cfg(target_os = "linux")]
         {
-            return home_dir()
-                .expect("Could not find home directory")
-                .join(".local/share/love/Techmino");
+            return get_xdg_dir_path("XDG_DATA_HOME", ".local/share");
         }
 
         #[cfg(target_os = "android")]
         {
-            // TODO: Check for fused directory
-            return PathBuf::from("/data/data/org.love2d.android/Techmino");
+            return PathBuf::from("/data/data");
         }
     }
 
+    /// The directory games run through LÖVE save to, each in a directory
+    /// named after their identity.
+    pub fn get_love_save_root_path() -> PathBuf {
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        return get_love_appdata_path().join("LOVE");
+
+        #[cfg(target_os = "linux")]
+        return get_love_appdata_path().join("love");
+
+        #[cfg(target_os = "android")]
+        return get_love_appdata_path().join("org.love2d.android");
+    }
+
     pub fn get_config_path() -> PathBuf {
         return get_conf_dir_path().join("config.json");
     }
 
+    /// The config file shared by every user of the machine, below the user's own config.
+    /// Android has no such location.
+    pub fn get_system_config_path() -> Option<PathBuf> {
+        #[cfg(target_os = "windows")]
+        {
+            let program_data = std::env::var("PROGRAMDATA").ok()?;
+            return Some(PathBuf::from(program_data).join("Boxedmino").join("config.json"));
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            return Some(PathBuf::from("/Library/Application Support/Boxedmino/config.json"));
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            return Some(PathBuf::from("/etc/boxedmino/config.json"));
+        }
+
+        #[cfg(target_os = "android")]
+        {
+            return None;
+        }
+    }
+
+    /// Path to a copy of the config file, kept before migrating or discarding it.
+    pub fn get_config_backup_path(label: &str) -> PathBuf {
+        return get_conf_dir_path().join(format!("config.{label}.backup.json"));
+    }
+
+    pub fn get_recipes_dir_path() -> PathBuf {
+        return get_conf_dir_path().join("recipes");
+    }
+
+    pub fn get_patches_dir_path() -> PathBuf {
+        return get_conf_dir_path().join("patches");
+    }
+
+    /// Where versions are checked out while they are exported.
+    pub fn get_export_worktree_path() -> PathBuf {
+        return get_cache_dir_path().join("export_worktree");
+    }
+
     pub fn get_cold_clear_download_path(version: &str) -> PathBuf {
-        return get_conf_dir_path()
+        return get_cache_dir_path()
             .join("cold_clear")
             .join(version.to_string() + ".zip");
     }
 
-    pub fn get_cold_clear_download_url(version: &str) -> String {
-        let file_name = match std::env::consts::OS {
-            "windows" => "Windows.zip",
-            "macos" => "macOS.zip",
-            "linux" => "Linux.zip",
-            "android" => "Android.zip",
-            "ios" => "iOS.zip",
-            _ => unreachable!()
-        };
+    pub fn get_archives_registry_path() -> PathBuf {
+        return get_conf_dir_path().join("archives.json");
+    }
+
+    /// Where registered game archives are extracted to.
+    pub fn get_archives_cache_path() -> PathBuf {
+        return get_cache_dir_path().join("archives");
+    }
+
+    pub fn get_custom_ai_libraries_path() -> PathBuf {
+        return get_conf_dir_path()
+            .join("cold_clear")
+            .join("custom_libraries.json");
+    }
 
-        #[cfg(not(any(
-            target_os = "windows",
-            target_os = "macos",
-            target_os = "linux",
-            target_os = "android",
-            target_os = "ios"
-        )))]
-        compile_error!("Unsupported operating system: {}", std::env::consts::OS);
-
-        return format!(
-            "https://github.com/26F-Studio/cold_clear_ai_love2d_wrapper/releases/download/{version}/{file_name}"
-        );
+    /// The identities set by each tagged commit of the game, see [`crate::save::location::get_identities`].
+    pub fn get_identity_cache_path() -> PathBuf {
+        return get_cache_dir_path().join("identities.json");
+    }
+
+    pub fn get_cold_clear_release_cache_path() -> PathBuf {
+        return get_cache_dir_path()
+            .join("cold_clear")
+            .join("releases.json");
     }
 
     pub const COLD_CLEAR_RELEASES_API_URL: &str =
@@ -0,0 +1,520 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use serde::Serialize;
+use crate::cold_clear;
+use crate::cold_clear::install::InstallMarker;
+use crate::cold_clear::releases::ReleaseCache;
+use crate::conf::{schema, Config};
+use crate::dirs::paths;
+use crate::git;
+use crate::output;
+use crate::repo_health;
+use crate::save;
+
+/// How long to wait for each endpoint in the network checks.
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Free space below which the sandbox check warns, in bytes.
+const LOW_DISK_SPACE: u64 = 200 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        };
+    }
+}
+
+/// The result of one diagnostic check.
+#[derive(Serialize, Clone, Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+    /// What the user can do about a warning or failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, message: impl Into<String>) -> Self {
+        return Self {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            message: message.into(),
+            hint: None,
+        };
+    }
+
+    fn warn(name: &str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        return Self {
+            name: name.to_string(),
+            status: CheckStatus::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        };
+    }
+
+    fn fail(name: &str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        return Self {
+            name: name.to_string(),
+            status: CheckStatus::Fail,
+            message: message.into(),
+            hint: Some(hint.into()),
+        };
+    }
+}
+
+/// Everything `boxedmino doctor` found, in a form that can be attached to bug reports.
+#[derive(Serialize, Clone, Debug)]
+pub struct Report {
+    pub boxedmino_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// The worst status among the checks.
+    pub fn status(&self) -> CheckStatus {
+        return self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass);
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self)
+            .expect("Failed to serialize diagnostics report");
+    }
+}
+
+/// Runs a `--version` command and returns the first line it printed.
+fn get_program_version(program: &Path) -> Result<String, String> {
+    let output = Command::new(program)
+        .arg("--version")
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(format!("`{} --version` exited with {}", program.to_string_lossy(), output.status));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    return Ok(stdout.lines().next().unwrap_or("").trim().to_string());
+}
+
+fn check_git() -> Check {
+    return match get_program_version(Path::new("git")) {
+        Ok(version) => Check::pass("Git", version),
+        Err(e) => Check::fail(
+            "Git",
+            format!("Git could not be run: {e}"),
+            "Install Git from https://git-scm.com/downloads and make sure `git --version` works in a terminal."
+        ),
+    };
+}
+
+fn check_love() -> Check {
+    let command = paths::get_love_command();
+
+    return match get_program_version(&command) {
+        Ok(version) => Check::pass("LÖVE", version),
+        Err(e) => Check::fail(
+            "LÖVE",
+            format!("`{}` could not be run: {e}", command.to_string_lossy()),
+            "Install LÖVE from https://love2d.org/ and make sure `love --version` works in a terminal."
+        ),
+    };
+}
+
+fn check_config() -> Check {
+    let path = paths::get_config_path();
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) if !path.exists() => {
+            return Check::pass("Config file", "No config file yet, the defaults are used");
+        }
+        Err(e) => {
+            return Check::fail(
+                "Config file",
+                format!("{} could not be read: {e}", path.to_string_lossy()),
+                "Check the permissions of the config file."
+            );
+        }
+    };
+
+    let parsed = match schema::parse(&contents) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Check::fail(
+                "Config file",
+                e.to_string(),
+                "Fix the file with `boxedmino config edit`, or start over with `boxedmino config reset`."
+            );
+        }
+    };
+
+    let issues: Vec<String> = parsed.issues
+        .iter()
+        .chain(schema::validate(&parsed.config).iter())
+        .map(|issue| issue.to_string())
+        .collect();
+
+    if !issues.is_empty() {
+        return Check::warn(
+            "Config file",
+            issues.join("\n"),
+            "Invalid keys are replaced with their defaults. Fix them with `boxedmino config edit`."
+        );
+    }
+
+    return Check::pass("Config file", path.to_string_lossy());
+}
+
+fn check_repo(cfg: &Config) -> Vec<Check> {
+    let repo_path = &cfg.game_repo_path;
+
+    if !git::is_repo_valid(repo_path) {
+        return vec![Check::fail(
+            "Game repository",
+            format!("{repo_path:?} is not a Techmino repository"),
+            "Run Boxedmino without arguments to set up the repository, or use `boxedmino config set game_repo_path <PATH>`."
+        )];
+    }
+
+    let mut checks = vec![Check::pass("Game repository", repo_path)];
+
+    let problems = repo_health::check(repo_path);
+
+    checks.push(if problems.is_empty() {
+        Check::pass("Repository state", "Clean")
+    } else {
+        Check::warn(
+            "Repository state",
+            problems
+                .iter()
+                .map(|problem| problem.to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            "Start Boxedmino to be offered a repair."
+        )
+    });
+
+    checks.push(match git::resolve_commit(repo_path, "HEAD") {
+        None => Check::fail(
+            "HEAD",
+            "The repository has no commits",
+            "Clone the repository again."
+        ),
+        Some(commit) => Check::pass(
+            "HEAD",
+            format!(
+                "{commit} ({}) on {}",
+                git::describe_tag(repo_path).unwrap_or("no tag".to_string()),
+                git::current_branch(repo_path)
+                    .map(|branch| format!("branch {branch}"))
+                    .unwrap_or("a detached HEAD".to_string())
+            )
+        ),
+    });
+
+    return checks;
+}
+
+/// Free space on the drive containing `path`, in bytes.
+#[cfg(unix)]
+fn get_free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // SAFETY: `path` is a valid C string and `stat` is a valid buffer
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    return Some(stat.f_bavail as u64 * stat.f_frsize as u64);
+}
+
+#[cfg(not(unix))]
+fn get_free_space(_path: &Path) -> Option<u64> {
+    return None;
+}
+
+fn format_size(bytes: u64) -> String {
+    return format!("{:.1} GiB", bytes as f64 / (1024.0 * 1024.0 * 1024.0));
+}
+
+/// Checks that files can be created in `path`,
+/// or in its closest existing parent if it does not exist yet.
+fn check_writable(name: &str, path: &Path) -> Check {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(path);
+
+    let probe = existing.join(".boxedmino_doctor_probe");
+
+    if let Err(e) = fs::write(&probe, "") {
+        return Check::fail(
+            name,
+            format!("{} is not writable: {e}", existing.to_string_lossy()),
+            "Check the permissions of the directory, or move Boxedmino's data with `--data-dir`."
+        );
+    }
+
+    let _ = fs::remove_file(&probe);
+
+    let location = if existing == path {
+        path.to_string_lossy().to_string()
+    } else {
+        format!("{} (not created yet)", path.to_string_lossy())
+    };
+
+    return match get_free_space(existing) {
+        Some(free) if free < LOW_DISK_SPACE => Check::warn(
+            name,
+            format!("{location}, only {} free", format_size(free)),
+            "Free up disk space; the game and Cold Clear may fail to save."
+        ),
+        Some(free) => Check::pass(name, format!("{location}, {} free", format_size(free))),
+        None => Check::pass(name, format!("{location}, free space unknown")),
+    };
+}
+
+fn check_save(cfg: &Config) -> Check {
+    let path = match save::location::find_save_path(cfg) {
+        Ok(path) => path,
+        Err(e) if cfg.import_save_on_play => {
+            return Check::fail(
+                "Main save",
+                e,
+                "Choose your save directory in the settings, or turn off save importing."
+            );
+        }
+        Err(e) => {
+            return Check::warn(
+                "Main save",
+                e,
+                "Your save is only needed to import it into the sandbox."
+            );
+        }
+    };
+
+    return match fs::read_dir(&path) {
+        Ok(_) => Check::pass("Main save", path.to_string_lossy()),
+        Err(e) => Check::fail(
+            "Main save",
+            format!("{} is not readable: {e}", path.to_string_lossy()),
+            "Check the permissions of your save directory."
+        ),
+    };
+}
+
+fn check_cold_clear(cfg: &Config) -> Check {
+    let lib_path = paths::get_sandboxed_lib_path();
+
+    if let Some(marker) = InstallMarker::load(&lib_path) {
+        if let Err(problem) = marker.verify(&lib_path) {
+            return Check::warn(
+                "Cold Clear",
+                format!("Installed version '{}' is damaged: {problem}", marker.version),
+                "It will be reinstalled on the next launch with Cold Clear enabled."
+            );
+        }
+    }
+
+    let downloaded = cold_clear::get_available_offline_versions();
+    let cache = ReleaseCache::load();
+
+    let summary = format!(
+        "{} downloaded, release list refreshed: {}",
+        if downloaded.is_empty() { "none".to_string() } else { downloaded.join(", ") },
+        cache.format_refreshed_at()
+    );
+
+    let available = downloaded.contains(&cfg.cold_clear_version) ||
+        cold_clear::custom::is_custom_version(&cfg.cold_clear_version);
+
+    if cfg.use_cold_clear && !available {
+        return Check::warn(
+            "Cold Clear",
+            format!("Version '{}' is not downloaded yet; {summary}", cfg.cold_clear_version),
+            "It will be downloaded on the next launch, which needs a network connection."
+        );
+    }
+
+    return Check::pass("Cold Clear", summary);
+}
+
+fn check_display() -> Vec<Check> {
+    if cfg!(not(target_os = "linux")) {
+        return vec![Check::pass("Display", "Native windowing")];
+    }
+
+    let has_x11 = std::env::var_os("DISPLAY").is_some();
+    let has_wayland = std::env::var_os("WAYLAND_DISPLAY").is_some() ||
+        std::env::var_os("WAYLAND_SOCKET").is_some();
+
+    if !has_x11 && !has_wayland {
+        return vec![Check::fail(
+            "Display",
+            "Neither DISPLAY nor WAYLAND_DISPLAY is set",
+            "The GUI and the game need a graphical session. Use `boxedmino run` from a desktop terminal."
+        )];
+    }
+
+    let session = if has_wayland { "Wayland" } else { "X11" };
+    let mut checks = vec![Check::pass("Display", session)];
+
+    if std::env::var("XDG_SESSION_TYPE").unwrap_or_default() == "wayland" {
+        checks.push(Check::warn(
+            "Clipboard",
+            "Copying text is not supported on Wayland",
+            "Select and copy text by hand instead."
+        ));
+    } else {
+        checks.push(match copypasta::ClipboardContext::new() {
+            Ok(_) => Check::pass("Clipboard", "Available"),
+            Err(e) => Check::warn(
+                "Clipboard",
+                format!("Not available: {e}"),
+                "Select and copy text by hand instead."
+            ),
+        });
+    }
+
+    return checks;
+}
+
+/// Turns a git remote URL into one that can be requested over HTTP, if possible.
+fn get_http_url(remote_url: &str) -> Option<String> {
+    if remote_url.starts_with("https://") || remote_url.starts_with("http://") {
+        return Some(remote_url.to_string());
+    }
+
+    // `git@github.com:26F-Studio/Techmino.git`
+    let (host, path) = remote_url.strip_prefix("git@")?.split_once(':')?;
+
+    return Some(format!("https://{host}/{path}"));
+}
+
+#[test]
+fn test_get_http_url() {
+    assert_eq!(
+        get_http_url("git@github.com:26F-Studio/Techmino.git"),
+        Some("https://github.com/26F-Studio/Techmino.git".to_string())
+    );
+    assert_eq!(
+        get_http_url("https://github.com/26F-Studio/Techmino.git"),
+        Some("https://github.com/26F-Studio/Techmino.git".to_string())
+    );
+    assert_eq!(get_http_url("/srv/git/Techmino"), None);
+}
+
+fn check_endpoint(client: &reqwest::blocking::Client, name: &str, url: &str) -> Check {
+    let response = client
+        .head(url)
+        .header("User-Agent", "boxedmino")
+        .send();
+
+    return match response {
+        // Any response means the server is reachable
+        Ok(response) => Check::pass(name, format!("{url} ({})", response.status())),
+        Err(e) => Check::warn(
+            name,
+            format!("{url} is unreachable: {e}"),
+            "Check your internet connection and proxy settings. Boxedmino works offline with cached data."
+        ),
+    };
+}
+
+fn check_network(cfg: &Config) -> Vec<Check> {
+    let client = match reqwest::blocking::Client::builder().timeout(NETWORK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return vec![Check::fail("Network", e.to_string(), "Report this as a bug.")],
+    };
+
+    let mut checks = vec![check_endpoint(
+        &client,
+        "Cold Clear releases",
+        paths::COLD_CLEAR_RELEASES_API_URL
+    )];
+
+    let remote = git::remote_url(&cfg.game_repo_path, "origin")
+        .unwrap_or(git::REPO_LINK.to_string());
+
+    checks.push(match get_http_url(&remote) {
+        Some(url) => check_endpoint(&client, "Game repository remote", &url),
+        None => Check::pass("Game repository remote", format!("{remote} (not checked)")),
+    });
+
+    return checks;
+}
+
+/// Runs every check. Network checks are skipped if `offline` is set.
+pub fn diagnose(cfg: &Config, offline: bool) -> Report {
+    let mut checks = vec![
+        check_git(),
+        check_love(),
+        check_config(),
+    ];
+
+    checks.extend(check_repo(cfg));
+    checks.push(check_writable("Sandbox", &paths::get_sandboxed_save_path()));
+    checks.push(check_save(cfg));
+    checks.push(check_writable("Cache directory", &paths::get_cache_dir_path()));
+    checks.push(check_cold_clear(cfg));
+    checks.extend(check_display());
+
+    if !offline {
+        checks.extend(check_network(cfg));
+    }
+
+    return Report {
+        boxedmino_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        checks,
+    };
+}
+
+/// Runs `boxedmino doctor`, returning the exit code.
+/// The code is 1 if any check failed.
+/// `--format json` prints the report as JSON, e.g. to attach it to a bug report.
+pub fn run(cfg: &Config, offline: bool) -> i32 {
+    let report = diagnose(cfg, offline);
+
+    if output::is_json() {
+        output::print_json(&report);
+    } else {
+        for check in &report.checks {
+            println!("[{}] {}: {}", check.status, check.name, check.message.replace('\n', "\n       "));
+
+            if let Some(hint) = &check.hint {
+                println!("       Hint: {hint}");
+            }
+        }
+    }
+
+    return if report.status() == CheckStatus::Fail { 1 } else { 0 };
+}
@@ -0,0 +1,226 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+use crate::cold_clear;
+use crate::cold_clear::install::MARKER_FILE_NAME;
+use crate::dirs::paths;
+use crate::game;
+use crate::git;
+use crate::info;
+use crate::output;
+use crate::progress::ProgressStyle;
+use crate::refs::{self, Resolution};
+
+/// What goes into an exported `.love` file.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// Any revision [`refs::resolve`] accepts
+    pub version: String,
+    /// Whether to add the sandboxing code, so the game saves to the sandbox directory
+    pub sandboxed: bool,
+    /// The Cold Clear version to put in `lib/`, if any
+    pub cold_clear_version: Option<String>,
+}
+
+/// The result of an export.
+#[derive(Serialize, Clone, Debug)]
+pub struct ExportReport {
+    pub resolution: Resolution,
+    pub output_path: String,
+    pub sandboxed: bool,
+    pub cold_clear_version: Option<String>,
+    pub size_bytes: u64,
+}
+
+/// Adds every file under `dir` to the archive, skipping the worktree's `.git` link.
+fn add_dir<W: io::Write + io::Seek>(
+    zip: &mut ZipWriter<W>,
+    root: &Path,
+    dir: &Path
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?
+        .collect::<Result<_, _>>()?;
+
+    // Sorted, so the same version always gives the same archive
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated);
+
+    for entry in entries {
+        if entry.file_name() == ".git" {
+            continue;
+        }
+
+        let path = entry.path();
+        let name = path.strip_prefix(root)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if entry.file_type()?.is_dir() {
+            zip.add_directory(name, options)?;
+            add_dir(zip, root, &path)?;
+            continue;
+        }
+
+        zip.start_file(name, options)?;
+        io::copy(&mut fs::File::open(&path)?, zip)?;
+    }
+
+    return Ok(());
+}
+
+/// Zips the contents of `dir` into a `.love` file at `output_path`.
+pub fn write_love_archive(
+    dir: &Path,
+    output_path: &Path
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut zip = ZipWriter::new(fs::File::create(output_path)?);
+
+    add_dir(&mut zip, dir, dir)?;
+    zip.finish()?;
+
+    return Ok(());
+}
+
+#[test]
+fn test_write_love_archive() {
+    let root = std::env::temp_dir()
+        .join(format!("boxedmino_test_export_{}", std::process::id()));
+    let game_dir = root.join("game");
+
+    fs::create_dir_all(game_dir.join("parts")).unwrap();
+    fs::write(game_dir.join("main.lua"), "print(1)").unwrap();
+    fs::write(game_dir.join("parts/mode.lua"), "return {}").unwrap();
+    fs::write(game_dir.join(".git"), "gitdir: elsewhere").unwrap();
+
+    let output_path = root.join("game.love");
+    write_love_archive(&game_dir, &output_path).unwrap();
+
+    let archive = zip::ZipArchive::new(fs::File::open(&output_path).unwrap()).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+
+    assert_eq!(names, vec!["main.lua", "parts/", "parts/mode.lua"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+/// Removes the export worktree, along with git's record of it.
+fn remove_worktree(repo_path: &str, worktree_path: &Path) {
+    if worktree_path.exists() {
+        let worktree = worktree_path.to_string_lossy();
+        let _ = git::run(repo_path, &["worktree", "remove", "--force", &worktree]);
+    }
+
+    if worktree_path.exists() {
+        let _ = fs::remove_dir_all(worktree_path);
+    }
+
+    let _ = git::run(repo_path, &["worktree", "prune"]);
+}
+
+/// Fills the checked out worktree as requested and zips it.
+fn build(
+    worktree_path: &Path,
+    options: &ExportOptions,
+    output_path: &Path,
+    style: ProgressStyle
+) -> Result<(), String> {
+    if options.sandboxed {
+        game::inject(&worktree_path.join("conf.lua"), &game::injected_script(true, None))
+            .map_err(|e| format!("Failed to add the sandboxing code to conf.lua: {e}"))?;
+    }
+
+    if let Some(version) = &options.cold_clear_version {
+        info!("Adding Cold Clear version '{version}'...");
+        let lib_path = worktree_path.join("lib");
+
+        cold_clear::unpack_cold_clear_to(version, &lib_path, style)
+            .map_err(|e| format!("Failed to add Cold Clear version '{version}': {e}"))?;
+
+        // Only of use to Boxedmino
+        let _ = fs::remove_file(lib_path.join(MARKER_FILE_NAME));
+    }
+
+    info!("Writing {}...", output_path.to_string_lossy());
+
+    return write_love_archive(worktree_path, output_path)
+        .map_err(|e| format!("Failed to write {}: {e}", output_path.to_string_lossy()));
+}
+
+/// Checks out a version into a temporary worktree, so the game repository
+/// is left alone, and packs it into a runnable `.love` file.
+pub fn export(
+    repo_path: &str,
+    options: &ExportOptions,
+    output_path: &Path,
+    style: ProgressStyle
+) -> Result<ExportReport, String> {
+    let resolution = refs::resolve(repo_path, &options.version, true)?;
+    info!("Exporting {resolution}");
+
+    let worktree_path = paths::get_export_worktree_path();
+
+    // Left behind if a previous export was interrupted
+    remove_worktree(repo_path, &worktree_path);
+
+    if let Some(parent) = worktree_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {e}", parent.to_string_lossy()))?;
+    }
+
+    let status = git::run(repo_path, &[
+        "worktree", "add", "--detach", "--quiet",
+        &worktree_path.to_string_lossy(),
+        &resolution.commit,
+    ]);
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(_) => return Err(format!("Failed to check out {} into a temporary worktree", resolution.input)),
+        Err(e) => return Err(format!("Failed to run `git worktree`: {e}")),
+    }
+
+    let result = build(&worktree_path, options, output_path, style);
+
+    remove_worktree(repo_path, &worktree_path);
+
+    result?;
+
+    let size_bytes = fs::metadata(output_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    return Ok(ExportReport {
+        resolution,
+        output_path: output_path.to_string_lossy().to_string(),
+        sandboxed: options.sandboxed,
+        cold_clear_version: options.cold_clear_version.clone(),
+        size_bytes,
+    });
+}
+
+/// Runs `boxedmino export`, returning the exit code.
+pub fn run(repo_path: &str, options: &ExportOptions, output_path: &str, style: ProgressStyle) -> i32 {
+    let report = match export(repo_path, options, Path::new(output_path), style) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    if output::is_json() {
+        output::print_json(&report);
+    } else {
+        println!("Exported {} to {} ({} bytes)", report.resolution.input, report.output_path, report.size_bytes);
+    }
+
+    return 0;
+}
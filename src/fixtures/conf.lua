@@ -0,0 +1,40 @@
+SYSTEM=love.system.getOS()
+if SYSTEM=='OS X' then SYSTEM='macOS' end
+MOBILE=SYSTEM=='Android' or SYSTEM=='iOS'
+function love.conf(t)
+    t.identity='Techmino'-- Saving folder
+    t.version="11.1"
+    t.gammacorrect=false
+    t.appendidentity=true-- Search files in source then in save directory
+    t.accelerometerjoystick=false-- Accelerometer=joystick on ios/android
+    if t.audio then
+        t.audio.mic=false
+        t.audio.mixwithsystem=true
+    end
+
+    local W=t.window
+    W.title='Techmino '..require'version'.string
+    W.width,W.height=1280,720
+    W.minwidth,W.minheight=640,360
+    W.vsync=0-- Unlimited FPS
+    W.msaa=0-- Multi-sampled antialiasing
+    W.depth=0-- Bits/samp of depth buffer
+    W.stencil=1-- Bits/samp of stencil buffer
+    W.display=1-- Monitor ID
+    W.highdpi=true-- High-dpi mode for the window on a Retina display
+    W.x,W.y=nil,nil
+    W.borderless=MOBILE
+    W.resizable=not MOBILE
+    W.fullscreentype=MOBILE and 'exclusive' or 'desktop'-- Fullscreen type
+    if love.filesystem.getInfo('media/image/icon.png') then
+        W.icon='media/image/icon.png'
+    end
+
+    local M=t.modules
+    M.window,M.system,M.event,M.thread=true,true,true,true
+    M.timer,M.math,M.data=true,true,true
+    M.video,M.audio,M.sound=true,true,true
+    M.graphics,M.font,M.image=true,true,true
+    M.mouse,M.touch,M.keyboard,M.joystick=true,true,true,true
+    M.physics=false
+end
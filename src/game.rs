@@ -1,23 +1,308 @@
+use crate::archives::{self, GameArchive};
 use crate::cold_clear;
 use crate::conf::Config;
 use crate::dirs;
 use crate::error_window;
-use crate::git;
+use crate::git::{self, HeadRef};
+use crate::history::{self, RunRecord};
+use crate::info;
+use crate::output;
+use crate::patches::{self, ApplyReport};
+use crate::progress::ProgressStyle;
+use crate::refs;
+use crate::repo_health;
+use crate::import_window;
+use crate::save;
+use crate::save::convert::{ConversionReport, GameVersion};
+use crate::save::import::{format_categories, import, parse_categories, SaveCategory};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Instant;
 
-pub fn run(cfg: &Config) {
-    let path = PathBuf::from(cfg.game_repo_path.clone());
+/// The sandboxing code put in front of `conf.lua`.
+const SANDBOX_SCRIPT: &str = include_str!("injected.lua");
 
-    if cfg.sandboxed {
-        let script = include_str!("injected.lua");
-        let main_lua = path.join("conf.lua");
-        let mut main_lua_contents = fs::read_to_string(&main_lua)
-            .expect("Failed to read Techmino's conf.lua file");
-        main_lua_contents = format!("{}\n{}", script, main_lua_contents);
-        fs::write(main_lua, main_lua_contents)
-            .expect("Failed to write to Techmino's conf.lua file");
+/// The code keeping LÖVE's save directories inside the portable directory.
+const PORTABLE_SCRIPT: &str = include_str!("injected_portable.lua");
+
+/// The first line of the injected code, used to find leftover injections.
+pub const INJECTION_MARKER: &str = "-- Injected by Boxedmino.";
+
+/// The last line of the injected code.
+const INJECTION_END_MARKER: &str = "-- End of code injected by Boxedmino";
+
+/// Signs of code injected by versions that did not add [`INJECTION_MARKER`].
+const LEGACY_INJECTION_MARKERS: [&str; 1] = ["love.filesystem.setIdentity(\"_tmp_boxedmino\")"];
+
+/// Whether `conf.lua` contains sandboxing code that was never removed.
+pub fn is_injected(conf_lua: &str) -> bool {
+    return conf_lua.contains(INJECTION_MARKER) ||
+        LEGACY_INJECTION_MARKERS.iter().any(|marker| conf_lua.contains(marker));
+}
+
+/// The code put in front of `conf.lua`: the sandboxing code if `sandboxed` is set,
+/// and the code moving saves into `portable_save_root` in portable mode.
+pub fn injected_script(sandboxed: bool, portable_save_root: Option<&Path>) -> String {
+    let mut script = format!(
+        "{INJECTION_MARKER} If you see this, Boxedmino was interrupted; running it again will offer to remove it.\n"
+    );
+
+    // Runs first, so the sandbox identity is moved as well
+    if let Some(root) = portable_save_root {
+        let root = format!("[==[{}]==]", root.to_string_lossy());
+        script += &PORTABLE_SCRIPT.replace("BOXEDMINO_PORTABLE_SAVE_ROOT", &root);
+    }
+
+    if sandboxed {
+        script += SANDBOX_SCRIPT;
+    }
+
+    script += INJECTION_END_MARKER;
+    script += "\n";
+
+    return script;
+}
+
+/// Removes the injected code from `conf.lua`, keeping any other changes.
+/// Returns `None` if it was injected by another version of Boxedmino.
+pub fn remove_injection(conf_lua: &str) -> Option<String> {
+    if !conf_lua.starts_with(INJECTION_MARKER) {
+        return None;
+    }
+
+    let end = conf_lua.find(INJECTION_END_MARKER)? + INJECTION_END_MARKER.len();
+
+    return conf_lua[end..]
+        .strip_prefix('\n')
+        .map(|rest| rest.to_string());
+}
+
+#[test]
+fn test_remove_injection() {
+    let original = "function love.conf(t)\nend\n";
+    let root = Path::new("/media/usb/data/love/saves");
+
+    for (sandboxed, portable_save_root) in [(true, None), (true, Some(root)), (false, Some(root))] {
+        let script = injected_script(sandboxed, portable_save_root);
+        let injected = format!("{script}{original}");
+
+        assert!(is_injected(&injected));
+        assert_eq!(remove_injection(&injected).as_deref(), Some(original));
+        assert_eq!(script.contains("[==[/media/usb/data/love/saves]==]"), portable_save_root.is_some());
+    }
+
+    assert!(!is_injected(original));
+    assert_eq!(remove_injection(original), None);
+}
+
+#[test]
+fn test_injected_script_on_stock_conf() {
+    let stock = include_str!("fixtures/conf.lua");
+    let root = Path::new(r"C:\Users\user\Boxedmino\data\love\saves");
+
+    let script = injected_script(true, Some(root));
+    let injected = format!("{script}{stock}");
+
+    // Backslashes in Windows paths are kept as they are
+    assert!(!script.contains("BOXEDMINO_PORTABLE_SAVE_ROOT"));
+    assert!(script.contains(r"local save_root = [==[C:\Users\user\Boxedmino\data\love\saves]==];"));
+
+    // Kept in its own scope, so its locals can't clash with the globals of conf.lua
+    assert!(PORTABLE_SCRIPT.starts_with("do\n") && PORTABLE_SCRIPT.trim_end().ends_with("\nend"));
+
+    // The sandbox identity is only set once the redirection is in place,
+    // and both run before LÖVE reads `t.identity` from `love.conf`
+    let redirect = injected.find("love.filesystem.setIdentity = function(identity").unwrap();
+    let sandbox = injected.find(r#"love.filesystem.setIdentity("_tmp_boxedmino")"#).unwrap();
+    let conf = injected.find("function love.conf(t)").unwrap();
+    assert!(redirect < sandbox && sandbox < conf);
+
+    assert!(injected.starts_with(INJECTION_MARKER));
+    assert_eq!(remove_injection(&injected).as_deref(), Some(stock));
+}
+
+/// Checks out `version` if one is given, making sure nothing uncommitted is lost.
+/// Returns what was checked out before, to return to afterwards.
+fn switch_version(repo_path: &str, version: &str) -> Result<HeadRef, String> {
+    let original = git::head_ref(repo_path)
+        .ok_or("The game repository has no commits")?;
+
+    let changes = git::changed_files(repo_path)
+        .ok_or("Failed to run `git status` in the game repository")?;
+
+    if !changes.is_empty() {
+        return Err(format!(
+            "The game repository has uncommitted changes, which switching to '{version}' could lose:\n{}\nCommit or stash them first.",
+            changes.join("\n")
+        ));
+    }
+
+    let resolution = refs::resolve(repo_path, version, true)?;
+    info!("Running {resolution}");
+
+    // Kept on disk, so an interrupted run can still be undone on the next start
+    repo_health::remember_head(repo_path, &original);
+
+    match git::checkout(repo_path, &resolution.commit) {
+        Ok(status) if status.success() => {}
+        Ok(_) => {
+            repo_health::forget_head();
+            return Err(format!("Command `git checkout {}` failed", resolution.commit));
+        }
+        Err(e) => {
+            repo_health::forget_head();
+            return Err(format!("Failed to run `git checkout {}`: {e}", resolution.commit));
+        }
+    }
+
+    return Ok(original);
+}
+
+/// Undoes [`switch_version`].
+fn return_to(repo_path: &str, original: &HeadRef) {
+    match git::checkout(repo_path, original.revision()) {
+        Ok(status) if status.success() => repo_health::forget_head(),
+        _ => eprintln!(
+            "Failed to return the game repository to {original}. Boxedmino will offer to do it on the next start."
+        ),
+    }
+}
+
+pub fn inject(conf_lua: &Path, script: &str) -> io::Result<()> {
+    let contents = fs::read_to_string(conf_lua)?;
+    return fs::write(conf_lua, format!("{script}{contents}"));
+}
+
+/// Takes the injected code back out of `conf.lua`, leaving the rest of the repo alone.
+/// If that fails, the startup check offers to clean up later.
+fn uninject(conf_lua: &Path) {
+    let original = fs::read_to_string(conf_lua)
+        .ok()
+        .and_then(|contents| remove_injection(&contents));
+
+    let result = match original {
+        Some(original) => fs::write(conf_lua, original),
+        None => Err(io::Error::other("the injected code was changed while the game ran")),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to remove Boxedmino's code from Techmino's conf.lua file: {e}");
+    }
+}
+
+/// Why the game could not be started.
+#[derive(Debug)]
+pub enum RunError {
+    /// No `archive:<name>` is registered with that name
+    ArchiveNotRegistered(String),
+    /// A registered archive could not be extracted
+    ArchiveExtraction { name: String, message: String },
+    /// There is no valid game repository to run from
+    NoRepository,
+    /// The requested version could not be checked out
+    SwitchVersion { version: String, message: String },
+    /// Boxedmino's code could not be added to the game's `conf.lua`
+    Inject(String),
+}
+
+impl RunError {
+    /// A short summary, e.g. for the title of an error window.
+    pub fn title(&self) -> &'static str {
+        return match self {
+            RunError::ArchiveNotRegistered(_) => "Unknown archive",
+            RunError::ArchiveExtraction { .. } => "Failed to extract the archive",
+            RunError::NoRepository => "No game repository",
+            RunError::SwitchVersion { .. } => "Failed to switch versions",
+            RunError::Inject(_) => "Failed to prepare the game",
+        };
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            RunError::ArchiveNotRegistered(version) =>
+                write!(f, "The archive '{version}' is not registered"),
+            RunError::ArchiveExtraction { name, message } =>
+                write!(f, "Failed to extract archive '{name}': {message}"),
+            RunError::NoRepository =>
+                write!(f, "No game repository is set up. Choose a registered archive instead, such as `archive:<name>`."),
+            RunError::SwitchVersion { version, message } =>
+                write!(f, "Failed to switch to version '{version}': {message}"),
+            RunError::Inject(message) =>
+                write!(f, "Failed to add Boxedmino's code to Techmino's conf.lua file: {message}"),
+        };
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Where the game files come from.
+enum GameSource {
+    /// The game repository, with the branch or commit to return to, if another version was checked out
+    Repo(Option<HeadRef>),
+    /// A registered archive, extracted to the cache
+    Archive(GameArchive),
+}
+
+/// Checks out `requested_version`, if any, runs the game,
+/// then returns the repo to the branch or commit it was on.
+/// Archives such as `archive:0.17.21` are extracted and run instead of the repo.
+/// The run is recorded in the history.
+pub fn run(cfg: &Config, requested_version: Option<&str>) -> Result<RunRecord, RunError> {
+    let source = match requested_version {
+        Some(version) if archives::is_archive_version(version) => {
+            let archive = archives::find(version)
+                .ok_or(RunError::ArchiveNotRegistered(version.to_string()))?;
+            GameSource::Archive(archive)
+        }
+        _ if !git::is_repo_valid(&cfg.game_repo_path) => return Err(RunError::NoRepository),
+        Some(version) => {
+            let original = switch_version(&cfg.game_repo_path, version)
+                .map_err(|message| RunError::SwitchVersion { version: version.to_string(), message })?;
+            GameSource::Repo(Some(original))
+        }
+        None => GameSource::Repo(None),
+    };
+
+    let (path, version) = match &source {
+        GameSource::Repo(_) => (
+            PathBuf::from(cfg.game_repo_path.clone()),
+            git::describe_tag(&cfg.game_repo_path),
+        ),
+        GameSource::Archive(archive) => (
+            archives::extract(archive)
+                .map_err(|e| RunError::ArchiveExtraction { name: archive.name.clone(), message: e.to_string() })?,
+            Some(archive.name.clone()),
+        ),
+    };
+    let game_dir = path.to_string_lossy().to_string();
+    let conf_lua = path.join("conf.lua");
+
+    // Patches go on before the injection, as they may touch conf.lua
+    let patch_report = patches::apply(&game_dir, &patches::parse_list(&cfg.patches));
+    report_patches(cfg, version.as_deref(), &patch_report);
+
+    // Keep LÖVE from writing to the host machine in portable mode
+    let portable_save_root = dirs::paths::get_portable_love_save_root();
+    let injected = cfg.sandboxed || portable_save_root.is_some();
+
+    if let Some(root) = &portable_save_root {
+        info!("Portable mode: LÖVE saves are kept in {}", root.to_string_lossy());
+        // The injected code refuses to run the game without it
+        if let Err(e) = fs::create_dir_all(root) {
+            eprintln!("Failed to create the portable save directory: {e}");
+        }
+    }
+
+    if injected {
+        if let Err(e) = inject(&conf_lua, &injected_script(cfg.sandboxed, portable_save_root.as_deref())) {
+            restore_source(cfg, &source, &game_dir, &patch_report.applied);
+            return Err(RunError::Inject(e.to_string()));
+        }
     }
 
     if cfg.clear_temp_dir {
@@ -25,30 +310,46 @@ pub fn run(cfg: &Config) {
     }
 
     if cfg.import_save_on_play {
-        overwrite_temp_dir();
+        overwrite_temp_dir(cfg, version.as_deref());
     }
 
     if cfg.use_cold_clear {
-        println!("Unpacking Cold Clear version '{}'...", &cfg.cold_clear_version);
-        let res = cold_clear::unpack_cold_clear(&cfg.cold_clear_version);
+        info!("Unpacking Cold Clear version '{}'...", &cfg.cold_clear_version);
+        let res = cold_clear::unpack_cold_clear(
+            &cfg.cold_clear_version,
+            ProgressStyle::new(cfg.use_gui)
+        );
 
-        if let Err(e) = res {
-            error_window::open_safe(
-                Some("Boxedmino - CC error".into()),
-                Some("An error occured while downloading or unpacking Cold Clear.".into()),
-                Some(e.to_string())
-            );
+        match res {
+            Ok(()) => info!("Finished unpacking Cold Clear"),
+            Err(e) if cfg.use_gui => {
+                error_window::open_safe(
+                    Some("Boxedmino - CC error".into()),
+                    Some("An error occured while downloading or unpacking Cold Clear.".into()),
+                    Some(e.to_string())
+                );
+            }
+            Err(e) => {
+                eprintln!("An error occured while downloading or unpacking Cold Clear:\n{e}");
+            }
         }
-
-        println!("Finished unpacking Cold Clear");
     }
 
-    let mut command = Command::new("love");
+    let mut command = Command::new(dirs::paths::get_love_command());
     command.arg(&path);
-    
+
+
+    // Keep stdout for the run result when it is read by a script
+    if output::is_json() {
+        command.stdout(std::io::stderr());
+    }
+
+    let started_at = chrono::Local::now();
+    let timer = Instant::now();
+
     let status = command.status();
 
-    if let Err(e) = status {
+    if let Err(e) = &status {
         error_window::open_safe(
             Some("Failed to run game".to_string()),
             Some("An error was yielded from love2d.".to_string()),
@@ -56,67 +357,195 @@ pub fn run(cfg: &Config) {
         );
     }
 
-    git::restore(&cfg.game_repo_path)
-        .expect("Failed to restore repository using git");
+    let record = RunRecord {
+        started_at: started_at.to_rfc3339(),
+        duration_secs: timer.elapsed().as_secs(),
+        requested_version: requested_version.map(|version| version.to_string()),
+        commit: match &source {
+            GameSource::Repo(_) => git::resolve_commit(&cfg.game_repo_path, "HEAD"),
+            GameSource::Archive(_) => None,
+        },
+        repo_path: match &source {
+            GameSource::Repo(_) => cfg.game_repo_path.clone(),
+            GameSource::Archive(archive) => archive.path.clone(),
+        },
+        version,
+        exit_code: status.ok().and_then(|status| status.code()),
+        sandboxed: cfg.sandboxed,
+        save_path: cfg.sandboxed.then(|| {
+            dirs::paths::get_sandboxed_save_path()
+                .to_string_lossy()
+                .to_string()
+        }),
+        cold_clear_version: cfg.use_cold_clear.then(|| cfg.cold_clear_version.clone()),
+        patches: patch_report.applied.clone(),
+        failed_patches: patch_report.failed.iter().map(|(name, _)| name.clone()).collect(),
+    };
+
+    if let Err(e) = history::record(&record) {
+        eprintln!("Failed to record run in history: {e}");
+    }
+
+    if injected {
+        uninject(&conf_lua);
+    }
+
+    restore_source(cfg, &source, &game_dir, &patch_report.applied);
 
-    git::checkout(&cfg.game_repo_path, "main")
-        .expect("Failed to checkout to main branch");
+    return Ok(record);
 }
 
-fn copy_dir_all(from: &str, to: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let entries = fs::read_dir(from);
+/// Reverts the applied patches and returns the repo
+/// to the branch or commit it was on before the run.
+fn restore_source(cfg: &Config, source: &GameSource, game_dir: &str, applied: &[String]) {
+    if let Err(e) = patches::revert(game_dir, applied) {
+        eprintln!("{e}");
+    }
 
-    if let Err(e) = entries {
-        return Err(Box::new(e));
+    if let GameSource::Repo(Some(original)) = source {
+        return_to(&cfg.game_repo_path, original);
     }
+}
+
+/// Asks which save categories to import in the GUI, remembering the choice.
+/// Returns `None` if the import was skipped.
+fn choose_categories(cfg: &Config, source_path: &Path) -> Option<Vec<SaveCategory>> {
+    let categories = parse_categories(&cfg.import_categories)
+        .unwrap_or_else(|e| {
+            eprintln!("{e}\nImporting everything instead.");
+            SaveCategory::ALL.to_vec()
+        });
 
-    let entries = entries.unwrap();
+    if !cfg.use_gui {
+        return Some(categories);
+    }
 
-    for entry in entries {
-        let entry = entry.expect("Failed to read entry");
-        let path = entry.path();
-        let file_name = path.file_name().unwrap();
-        let new_path = PathBuf::from(to).join(file_name);
-        if path.is_dir() {
-            fs::create_dir_all(&new_path)?;
-            copy_dir_all(&path.to_string_lossy(), &new_path.to_string_lossy())?;
-        } else {
-            fs::copy(&path, &new_path)?;
+    let chosen = match import_window::ask_categories(&source_path.to_string_lossy(), &categories) {
+        Ok(chosen) => chosen?,
+        Err(e) => {
+            eprintln!("Failed to open import dialog: {e}");
+            return Some(categories);
         }
+    };
+
+    if chosen != categories {
+        let mut config = cfg.clone();
+        config.import_categories = format_categories(&chosen);
+        config.save_keys(&["import_categories"]);
     }
 
-    return Ok(());
+    return Some(chosen);
 }
 
-fn overwrite_temp_dir() {
-    let sandboxed_path = dirs::paths::get_sandboxed_save_path();
-    
-    if !dirs::is_dir_empty(sandboxed_path.to_str().unwrap()) {
-        dirs::clear_temp_dir();
+fn report_conversion(cfg: &Config, target: GameVersion, conversion: &ConversionReport) {
+    for step in &conversion.applied {
+        info!("Converted save for {target}: {step}");
     }
 
-    let normal_path = dirs::paths::get_normal_save_path();
-
-    if !normal_path.exists() {
-        eprintln!("Could not find normal save directory (inferred location: '{}')", normal_path.to_string_lossy());
+    if conversion.dropped.is_empty() {
         return;
     }
 
-    if !sandboxed_path.exists() {
-        fs::create_dir_all(&sandboxed_path)
-            .expect("Failed to create sandboxed save directory");
+    let message = format!(
+        "Version {target} has no place for the following, so they were not imported:\n{}",
+        conversion.dropped.join("\n")
+    );
+
+    if cfg.use_gui {
+        error_window::open_safe(
+            Some("Boxedmino - Save conversion".to_string()),
+            Some("Some save data was dropped".to_string()),
+            Some(message)
+        );
+    } else {
+        eprintln!("{message}");
+    }
+}
+
+fn report_patches(cfg: &Config, version: Option<&str>, report: &ApplyReport) {
+    for name in &report.applied {
+        info!("Applied patch '{name}'");
+    }
+
+    if report.failed.is_empty() {
+        return;
     }
 
-    if let Err(e) = copy_dir_all(
-        normal_path.to_str().unwrap(),
-        sandboxed_path.to_str().unwrap()
-    ) {
+    let version = version.unwrap_or("this version");
+
+    let failures: Vec<String> = report.failed
+        .iter()
+        .map(|(name, error)| format!("{name}: {error}"))
+        .collect();
+
+    let message = format!(
+        "The following patches do not apply to {version}, so the game runs without them:\n{}",
+        failures.join("\n")
+    );
+
+    if cfg.use_gui {
         error_window::open_safe(
-            None,
-            Some("Failed to copy save directory".to_string()),
-            Some(format!("Error: {}", e))
+            Some("Boxedmino - Patches".to_string()),
+            Some("Some patches failed to apply".to_string()),
+            Some(message)
         );
+    } else {
+        eprintln!("{message}");
     }
+}
+
+fn overwrite_temp_dir(cfg: &Config, version: Option<&str>) {
+    let sandboxed_path = dirs::paths::get_sandboxed_save_path();
 
-    println!("Overwritten temporary directory");
-}
\ No newline at end of file
+    let normal_path = match save::location::find_save_path(cfg) {
+        Ok(path) => path,
+        Err(e) if cfg.use_gui => {
+            error_window::open_safe(
+                None,
+                Some("Failed to find your save directory".to_string()),
+                Some(e)
+            );
+            return;
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    let categories = match choose_categories(cfg, &normal_path) {
+        Some(categories) => categories,
+        None => {
+            info!("Skipped importing save");
+            return;
+        }
+    };
+
+    info!(
+        "Importing {} from {}",
+        format_categories(&categories),
+        normal_path.to_string_lossy()
+    );
+
+    // The version that runs decides which format the save is converted to
+    let target = version.and_then(GameVersion::parse);
+
+    match import(&normal_path, &sandboxed_path, &categories, target) {
+        Ok(report) => {
+            for path in &report.imported {
+                info!("Imported {}", path.to_string_lossy());
+            }
+
+            if let (Some(target), Some(conversion)) = (target, report.conversion) {
+                report_conversion(cfg, target, &conversion);
+            }
+        }
+        Err(e) => {
+            error_window::open_safe(
+                None,
+                Some("Failed to copy save directory".to_string()),
+                Some(format!("Error: {}", e))
+            );
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use crate::slint_types::GitCloneWaitWindow;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::process::{Command, ExitStatus, Stdio};
-use slint::{SharedString, ComponentHandle};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use serde::{Serialize, Deserialize};
 
 pub fn tags(repo_path: &str) -> Vec<String> {
     let mut cmd = Command::new("git");
@@ -25,6 +26,267 @@ pub fn tags(repo_path: &str) -> Vec<String> {
     return versions;
 }
 
+/// A tag and the commit it points to.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TagDetails {
+    pub tag: String,
+    pub commit: String,
+    /// When the tag, or the commit of a lightweight tag, was created
+    pub date: String,
+}
+
+/// Parses the output of `git for-each-ref` in the format used by [`tag_details`].
+fn parse_tag_details(output: &str) -> Vec<TagDetails> {
+    return output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let tag = fields.next()?;
+            let object = fields.next()?;
+            // Annotated tags point to a tag object, which points to the commit
+            let peeled = fields.next().filter(|peeled| !peeled.is_empty());
+            let date = fields.next().unwrap_or("");
+
+            return Some(TagDetails {
+                tag: tag.to_string(),
+                commit: peeled.unwrap_or(object).to_string(),
+                date: date.to_string(),
+            });
+        })
+        .collect();
+}
+
+#[test]
+fn test_parse_tag_details() {
+    let output = "0.17.0\taaaa\tbbbb\t2022-01-01T00:00:00+08:00\n\
+        pre0.17.1\tcccc\t\t2022-02-02T00:00:00+08:00\n";
+
+    assert_eq!(parse_tag_details(output), vec![
+        TagDetails {
+            tag: "0.17.0".to_string(),
+            commit: "bbbb".to_string(),
+            date: "2022-01-01T00:00:00+08:00".to_string(),
+        },
+        TagDetails {
+            tag: "pre0.17.1".to_string(),
+            commit: "cccc".to_string(),
+            date: "2022-02-02T00:00:00+08:00".to_string(),
+        },
+    ]);
+}
+
+/// Returns every tag with the commit it points to, in the order of [`tags`].
+pub fn tag_details(repo_path: &str) -> Vec<TagDetails> {
+    let output = Command::new("git")
+        .args([
+            "for-each-ref",
+            "--sort=refname",
+            "--format=%(refname:short)%09%(objectname)%09%(*objectname)%09%(creatordate:iso-strict)",
+            "refs/tags",
+        ])
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run command 'git for-each-ref' to retrieve version list");
+
+    let output = String::from_utf8(output.stdout)
+        .expect("Failed to convert 'git for-each-ref' UTF-8 output");
+
+    return parse_tag_details(&output);
+}
+
+/// The name of the checked out branch, or `None` if the HEAD is detached.
+pub fn current_branch(repo_path: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let branch = String::from_utf8(output.stdout).ok()?;
+
+    return Some(branch.trim().to_string());
+}
+
+/// Lists files with uncommitted changes, as printed by `git status --porcelain`.
+/// Returns `None` if git could not be run in the repo.
+pub fn changed_files(repo_path: &str) -> Option<Vec<String>> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+
+    return Some(
+        output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect()
+    );
+}
+
+/// The URL of a remote, such as `origin`.
+pub fn remote_url(repo_path: &str, remote: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["remote", "get-url", remote])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let url = String::from_utf8(output.stdout).ok()?;
+
+    return Some(url.trim().to_string());
+}
+
+/// What the HEAD of a repo points to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadRef {
+    Branch(String),
+    /// A detached HEAD at this commit
+    Detached(String),
+}
+
+impl HeadRef {
+    /// The argument to give `git checkout` to return to this HEAD.
+    pub fn revision(&self) -> &str {
+        return match self {
+            HeadRef::Branch(branch) => branch,
+            HeadRef::Detached(commit) => commit,
+        };
+    }
+}
+
+impl fmt::Display for HeadRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            HeadRef::Branch(branch) => write!(f, "branch `{branch}`"),
+            HeadRef::Detached(commit) => write!(f, "commit {}", &commit[..commit.len().min(10)]),
+        };
+    }
+}
+
+/// The branch or commit currently checked out,
+/// or `None` if the repo has no commits.
+pub fn head_ref(repo_path: &str) -> Option<HeadRef> {
+    if let Some(branch) = current_branch(repo_path) {
+        return Some(HeadRef::Branch(branch));
+    }
+
+    return resolve_commit(repo_path, "HEAD").map(HeadRef::Detached);
+}
+
+fn branch_exists(repo_path: &str, branch: &str) -> bool {
+    return resolve_commit(repo_path, &format!("refs/heads/{branch}")).is_some();
+}
+
+/// The branch the repo's `origin` remote has checked out, such as `main` or `master`.
+///
+/// Falls back to a local `main` or `master` branch
+/// if the remote HEAD is unknown, e.g. in copied repos.
+pub fn default_branch(repo_path: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["symbolic-ref", "--quiet", "--short", "refs/remotes/origin/HEAD"])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    // `git checkout` creates the local branch from the remote one if needed
+    if output.status.success() {
+        let remote_head = String::from_utf8(output.stdout).ok()?;
+        return Some(remote_head.trim().trim_start_matches("origin/").to_string());
+    }
+
+    return ["main", "master"]
+        .into_iter()
+        .find(|branch| branch_exists(repo_path, branch))
+        .map(|branch| branch.to_string());
+}
+
+/// Local and remote-tracking branches, e.g. `main` and `origin/dev`.
+pub fn branches(repo_path: &str) -> Vec<String> {
+    let output = Command::new("git")
+        .args(["for-each-ref", "--format=%(refname)", "refs/heads", "refs/remotes"])
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run command 'git for-each-ref' to retrieve branch list");
+
+    let output = String::from_utf8(output.stdout)
+        .expect("Failed to convert 'git for-each-ref' UTF-8 output");
+
+    return output
+        .lines()
+        // `origin/HEAD` only points to another branch
+        .filter(|name| !name.is_empty() && !name.ends_with("/HEAD"))
+        .map(|name| {
+            name.strip_prefix("refs/heads/")
+                .or(name.strip_prefix("refs/remotes/"))
+                .unwrap_or(name)
+                .to_string()
+        })
+        .collect();
+}
+
+/// The full name of a ref, such as `refs/tags/0.17.0` for `0.17.0`.
+/// Returns `None` for commit hashes and expressions like `0.17.0~3`.
+pub fn full_ref_name(repo_path: &str, revision: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", "--symbolic-full-name", revision])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    let name = String::from_utf8(output.stdout).ok()?;
+    let name = name.trim();
+
+    if !output.status.success() || name.is_empty() {
+        return None;
+    }
+
+    return Some(name.to_string());
+}
+
+/// The subject and committer date of a commit.
+pub fn commit_summary(repo_path: &str, commit: &str) -> Option<(String, String)> {
+    let output = Command::new("git")
+        .args(["log", "-1", "--format=%s%n%cs", commit])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+    let mut lines = output.lines();
+
+    return Some((
+        lines.next().unwrap_or("").to_string(),
+        lines.next().unwrap_or("").to_string(),
+    ));
+}
+
 /// Returns the hash and name of every commit in the repo.
 pub fn get_commits(repo_path: &str) -> Vec<(String, String)> {
     let mut cmd = Command::new("git");
@@ -49,192 +311,146 @@ pub fn get_commits(repo_path: &str) -> Vec<(String, String)> {
     return commits;
 }
 
-pub fn restore(repo_path: &str) -> io::Result<ExitStatus> {
-    return Command::new("git")
-        .args(["restore", "."])
+/// Reads a file as it is at the given revision, without checking it out.
+pub fn show_file(repo_path: &str, revision: &str, file_path: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["show", &format!("{revision}:{file_path}")])
         .current_dir(repo_path)
-        .status();
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    return String::from_utf8(output.stdout).ok();
 }
 
-pub fn checkout(repo_path: &str, version: &str) -> io::Result<ExitStatus> {
-    return Command::new("git")
-        .args(["checkout", version])
+/// The full hash of the commit a tag, branch or commit points to.
+pub fn resolve_commit(repo_path: &str, revision: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", &format!("{revision}^{{commit}}")])
         .current_dir(repo_path)
-        .status();
-}
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
 
+    if !output.status.success() {
+        return None;
+    }
 
-pub fn clone(path: SharedString) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut terminal_opened = true;
-    let command =
-        get_terminal_clone_command(path.to_string().clone());
-    
-    let mut command = match command {
-        Some(c) => c,
-        None => {
-            terminal_opened = false;
-            get_fallback_clone_command(path.to_string().clone())
-        }
-    };
+    let commit = String::from_utf8(output.stdout).ok()?;
 
-    if terminal_opened {
-        command.status()?;
-    } else {
-        let window = GitCloneWaitWindow::new();
+    return Some(commit.trim().to_string());
+}
 
-        if let Err(e) = window {
-            return Err(Box::new(e));
-        }
+/// The closest tag reachable from the checked out commit.
+pub fn describe_tag(repo_path: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["describe", "--tags", "--abbrev=0"])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
 
-        let window = window.unwrap();
+    if !output.status.success() {
+        return None;
+    }
 
-        let weak = window.as_weak();
+    let tag = String::from_utf8(output.stdout).ok()?;
 
-        window.on_dismiss(move || {
-            weak.unwrap().window().hide().unwrap();
-        });
+    return Some(tag.trim().to_string());
+}
 
-        let child = command.spawn()
-            .expect("Failed to run git clone command");
+pub fn checkout(repo_path: &str, version: &str) -> io::Result<ExitStatus> {
+    return Command::new("git")
+        .args(["checkout", version])
+        .current_dir(repo_path)
+        .status();
+}
 
-        // when the command is done, close the window
-        let weak = window.as_weak();
-        std::thread::spawn(move || {
-            child.wait_with_output().expect("Failed to wait for git clone command");
-            weak.unwrap().set_finished(true);
-            weak.unwrap().window().hide().unwrap();
-        });
+/// The latest commit reachable from `revision` committed before `date`,
+/// which may be anything git understands as a date, such as `2022-03-01` or `2 weeks ago`.
+pub fn last_commit_before(repo_path: &str, revision: &str, date: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-list", "-1", &format!("--before={date}"), revision, "--"])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
 
-        window.run()?;
+    if !output.status.success() {
+        return None;
     }
 
-    return Ok(());
+    let commit = String::from_utf8(output.stdout).ok()?;
+    let commit = commit.trim();
+
+    return (!commit.is_empty()).then(|| commit.to_string());
 }
 
+/// Runs git with the given arguments in the repo, printing its output.
+pub fn run(repo_path: &str, args: &[&str]) -> io::Result<ExitStatus> {
+    return Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .status();
+}
 
-const REPO_LINK: &str = "https://github.com/26F-Studio/Techmino.git";
+/// Runs git with the given arguments in the repo, capturing its output instead of printing it.
+/// Fails if git can't be run or exits with an error, which is then taken from its stderr.
+pub fn run_checked(repo_path: &str, args: &[&str]) -> Result<Output, String> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(repo_path);
 
-#[cfg(target_os = "windows")]
-fn get_terminal_clone_command(path: String) -> Option<Command> {
-    let mut cmd = Command::new("cmd");
-    cmd.args([
-        "/c",
-        "git",
-        "clone",
-        REPO_LINK,
-        path.as_str()
-    ]);
-    return Some(cmd);
+    return check_output(&mut command, args);
 }
 
-#[cfg(target_os = "macos")]
-fn get_terminal_clone_command(path: String) -> Option<Command> {
-    let script_dir = "/tmp/_boxedmino_clone.sh";
+/// Runs git like [`run_checked`], with `index_file` in place of the repo's index.
+pub fn run_checked_with_index(repo_path: &str, args: &[&str], index_file: &Path) -> Result<Output, String> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(repo_path).env("GIT_INDEX_FILE", index_file);
 
-    let script_result = fs::write(
-        script_dir,
-        format!(
-            "git clone {} {}; rm {}",
-            REPO_LINK,
-            path,
-            script_dir
-        ),
-    );
-
-    if let Err(_) = script_result {
-        return None;
-    }
+    return check_output(&mut command, args);
+}
 
-    let chmod_result =
-        Command::new("chmod")
-            .args([
-                "+x",
-                script_dir
-            ])
-            .status();
-    
-    if let Err(_) = chmod_result {
-        return None;
-    }
+fn check_output(command: &mut Command, args: &[&str]) -> Result<Output, String> {
+    let output = command.output()
+        .map_err(|e| format!("Failed to run `git {}`: {e}", args.join(" ")))?;
 
-    let mut command = Command::new("open");
-    command.args([
-        "-a",
-        "/Applications/Utilities/Terminal.app",
-        script_dir
-    ]);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
 
-    return Some(command);
-}
-
-#[cfg(target_os = "linux")]
-fn get_terminal_clone_command(path: String) -> Option<Command> {
-    let popular_term_emus = [
-        "x-terminal-emulator",
-        "xterm",
-        "gnome-terminal",
-        "konsole",
-        "alacritty",
-        "kitty",
-        "tilix",
-        "terminator",
-        "urxvt",
-        "rxvt",
-        "lxterminal",
-        "xfce4-terminal",
-        "guake",
-        "yakuake",
-        "st",
-        "Eterm",
-        "hyper",
-        "qterminal",
-        "tilda",
-        "wezterm",
-        "termux",
-        "foot",
-        "sakura",
-        "mlterm",
-        "cool-retro-term",
-        "extraterm",
-        "termite"
-    ];
-
-    let terminal = popular_term_emus.iter().find(|term| {
-        Command::new("which")
-            .stdout(Stdio::null())
-            .arg(term)
-            .status()
-            .is_ok()
-    });
-
-    if let Some(terminal) = terminal {
-        let mut cmd = Command::new(terminal);
-
-        cmd.args([
-            "-e",
-            "git",
-            "clone",
-            REPO_LINK,
-            path.as_str()
-        ]);
-
-        return Some(cmd);
+        return Err(match stderr.is_empty() {
+            true => format!("`git {}` exited with {}", args.join(" "), output.status),
+            false => stderr,
+        });
     }
 
-    return None;
+    return Ok(output);
 }
 
-fn get_fallback_clone_command(path: String) -> Command {
-    let mut cmd = Command::new("git");
-    cmd.args([
-        "clone",
-        REPO_LINK,
-        path.as_str()
-    ]);
+/// The path of the `.git` directory, which is elsewhere for worktrees and submodules.
+pub fn git_dir(repo_path: &str) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--absolute-git-dir"])
+        .current_dir(repo_path)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let path = String::from_utf8(output.stdout).ok()?;
 
-    return cmd;
+    return Some(PathBuf::from(path.trim()));
 }
 
+pub const REPO_LINK: &str = "https://github.com/26F-Studio/Techmino.git";
+
 pub fn is_repo_valid(path: &str) -> bool {
     let path = std::path::Path::new(path);
     if !path.is_dir() {
@@ -243,7 +459,7 @@ pub fn is_repo_valid(path: &str) -> bool {
 
     let files = fs::read_dir(path);
 
-    if let Err(_) = files {
+    if files.is_err() {
         return false;
     }
 
@@ -265,4 +481,75 @@ pub fn is_repo_valid(path: &str) -> bool {
     }
 
     return has_git && has_main_lua;
-}
\ No newline at end of file
+}
+
+/// A git repo in a temporary directory for tests, removed when dropped,
+/// even if the test fails.
+#[cfg(test)]
+pub struct ScratchRepo {
+    /// The temporary directory, which tests may also use for other files
+    pub root: PathBuf,
+    /// The repo itself, in `root/repo`, on the `main` branch
+    pub path: PathBuf,
+}
+
+#[cfg(test)]
+impl ScratchRepo {
+    /// Creates an empty repo in `boxedmino_test_<name>_<pid>` in the temp directory.
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir()
+            .join(format!("boxedmino_test_{name}_{}", std::process::id()));
+        let path = root.join("repo");
+
+        // Left over from a run that was killed
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&path).unwrap();
+
+        let repo = Self { root, path };
+        repo.git(&["init", "--quiet", "--initial-branch", "main"]);
+
+        return repo;
+    }
+
+    pub fn path_str(&self) -> String {
+        return self.path.to_string_lossy().to_string();
+    }
+
+    /// Runs git in the repo as a test user, failing the test if git fails.
+    pub fn git(&self, args: &[&str]) {
+        self.run(None, args);
+    }
+
+    /// Runs git like [`ScratchRepo::git`], with commits dated `date`.
+    pub fn git_at(&self, date: &str, args: &[&str]) {
+        self.run(Some(date), args);
+    }
+
+    fn run(&self, date: Option<&str>, args: &[&str]) {
+        let mut command = Command::new("git");
+        command
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&self.path);
+
+        if let Some(date) = date {
+            command
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date);
+        }
+
+        let output = command.output().unwrap();
+        assert!(
+            output.status.success(),
+            "git {args:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[cfg(test)]
+impl Drop for ScratchRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use serde::{Serialize, Deserialize};
+use crate::dirs::paths;
+use crate::info;
+use crate::output;
+
+/// What happened when the game was launched.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RunRecord {
+    /// When the game was started, in RFC 3339 format
+    pub started_at: String,
+    pub duration_secs: u64,
+    /// The revision that was asked for, or `None` for the one already checked out
+    pub requested_version: Option<String>,
+    /// The closest tag to the commit that ran, or the name of the archive that ran
+    pub version: Option<String>,
+    /// `None` for archives
+    pub commit: Option<String>,
+    /// The game repository, or the archive the game ran from
+    pub repo_path: String,
+    /// The exit code of LÖVE, or `None` if it failed to start or was killed
+    pub exit_code: Option<i32>,
+    pub sandboxed: bool,
+    pub save_path: Option<String>,
+    /// The Cold Clear version, if it was enabled
+    pub cold_clear_version: Option<String>,
+    /// The patches that were applied on top of the commit
+    #[serde(default)]
+    pub patches: Vec<String>,
+    /// The patches that were selected but did not apply
+    #[serde(default)]
+    pub failed_patches: Vec<String>,
+}
+
+impl RunRecord {
+    pub fn print(&self) {
+        if output::is_json() {
+            output::print_json(self);
+            return;
+        }
+
+        let exit_code = self.exit_code
+            .map(|code| code.to_string())
+            .unwrap_or("none".to_string());
+
+        println!(
+            "{}\t{}\t{}\texit code {exit_code}\t{}s",
+            self.started_at,
+            self.version.as_deref().unwrap_or("unknown version"),
+            // Archives have no commit, so show where they are instead
+            self.commit.as_deref().unwrap_or(&self.repo_path),
+            self.duration_secs
+        );
+    }
+}
+
+/// Appends a run to the history file.
+pub fn record(run: &RunRecord) -> io::Result<()> {
+    let path = paths::get_history_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let line = serde_json::to_string(run)
+        .expect("Failed to serialize run record");
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    return writeln!(file, "{line}");
+}
+
+/// Parses the history file, skipping lines that cannot be read.
+pub fn parse(contents: &str) -> Vec<RunRecord> {
+    return contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+}
+
+/// Loads every recorded run, oldest first.
+pub fn load() -> Vec<RunRecord> {
+    return match fs::read_to_string(paths::get_history_path()) {
+        Ok(contents) => parse(&contents),
+        Err(_) => vec![],
+    };
+}
+
+#[test]
+fn test_parse_history() {
+    let run = RunRecord {
+        started_at: "2024-05-01T12:00:00+00:00".to_string(),
+        duration_secs: 42,
+        requested_version: Some("0.17.0".to_string()),
+        version: Some("0.17.0".to_string()),
+        commit: Some("0123456789abcdef".to_string()),
+        repo_path: "/games/Techmino".to_string(),
+        exit_code: Some(0),
+        sandboxed: true,
+        save_path: None,
+        cold_clear_version: None,
+        patches: vec!["fix-crash".to_string()],
+        failed_patches: vec![],
+    };
+
+    let contents = format!("{}\nnot json\n\n", serde_json::to_string(&run).unwrap());
+
+    assert_eq!(parse(&contents), vec![run]);
+}
+
+/// Runs `boxedmino history`, returning the exit code.
+pub fn run(limit: Option<usize>) -> i32 {
+    let runs = load();
+    let skip = limit
+        .map(|limit| runs.len().saturating_sub(limit))
+        .unwrap_or(0);
+    let runs = &runs[skip..];
+
+    if output::is_json() {
+        output::print_json(&runs);
+        return 0;
+    }
+
+    if runs.is_empty() {
+        info!("The game has not been run yet.");
+    }
+
+    for run in runs {
+        run.print();
+    }
+
+    return 0;
+}
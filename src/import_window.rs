@@ -0,0 +1,53 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::save::import::SaveCategory;
+use crate::slint_types::{ImportCategory, ImportSaveDialog};
+use slint::{ComponentHandle, Model, ModelRc, VecModel};
+
+/// Asks which parts of the save at `source_path` to import,
+/// with `selected` checked to begin with.
+///
+/// Returns `None` if the user chose not to import anything.
+pub fn ask_categories(
+    source_path: &str,
+    selected: &[SaveCategory]
+) -> Result<Option<Vec<SaveCategory>>, slint::PlatformError> {
+    let dialog = ImportSaveDialog::new()?;
+    let result: Rc<RefCell<Option<Vec<SaveCategory>>>> = Rc::new(RefCell::new(None));
+
+    let categories: Vec<ImportCategory> = SaveCategory::ALL
+        .iter()
+        .map(|category| ImportCategory {
+            name: category.name().into(),
+            description: category.description().into(),
+            checked: selected.contains(category),
+        })
+        .collect();
+
+    dialog.set_source_path(source_path.into());
+    dialog.set_categories(ModelRc::new(VecModel::from(categories)));
+
+    let weak = dialog.as_weak();
+    let result_clone = result.clone();
+    dialog.on_confirm(move || {
+        let dialog = weak.unwrap();
+
+        let chosen: Vec<SaveCategory> = dialog.get_categories()
+            .iter()
+            .filter(|category| category.checked)
+            .filter_map(|category| SaveCategory::parse(&category.name))
+            .collect();
+
+        *result_clone.borrow_mut() = Some(chosen);
+        dialog.window().hide().unwrap();
+    });
+
+    let weak = dialog.as_weak();
+    dialog.on_skip(move || {
+        weak.unwrap().window().hide().unwrap();
+    });
+
+    dialog.run()?;
+
+    return Ok(result.take());
+}
@@ -1,5 +1,6 @@
 love.filesystem.setIdentity("_tmp_boxedmino");
 print("Running under sandboxed environment");
+print("Saving to " .. love.filesystem.getSaveDirectory());
 do
     local identity = "love";
     love.filesystem.setIdentity = function(new_identity)
@@ -10,4 +11,3 @@ do
         return identity;
     end
 end
-
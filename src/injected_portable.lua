@@ -0,0 +1,37 @@
+do
+    -- LÖVE has no way to choose where saves go, so PhysFS is pointed elsewhere
+    -- every time the identity changes, before anything is written
+    local save_root = BOXEDMINO_PORTABLE_SAVE_ROOT;
+    local ffi = require("ffi");
+    ffi.cdef[[
+        int PHYSFS_setWriteDir(const char *newDir);
+        int PHYSFS_mkdir(const char *dirName);
+        int PHYSFS_mount(const char *newDir, const char *mountPoint, int appendToPath);
+        int PHYSFS_unmount(const char *oldDir);
+    ]];
+    local physfs = ffi.os == "Windows" and ffi.load("love") or ffi.C;
+
+    local set_identity = love.filesystem.setIdentity;
+    local get_save_directory = love.filesystem.getSaveDirectory;
+    local save_directory;
+
+    love.filesystem.setIdentity = function(identity, append_to_path)
+        set_identity(identity, append_to_path);
+        physfs.PHYSFS_unmount(get_save_directory());
+        if save_directory then
+            physfs.PHYSFS_unmount(save_directory);
+        end
+
+        save_directory = save_root .. "/" .. identity;
+        if physfs.PHYSFS_setWriteDir(save_root) == 0
+            or physfs.PHYSFS_mkdir(identity) == 0
+            or physfs.PHYSFS_setWriteDir(save_directory) == 0
+        then
+            error("Boxedmino could not keep saves in the portable directory " .. save_directory);
+        end
+        physfs.PHYSFS_mount(save_directory, nil, append_to_path and 1 or 0);
+    end
+    love.filesystem.getSaveDirectory = function()
+        return save_directory or get_save_directory();
+    end
+end
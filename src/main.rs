@@ -16,18 +16,40 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use clap::{Parser, Subcommand};
 use once_cell::sync::OnceCell;
+use serde::Serialize;
+use output::OutputFormat;
 
+mod archives;
+mod bundle;
 mod cold_clear;
 mod dirs;
 mod conf;
+mod doctor;
+mod export;
 mod game;
 mod git;
+mod history;
 mod main_window;
+mod output;
+mod patches;
+mod progress;
+mod recipe;
+mod refs;
+mod repo_health;
+mod save;
+mod save_viewer;
+mod status;
+mod transfer;
 mod error_window;
+mod import_window;
+mod repair_window;
 mod setup;
+// Slint's cosmic style exports a `TextStyle` struct that is never constructed
+#[allow(dead_code)]
 mod slint_types;
 
 #[derive(Parser)]
@@ -44,6 +66,19 @@ For more information on running the game, use `boxedmino help run`.")]
 struct Cli { 
     #[command(subcommand)]
     command: Option<CliInstruction>,
+
+    /// Don't show progress bars or status messages in the terminal
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// How to print results. Status messages always go to stderr.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Keep all Boxedmino files in this directory instead of the usual locations.
+    /// Can also be set with the BOXEDMINO_HOME environment variable, except in portable mode.
+    #[arg(long, global = true, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Clone, Debug)]
@@ -55,9 +90,47 @@ pub enum CliInstruction {
         repo_path: Option<String>,
     },
 
+    #[clap(about = "Shows which commit a version, branch or other revision points to")]
+    Resolve {
+        /// A tag, branch such as `origin/dev`, pull request such as `pull/42/head`,
+        /// commit, or expression such as `0.17.0~3`
+        reference: String,
+
+        /// Fetch pull requests from the `origin` remote
+        #[arg(long)]
+        fetch: bool,
+    },
+
+    #[clap(about = "Lists Cold Clear versions that can be selected")]
+    ListCcVersions {
+        /// Fetch the list of releases before listing them
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    #[clap(about = "Shows the game repository, directories and settings in use")]
+    Status,
+
+    #[clap(about = "Checks that everything Boxedmino needs works, and suggests fixes")]
+    Doctor {
+        /// Skip the network checks
+        #[arg(long)]
+        offline: bool,
+    },
+
+    #[clap(about = "Lists previous runs of the game")]
+    History {
+        /// Only show this many of the latest runs
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+
     #[clap(about = "Runs the game")]
     Run {
-        /// The version of the game to run. Accepts git tags and commit hashes.
+        /// The version of the game to run. Accepts tags, branches such as `origin/dev`,
+        /// pull requests such as `pull/42/head`, commit hashes,
+        /// expressions such as `0.17.0~3`, the last commit before a date such as `main@{2022-03-01}`,
+        /// and registered archives such as `archive:0.17.21`.
         #[arg(short, long)]
         version: Option<String>,
 
@@ -78,21 +151,302 @@ pub enum CliInstruction {
         ///      If on, Techmino's AI (ColdClear) will be enabled.
         #[arg(short, long, verbatim_doc_comment)]
         flags: Option<String>,
+
+        /// Import only these parts of your main save, separated by commas.
+        /// Implies the `I` flag.
+        /// Categories: settings, keymap, unlocks, records, replays, or all.
+        #[arg(long, value_name = "CATEGORIES")]
+        import: Option<String>,
+
+        /// Apply these saved patches on top of the version, separated by commas.
+        /// Pass an empty string to apply none.
+        #[arg(long, value_name = "NAMES")]
+        patches: Option<String>,
+
+        /// Launch as described by a recipe file, or by the name of a saved recipe.
+        /// Other options override the recipe.
+        #[arg(long, value_name = "FILE")]
+        recipe: Option<String>,
+    },
+
+    #[clap(about = "Packs a version of the game into a .love file that runs without Boxedmino")]
+    Export {
+        /// The version to export, in any form `boxedmino run` accepts
+        #[arg(short, long)]
+        version: String,
+
+        /// Add the sandboxing code, so the game saves to a separate directory
+        #[arg(long)]
+        sandboxed: bool,
+
+        /// Add this Cold Clear version to the archive
+        #[arg(long, value_name = "VERSION")]
+        with_cc: Option<String>,
+
+        /// Where to write the .love file
+        #[arg(short, long, value_name = "FILE")]
+        output: String,
+    },
+
+    #[clap(about = "Reads or changes the saved settings")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    #[clap(about = "Inspects and compares Techmino save files")]
+    Save {
+        #[command(subcommand)]
+        action: SaveAction,
+    },
+
+    #[clap(about = "Manages custom AI libraries used in place of Cold Clear releases")]
+    AiLibrary {
+        #[command(subcommand)]
+        action: AiLibraryAction,
+    },
+
+    #[clap(about = "Manages .love files and zip archives of the game that run without a Git repository")]
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
+
+    #[clap(about = "Downloads the game repository, or moves it to machines without internet access")]
+    Repo {
+        #[command(subcommand)]
+        action: RepoAction,
+    },
+
+    #[clap(about = "Manages patches that can be applied on top of any version")]
+    Patch {
+        #[command(subcommand)]
+        action: PatchAction,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum SaveAction {
+    #[clap(about = "Prints the contents of a save file")]
+    Show {
+        file: String,
+    },
+
+    #[clap(about = "Compares two save files, or two save directories")]
+    Diff {
+        old: String,
+        new: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ConfigAction {
+    #[clap(about = "Prints the value of a setting")]
+    Get {
+        key: String,
+    },
+
+    #[clap(about = "Changes the value of a setting")]
+    Set {
+        key: String,
+        /// The new value. Booleans accept true/false, on/off, yes/no and 1/0.
+        value: String,
+    },
+
+    #[clap(about = "Prints every setting and its value")]
+    List {
+        /// Also show which layer each value came from
+        #[arg(long)]
+        origin: bool,
+    },
+
+    #[clap(about = "Resets a setting, or all settings, to the default value")]
+    Reset {
+        /// The setting to reset. If omitted, all settings are reset.
+        key: Option<String>,
+    },
+
+    #[clap(about = "Prints the path to the config file")]
+    Path,
+
+    #[clap(about = "Opens the config file in $VISUAL or $EDITOR")]
+    Edit,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum AiLibraryAction {
+    #[clap(about = "Registers a directory or zip archive as an AI library")]
+    Add {
+        /// Path to a directory containing the library files, or a zip archive of them
+        path: String,
+
+        /// Name of the library, e.g. `my_cold_clear`
+        #[arg(short, long)]
+        name: String,
+
+        /// Version of the library, e.g. `1.0.0`
+        #[arg(short, long)]
+        version: String,
+
+        /// The OS the library was built for, e.g. `linux`. Unchecked if omitted.
+        #[arg(long)]
+        os: Option<String>,
+
+        /// The CPU architecture the library was built for, e.g. `x86_64`. Unchecked if omitted.
+        #[arg(long)]
+        arch: Option<String>,
+    },
+
+    #[clap(about = "Lists registered AI libraries")]
+    List,
+
+    #[clap(about = "Unregisters an AI library. Its files are left untouched.")]
+    Remove {
+        /// ID of the library, as shown by `boxedmino ai-library list`
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ArchiveAction {
+    #[clap(about = "Registers a .love file or zip archive of the game")]
+    Add {
+        /// Path to the archive
+        path: String,
+
+        /// Name of the archive, e.g. `0.17.21`. Defaults to the file name.
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+
+    #[clap(about = "Lists registered archives")]
+    List,
+
+    #[clap(about = "Unregisters an archive and deletes its extracted files. The archive itself is left untouched.")]
+    Remove {
+        /// ID of the archive, as shown by `boxedmino archive list`
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum RepoAction {
+    #[clap(about = "Downloads the game repository and makes it the one Boxedmino uses")]
+    Clone {
+        /// Where to put the repository: an empty directory, or one that does not exist yet
+        path: String,
+
+        /// How much of the repository to download
+        #[arg(long, value_enum, default_value_t)]
+        mode: transfer::CloneMode,
+    },
+
+    #[clap(about = "Fetches new versions from GitHub into the game repository")]
+    Fetch,
+
+    #[clap(about = "Writes the game repository and downloaded Cold Clear versions to a bundle file")]
+    ExportBundle {
+        /// Where to write the bundle
+        #[arg(short, long, value_name = "FILE")]
+        output: String,
+    },
+
+    #[clap(about = "Adds the new tags and Cold Clear versions in a bundle file")]
+    ImportBundle {
+        /// A bundle made with `boxedmino repo export-bundle`
+        file: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum PatchAction {
+    #[clap(about = "Saves the uncommitted changes in the game repository as a patch")]
+    Capture {
+        /// Name of the patch, e.g. `fix-love-11-crash`
+        name: String,
+    },
+
+    #[clap(about = "Lists saved patches")]
+    List,
+
+    #[clap(about = "Deletes a saved patch")]
+    Remove {
+        name: String,
+    },
+
+    #[clap(about = "Checks which patches apply to a version of the game")]
+    Check {
+        /// The version to check against, in any form `boxedmino run` accepts
+        #[arg(short, long)]
+        version: String,
+
+        /// The patches to check. If omitted, all saved patches are checked.
+        names: Vec<String>,
     },
 }
 
 pub static INSTRUCTION: OnceCell<Option<CliInstruction>> = OnceCell::new();
+static QUIET: OnceCell<bool> = OnceCell::new();
+
+static DATA_DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
+
+/// Whether the `--quiet` flag was passed.
+pub fn is_quiet() -> bool {
+    return *QUIET.get().unwrap_or(&false);
+}
+
+/// The directory passed with `--data-dir`, if any.
+pub fn get_data_dir_arg() -> Option<&'static PathBuf> {
+    return DATA_DIR.get()?.as_ref();
+}
 
 fn main() -> Result<(), slint::PlatformError> {
-    print_intro();
-    
-    INSTRUCTION.set(Cli::parse().command).unwrap();
+    let cli = Cli::parse();
+    QUIET.set(cli.quiet).unwrap();
+    output::set_format(cli.format);
+    DATA_DIR.set(cli.data_dir).unwrap();
+    INSTRUCTION.set(cli.command).unwrap();
+
+    if !is_quiet() {
+        print_intro();
+    }
+
+    dirs::migrate_legacy_data();
+
+    match INSTRUCTION.get().unwrap_or(&None) {
+        Some(CliInstruction::Config { action }) => {
+            std::process::exit(conf::cli::run(action));
+        }
+        Some(CliInstruction::AiLibrary { action }) => {
+            std::process::exit(run_ai_library_command(action));
+        }
+        Some(CliInstruction::Save { action }) => {
+            std::process::exit(save::cli::run(action));
+        }
+        Some(CliInstruction::Archive { action }) => {
+            std::process::exit(archives::run(action));
+        }
+        Some(CliInstruction::ListCcVersions { refresh }) => {
+            std::process::exit(cold_clear::cli::list_versions(*refresh));
+        }
+        Some(CliInstruction::History { limit }) => {
+            std::process::exit(history::run(*limit));
+        }
+        Some(CliInstruction::Doctor { offline }) => {
+            std::process::exit(doctor::run(&conf::Config::load(), *offline));
+        }
+        Some(CliInstruction::Status) => {
+            std::process::exit(status::run(&conf::Config::load()));
+        }
+        _ => {}
+    }
 
     if let Err(missing_dependencies) = check_dependencies() {
         let mut message = "The following dependencies are missing:".to_string();
         for dependency in missing_dependencies {
             message.push_str(&format!("\n- {}", dependency));
         }
+        message.push_str("\nRun `boxedmino doctor` for details.\n");
         message.push_str("You can find download links in the console output, if you opened this program from the terminal.");
         error_window::open_safe(
             Some("Boxedmino - Startup Error".to_string()),
@@ -104,45 +458,117 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let mut config = conf::Config::load();
 
-    if let Some(i) = INSTRUCTION.get().unwrap_or(&None) {
-        if let CliInstruction::ListVersions { .. } = i {
-            eprintln!("Available versions: ");
-            println!("{}", git::tags(&config.game_repo_path).join("\n"));
-            std::process::exit(0);
-        }
+    if let Some(CliInstruction::ListVersions { .. }) = INSTRUCTION.get().unwrap_or(&None) {
+        list_versions(&config.game_repo_path);
+        std::process::exit(0);
+    }
+
+    if let Some(CliInstruction::Resolve { reference, fetch }) = INSTRUCTION.get().unwrap_or(&None) {
+        std::process::exit(refs::run(&config.game_repo_path, reference, *fetch));
+    }
+
+    if let Some(CliInstruction::Export { version, sandboxed, with_cc, output }) = INSTRUCTION.get().unwrap_or(&None) {
+        let options = export::ExportOptions {
+            version: version.clone(),
+            sandboxed: *sandboxed,
+            cold_clear_version: with_cc.clone(),
+        };
+        let style = progress::ProgressStyle::new(false);
+
+        std::process::exit(export::run(&config.game_repo_path, &options, output, style));
+    }
+
+    if let Some(CliInstruction::Repo { action }) = INSTRUCTION.get().unwrap_or(&None) {
+        let style = progress::ProgressStyle::new(false);
+        let repo_path = &config.game_repo_path;
+
+        std::process::exit(match action {
+            RepoAction::Clone { path, mode } => transfer::run_clone(path, *mode, style),
+            RepoAction::Fetch => transfer::run_fetch(repo_path, style),
+            RepoAction::ExportBundle { output } => bundle::run_export(repo_path, output),
+            RepoAction::ImportBundle { file } => bundle::run_import(repo_path, file),
+        });
+    }
+
+    if let Some(CliInstruction::Patch { action }) = INSTRUCTION.get().unwrap_or(&None) {
+        std::process::exit(patches::run(action, &config.game_repo_path));
     }
 
     let no_repo = !config.repo_initialized ||
         !git::is_repo_valid(&config.game_repo_path);
 
-    if no_repo {
+    // Registered archives can be run without a repository
+    if no_repo && archives::load_archives().is_empty() {
         setup::run_setup()?;
         config = conf::Config::load();
     }
 
+    if git::is_repo_valid(&config.game_repo_path) {
+        repo_health::check_and_repair(&config);
+    }
+
     if config.use_gui {
         main_window::open(&config)?;
     } else {
-        let version = get_version_from_cli();
-
-        if let Some(v) = version {
-            git::checkout(
-                &config.game_repo_path,
-                v
-            ).expect(
-                format!("Failed to run `git checkout {v}`").as_str()
-            ).success()
-            .then(|| {
-                panic!("Command `git checkout {v}` failed");
-            });
+        let recipe = recipe::get_cli_recipe();
+
+        let version = get_version_from_cli()
+            .or(recipe.map(|recipe| recipe.revision()));
+
+        if let (Some(recipe), None) = (recipe, get_version_from_cli()) {
+            recipe.check_version_moved(&config.game_repo_path);
         }
 
-        game::run(&config);
+        let record = match game::run(&config, version) {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        record.print();
+
+        // Scripts can tell whether the game crashed from our exit code
+        match record.exit_code {
+            Some(0) => {}
+            Some(code) => std::process::exit(code),
+            None => std::process::exit(1),
+        }
     }
 
     return Ok(());
 }
 
+#[derive(Serialize)]
+struct VersionList {
+    repo_path: String,
+    versions: Vec<git::TagDetails>,
+    archives: Vec<archives::GameArchive>,
+}
+
+fn list_versions(repo_path: &str) {
+    let repo_valid = git::is_repo_valid(repo_path);
+
+    if output::is_json() {
+        output::print_json(&VersionList {
+            repo_path: repo_path.to_string(),
+            versions: if repo_valid { git::tag_details(repo_path) } else { vec![] },
+            archives: archives::load_archives(),
+        });
+        return;
+    }
+
+    info!("Available versions: ");
+
+    if repo_valid {
+        println!("{}", git::tags(repo_path).join("\n"));
+    }
+
+    for archive in archives::load_archives() {
+        println!("{}", archive.id());
+    }
+}
+
 fn print_intro() {
     let version = env!("CARGO_PKG_VERSION");
     eprintln!("╔═════╗");
@@ -161,24 +587,24 @@ fn check_dependencies() -> Result<(), Vec<String>> {
         .status()
         .is_err()
     {
-        eprintln!("{}\n{}\n{}",
-            "It seems that Git is not installed on your system.",
-            "Install Git from: https://git-scm.com/downloads",
-            "Make sure to add Git to your PATH, and that running `git --version` in the terminal works."
+        eprintln!(
+            "It seems that Git is not installed on your system.\n\
+            Install Git from: https://git-scm.com/downloads\n\
+            Make sure to add Git to your PATH, and that running `git --version` in the terminal works."
         );
         missing_dependencies.push("git".to_string());
     }
 
-    if Command::new("love")
+    if Command::new(dirs::paths::get_love_command())
         .arg("--version")
         .stdout(Stdio::null())
         .status()
         .is_err()
     {
-        eprintln!("{}\n{}\n{}",
-            "It seems that LÖVE is not installed on your system.",
-            "Install LÖVE from: https://love2d.org/",
-            "Make sure to add LÖVE to your PATH, and that running `love --version` in the terminal works."
+        eprintln!(
+            "It seems that LÖVE is not installed on your system.\n\
+            Install LÖVE from: https://love2d.org/\n\
+            Make sure to add LÖVE to your PATH, and that running `love --version` in the terminal works."
         );
         missing_dependencies.push("love".to_string());
     }
@@ -190,6 +616,69 @@ fn check_dependencies() -> Result<(), Vec<String>> {
     return Ok(());
 }
 
+fn run_ai_library_command(action: &AiLibraryAction) -> i32 {
+    match action {
+        AiLibraryAction::Add { path, name, version, os, arch } => {
+            let library = cold_clear::custom::CustomLibrary {
+                name: name.clone(),
+                version: version.clone(),
+                path: path.clone(),
+                os: os.clone(),
+                arch: arch.clone(),
+            };
+
+            match cold_clear::custom::register(library) {
+                Ok(library) if output::is_json() => {
+                    output::print_json(&library);
+                    return 0;
+                }
+                Ok(library) => {
+                    println!("Registered AI library '{}'", library.id());
+                    info!("Select it as the Cold Clear version to use it.");
+                    return 0;
+                }
+                Err(e) => {
+                    eprintln!("Failed to register AI library: {e}");
+                    return 1;
+                }
+            }
+        }
+        AiLibraryAction::List => {
+            if output::is_json() {
+                output::print_json(&cold_clear::custom::load_libraries());
+                return 0;
+            }
+
+            for library in cold_clear::custom::load_libraries() {
+                let os = library.os.as_deref().unwrap_or("any OS");
+                let arch = library.arch.as_deref().unwrap_or("any architecture");
+                println!("{}\t{}\t({os}, {arch})", library.id(), library.path);
+            }
+            return 0;
+        }
+        AiLibraryAction::Remove { id } => {
+            match cold_clear::custom::unregister(id) {
+                Ok(true) if output::is_json() => {
+                    output::print_json(&serde_json::json!({ "removed": id }));
+                    return 0;
+                }
+                Ok(true) => {
+                    println!("Unregistered AI library '{id}'");
+                    return 0;
+                }
+                Ok(false) => {
+                    eprintln!("No AI library with ID '{id}' is registered.");
+                    return 1;
+                }
+                Err(e) => {
+                    eprintln!("Failed to unregister AI library: {e}");
+                    return 1;
+                }
+            }
+        }
+    }
+}
+
 fn get_version_from_cli() -> Option<&'static str> {
     let instruction = INSTRUCTION
         .get()
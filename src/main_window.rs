@@ -1,25 +1,61 @@
 use open as file_open;
 use copypasta::ClipboardProvider;
+use crate::archives;
+use crate::bundle;
 use crate::cold_clear;
+use crate::cold_clear::custom;
+use crate::cold_clear::releases::{self, ReleaseCache};
 use crate::dirs;
+use crate::doctor;
 use crate::conf::Config;
 use crate::game;
 use crate::git;
+use crate::info;
 use crate::error_window;
-use crate::slint_types::MainWindow;
-use slint::{ModelRc, VecModel, SharedString, ModelExt, ComponentHandle};
+use crate::export;
+use crate::patches;
+use crate::progress::ProgressStyle;
+use crate::recipe::{self, Recipe};
+use crate::refs;
+use crate::save::location;
+use crate::save_viewer;
+use crate::slint_types::{DiagnosticCheck, MainWindow, SaveLocation};
+use crate::transfer;
+use rfd::FileDialog;
+use slint::{ModelRc, VecModel, SharedString, ModelExt, ComponentHandle, Weak};
+
+fn get_versions(repo_path: &str, include_branches: bool, include_commits: bool) -> ModelRc<SharedString> {
+    let archives = archives::load_archives()
+        .iter()
+        .map(|archive| SharedString::from(archive.id()))
+        .collect::<Vec<SharedString>>();
+
+    if !git::is_repo_valid(repo_path) {
+        return ModelRc::new(VecModel::from(archives));
+    }
 
-fn get_versions(repo_path: &str, include_commits: bool) -> ModelRc<SharedString> {
     let mut versions = git::tags(repo_path)
         .iter()
-        .map(|s| SharedString::from(s))
+        .map(SharedString::from)
         .collect::<Vec<SharedString>>();
 
+    versions.extend(archives);
+
+    if include_branches {
+        let branches = git::branches(repo_path)
+            .iter()
+            .map(|branch| format!("[Branch {branch}]"))
+            .map(SharedString::from)
+            .collect::<Vec<SharedString>>();
+
+        versions.extend(branches);
+    }
+
     if include_commits {
         let commits = git::get_commits(repo_path)
             .iter()
             .map(|(hash, name)| format!("[Commit {hash}: {name}]"))
-            .map(|s| SharedString::from(s))
+            .map(SharedString::from)
             .collect::<Vec<SharedString>>();
 
         versions.extend(commits);
@@ -30,17 +66,96 @@ fn get_versions(repo_path: &str, include_commits: bool) -> ModelRc<SharedString>
     );
 }
 
-/// Gets the commit hash of the formatted commit name.
+/// Gets the commit hash of the formatted commit name, or the name of the formatted branch name.
 /// If the name is not in a valid format, the original string will be returned.
 fn try_unwrap_version_name(name: &str) -> String {
     if name.starts_with("[Commit ") {
         let hash_end = name.find(':').unwrap_or(name.len());
         return name[8..hash_end].to_string();
+    } else if let Some(branch) = name.strip_prefix("[Branch ").and_then(|rest| rest.strip_suffix(']')) {
+        return branch.to_string();
     } else {
-        return name.to_string();
+        return name.trim().to_string();
     }
 }
 
+#[test]
+fn test_try_unwrap_version_name() {
+    assert_eq!(try_unwrap_version_name("[Commit 0123abc: Fix bug]"), "0123abc");
+    assert_eq!(try_unwrap_version_name("[Branch origin/dev]"), "origin/dev");
+    assert_eq!(try_unwrap_version_name(" 0.17.0~3 "), "0.17.0~3");
+}
+
+/// Checks out `version`, if one was chosen, and runs the game.
+fn open_game(cfg: &Config, version: &str) {
+    let version = Some(version).filter(|version| !version.is_empty());
+
+    if let Err(e) = game::run(cfg, version) {
+        error_window::open_safe(
+            Some("Failed to start the game".to_string()),
+            Some(e.title().to_string()),
+            Some(e.to_string())
+        );
+    }
+}
+
+fn set_recipes(window: &MainWindow) {
+    let names: Vec<SharedString> = recipe::list()
+        .into_iter()
+        .map(|(_, recipe)| SharedString::from(recipe.name))
+        .collect();
+
+    window.set_recipes(ModelRc::new(VecModel::from(names)));
+}
+
+/// Asks where to save a `.love` file of the version and exports it,
+/// with the sandbox and Cold Clear settings of `cfg`.
+/// Returns a status message, or an empty string if cancelled.
+fn export_version(repo_path: &str, version: &str, cfg: &Config) -> String {
+    let file_name = format!("Techmino_{}.love", version.replace(['/', '\\', ':', '~', '^', '@', '{', '}'], "_"));
+
+    let output_path = FileDialog::new()
+        .set_title("Export game")
+        .set_file_name(file_name)
+        .add_filter("LÖVE game", &["love"])
+        .save_file();
+
+    let output_path = match output_path {
+        Some(path) => path,
+        None => return "".to_string()
+    };
+
+    let options = export::ExportOptions {
+        version: version.to_string(),
+        sandboxed: cfg.sandboxed,
+        cold_clear_version: cfg.use_cold_clear
+            .then(|| cfg.cold_clear_version.clone()),
+    };
+
+    return match export::export(repo_path, &options, &output_path, ProgressStyle::Window) {
+        Ok(report) => format!("Exported {} to {}", report.resolution.input, report.output_path),
+        Err(e) => e,
+    };
+}
+
+fn set_archives(window: &MainWindow) {
+    let ids: Vec<SharedString> = archives::load_archives()
+        .iter()
+        .map(|archive| SharedString::from(archive.id()))
+        .collect();
+
+    window.set_archives(ModelRc::new(VecModel::from(ids)));
+}
+
+fn set_patches(window: &MainWindow) {
+    let names: Vec<SharedString> = patches::list()
+        .iter()
+        .map(SharedString::from)
+        .collect();
+
+    window.set_patches(ModelRc::new(VecModel::from(names)));
+}
+
 pub fn open(cfg: &Config) -> Result<MainWindow, slint::PlatformError> {
     let main_window = MainWindow::new()?;
     main_window.on_open_game(|version| {
@@ -48,33 +163,57 @@ pub fn open(cfg: &Config) -> Result<MainWindow, slint::PlatformError> {
 
         let version = try_unwrap_version_name(&version);
 
-        if !version.is_empty() {
-            let reset_cmd = git::restore(&cfg.game_repo_path);
+        open_game(&cfg, &version);
+    });
+    set_recipes(&main_window);
+    let main_window_weak = main_window.as_weak();
+    main_window.on_save_recipe(move |name, version, settings| {
+        let window = main_window_weak.unwrap();
+        let version = try_unwrap_version_name(&version);
 
-            if let Err(e) = reset_cmd {
-                error_window::open_safe(
-                    Some("Failed to reset repository".to_string()),
-                    Some("Failed to reset repository before switching versions".to_string()),
-                    Some(format!("Git: {e}"))
-                );
-                return;
-            }
+        let cfg = Config {
+            game_repo_path: Config::load().game_repo_path,
+            ..settings.into()
+        };
 
-            let status = git::checkout(&cfg.game_repo_path, &version);
+        let recipe = match Recipe::new(&name, &version, &cfg) {
+            Ok(recipe) => recipe,
+            Err(e) => return e.into()
+        };
 
-            if let Err(e) = status {
+        let path = recipe::get_path_for_name(&recipe.name);
+
+        if let Err(e) = recipe.save(&path) {
+            return e.into();
+        }
+
+        set_recipes(&window);
+
+        return format!("Saved recipe to {}", path.to_string_lossy()).into();
+    });
+    main_window.on_export_version(|version, settings| {
+        let version = try_unwrap_version_name(&version);
+        let cfg: Config = settings.into();
+
+        return export_version(&Config::load().game_repo_path, &version, &cfg).into();
+    });
+    main_window.on_run_recipe(|name| {
+        let recipe = match recipe::find(&name) {
+            Some(recipe) => recipe,
+            None => {
                 error_window::open_safe(
-                    Some("Failed to switch versions".to_string()),
-                    Some(
-                        format!("Failed to switch to version '{version}'")
-                    ),
-                    Some(format!("Git: {e}"))
+                    None,
+                    Some(format!("Recipe '{name}' not found")),
+                    None
                 );
                 return;
             }
-        }
+        };
+
+        let cfg = Config::load();
+        recipe.check_version_moved(&cfg.game_repo_path);
 
-        game::run(&cfg);
+        open_game(&recipe.apply(&cfg), recipe.revision());
     });
     main_window.set_sandbox_path(
         dirs::paths::get_sandboxed_save_path()
@@ -100,20 +239,53 @@ pub fn open(cfg: &Config) -> Result<MainWindow, slint::PlatformError> {
     main_window.set_settings(cfg.clone().into());
     main_window.set_is_wayland_used(is_wayland_session());
     main_window.set_versions(
-        get_versions(&cfg.game_repo_path, false)
+        get_versions(&cfg.game_repo_path, false, false)
     );
-    main_window.set_cc_versions(
-        ModelRc::new(
-            VecModel::from(
-                cold_clear::get_available_offline_versions()
-                    .iter()
-                    .map(|s| SharedString::from(s))
-                    .collect::<Vec<SharedString>>()
-            )
-        )
-    );
-    main_window.on_update_version_list(|include_commits| {
-        get_versions(Config::load().game_repo_path.as_str(), include_commits)
+    set_cc_versions(&main_window, &ReleaseCache::load());
+    find_save_locations(main_window.as_weak(), cfg.game_repo_path.clone());
+    main_window.on_open_save_viewer(|| {
+        let main_save_path = location::find_save_path(&Config::load()).ok();
+
+        let result = save_viewer::open(
+            &dirs::paths::get_sandboxed_save_path(),
+            main_save_path.as_deref()
+        );
+
+        if let Err(e) = result {
+            error_window::open_safe(
+                None,
+                Some("Failed to open the save viewer".to_string()),
+                Some(e.to_string())
+            );
+        }
+    });
+    main_window.on_browse_for_save_dir(|current_path| {
+        return FileDialog::new()
+            .set_title("Choose your Techmino save directory")
+            .pick_folder()
+            .map(|path| SharedString::from(path.to_string_lossy().as_ref()))
+            .unwrap_or(current_path);
+    });
+    main_window.on_update_version_list(|include_branches, include_commits| {
+        get_versions(Config::load().game_repo_path.as_str(), include_branches, include_commits)
+    });
+    let repo_path = cfg.game_repo_path.clone();
+    main_window.on_resolve_version(move |version| {
+        let version = try_unwrap_version_name(&version);
+
+        if archives::is_archive_version(&version) {
+            return match archives::find(&version) {
+                Some(archive) => format!("Opens {}", archive.path).into(),
+                None => format!("The archive '{version}' is not registered").into(),
+            };
+        }
+
+        return match refs::resolve(&repo_path, &version, false) {
+            Ok(resolution) => format!("Opens {resolution}").into(),
+            Err(e) if refs::parse_pull_request(version.trim()).is_some() =>
+                format!("{e}. It will be fetched when the game is opened.").into(),
+            Err(e) => e.into(),
+        };
     });
     main_window.on_clear_save_dir(dirs::clear_temp_dir);
     main_window.on_filter(|arr: ModelRc<SharedString>, search: SharedString| -> ModelRc<SharedString> {
@@ -126,38 +298,276 @@ pub fn open(cfg: &Config) -> Result<MainWindow, slint::PlatformError> {
     });
     main_window.on_apply_settings(|settings| {
         let config: Config = settings.into();
-        config.save();
+        config.save_changes(&Config::load());
+    });
+
+    let main_window_weak = main_window.as_weak();
+    main_window.on_register_cc_library(move |name, version, is_archive| {
+        let window = main_window_weak.unwrap();
+        return register_cc_library(&window, &name, &version, is_archive).into();
+    });
+
+    let main_window_weak = main_window.as_weak();
+    main_window.on_refresh_cc_versions(move || {
+        refresh_cc_versions(main_window_weak.clone());
+    });
+
+    refresh_cc_versions(main_window.as_weak());
+
+    main_window.on_import_bundle(|| {
+        let bundle_path = FileDialog::new()
+            .set_title("Choose a bundle made with `boxedmino repo export-bundle`")
+            .add_filter("Boxedmino bundle", &["zip"])
+            .pick_file();
+
+        let bundle_path = match bundle_path {
+            Some(bundle_path) => bundle_path,
+            None => return "".into()
+        };
+
+        return match bundle::import(&bundle_path, &Config::load().game_repo_path) {
+            Ok(report) if report.new_tags.is_empty() => "The bundle has no new versions.".into(),
+            Ok(report) => format!("Added versions: {}", report.new_tags.join(", ")).into(),
+            Err(e) => e.into(),
+        };
     });
+    main_window.on_fetch_versions(|| {
+        return match transfer::fetch_versions(&Config::load().game_repo_path, ProgressStyle::Window) {
+            Ok(new_tags) if new_tags.is_empty() => "No new versions.".into(),
+            Ok(new_tags) => format!("Added versions: {}", new_tags.join(", ")).into(),
+            Err(e) if e == transfer::CANCELLED_MESSAGE => "".into(),
+            Err(e) => format!("Failed to fetch new versions: {e}").into(),
+        };
+    });
+    set_archives(&main_window);
+    let main_window_weak = main_window.as_weak();
+    main_window.on_add_archive(move || {
+        let window = main_window_weak.unwrap();
+
+        let path = FileDialog::new()
+            .set_title("Choose a .love file or zip archive of the game")
+            .add_filter("Game archive", &["love", "zip"])
+            .pick_file();
+
+        let path = match path {
+            Some(path) => path,
+            None => return "".into()
+        };
+
+        let message = match archives::register(&path.to_string_lossy(), None) {
+            Ok(archive) => format!("Registered {}", archive.id()),
+            Err(e) => format!("Failed to register archive: {e}"),
+        };
+
+        set_archives(&window);
+
+        return message.into();
+    });
+    let main_window_weak = main_window.as_weak();
+    main_window.on_remove_archive(move |id| {
+        if let Err(e) = archives::unregister(&id) {
+            error_window::open_safe(
+                None,
+                Some("Failed to remove archive".to_string()),
+                Some(e.to_string())
+            );
+        }
 
-    // Fetch for new CC version asynchronously
+        set_archives(&main_window_weak.unwrap());
+    });
+    set_patches(&main_window);
     let main_window_weak = main_window.as_weak();
+    main_window.on_capture_patch(move |name| {
+        let window = main_window_weak.unwrap();
+
+        let message = match patches::capture(&Config::load().game_repo_path, name.trim()) {
+            Ok(path) => format!("Saved patch to {}", path.to_string_lossy()),
+            Err(e) => e,
+        };
+
+        set_patches(&window);
+
+        return message.into();
+    });
+    let main_window_weak = main_window.as_weak();
+    main_window.on_remove_patch(move |name| {
+        if let Err(e) = patches::remove(&name) {
+            error_window::open_safe(
+                None,
+                Some("Failed to remove patch".to_string()),
+                Some(e)
+            );
+        }
+
+        set_patches(&main_window_weak.unwrap());
+    });
+    main_window.on_is_patch_enabled(|enabled, name| {
+        return patches::parse_list(&enabled).contains(&name.to_string());
+    });
+    main_window.on_set_patch_enabled(|enabled, name, checked| {
+        let mut names = patches::parse_list(&enabled);
+        names.retain(|enabled_name| enabled_name != name.as_str());
+
+        if checked {
+            names.push(name.to_string());
+        }
+
+        return names.join(",").into();
+    });
+
+    let main_window_weak = main_window.as_weak();
+    main_window.on_run_diagnostics(move || {
+        run_diagnostics(main_window_weak.clone());
+    });
+
+    main_window.run()?;
+
+    return Ok(main_window);
+}
+
+/// Looks for save directories in the background,
+/// as reading the identity of every version takes a while.
+fn find_save_locations(window_weak: Weak<MainWindow>, repo_path: String) {
     std::thread::spawn(move || {
-        println!("Fetching CC versions...");
-        let rt = tokio::runtime::Runtime::new()
-            .expect("Failed to create Tokio runtime");
-        let versions = rt.block_on(async {
-            cold_clear::get_available_versions()
-                .await
-                .iter()
-                .map(|s| SharedString::from(s))
-                .collect::<Vec<SharedString>>()
+        let candidates: Vec<(String, String, String)> = location::find_candidates(&repo_path)
+            .iter()
+            .map(|candidate| (
+                candidate.path.to_string_lossy().to_string(),
+                format!("{} ({})", candidate.identity, candidate.kind),
+                candidate.format_modified(),
+            ))
+            .collect();
+
+        let _ = window_weak.upgrade_in_event_loop(move |window| {
+            let locations: Vec<SaveLocation> = candidates
+                .into_iter()
+                .map(|(path, description, modified)| SaveLocation {
+                    path: path.into(),
+                    description: description.into(),
+                    modified: modified.into(),
+                })
+                .collect();
+
+            window.set_save_locations(ModelRc::new(VecModel::from(locations)));
         });
-        println!("Online CC versions: {}", versions.len());
-        main_window_weak.upgrade_in_event_loop(move |window| {
-            window.set_cc_versions(
-                ModelRc::new(
-                    VecModel::from(versions)
-                )
+    });
+}
+
+fn set_cc_versions(window: &MainWindow, cache: &ReleaseCache) {
+    window.set_cc_versions(
+        ModelRc::new(
+            VecModel::from(
+                cold_clear::get_available_versions(cache)
+                    .iter()
+                    .map(SharedString::from)
+                    .collect::<Vec<SharedString>>()
             )
+        )
+    );
+    window.set_cc_versions_refreshed_at(cache.format_refreshed_at().into());
+}
+
+/// Asks the user for a custom AI library and registers it.
+/// Returns an error message, or an empty string on success or cancellation.
+fn register_cc_library(window: &MainWindow, name: &str, version: &str, is_archive: bool) -> String {
+    let dialog = FileDialog::new();
+
+    let path = if is_archive {
+        dialog.add_filter("Zip archive", &["zip"]).pick_file()
+    } else {
+        dialog.pick_folder()
+    };
+
+    let path = match path {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => return "".to_string()
+    };
+
+    let library = custom::CustomLibrary {
+        name: name.to_string(),
+        version: version.to_string(),
+        path,
+        os: None,
+        arch: None,
+    };
+
+    match custom::register(library) {
+        Ok(library) => {
+            info!("Registered AI library '{}'", library.id());
+            set_cc_versions(window, &ReleaseCache::load());
+            return "".to_string();
+        }
+        Err(e) => {
+            return format!("Failed to register AI library: {e}");
+        }
+    }
+}
+
+/// Revalidates the cached CC release list in the background.
+/// The cached list stays in use if the refresh fails.
+fn refresh_cc_versions(window_weak: Weak<MainWindow>) {
+    if let Some(window) = window_weak.upgrade() {
+        window.set_cc_versions_refreshing(true);
+        window.set_cc_versions_error("".into());
+    }
+
+    std::thread::spawn(move || {
+        info!("Fetching CC versions...");
+        let rt = tokio::runtime::Runtime::new()
+            .expect("Failed to create Tokio runtime");
+        let result = rt.block_on(releases::refresh())
+            .map_err(|e| e.to_string());
+
+        if let Err(e) = &result {
+            eprintln!("Failed to refresh ColdClear release list: {e}");
+        }
+
+        window_weak.upgrade_in_event_loop(move |window| {
+            window.set_cc_versions_refreshing(false);
+
+            match result {
+                Ok(cache) => {
+                    info!("Online CC versions: {}", cache.releases.len());
+                    set_cc_versions(&window, &cache);
+                }
+                Err(e) => {
+                    window.set_cc_versions_error(
+                        format!("Could not refresh the release list: {e}").into()
+                    );
+                }
+            }
         })
         .expect("Failed to upgrade weak ref in event loop while fetching CC versions");
     });
+}
 
-    main_window.run()?;
+/// Runs the diagnostics in the background, as the network checks may take a while.
+fn run_diagnostics(window_weak: Weak<MainWindow>) {
+    if let Some(window) = window_weak.upgrade() {
+        window.set_diagnostics_running(true);
+    }
 
-    return Ok(main_window);
-}
+    std::thread::spawn(move || {
+        let report = doctor::diagnose(&Config::load(), false);
 
+        window_weak.upgrade_in_event_loop(move |window| {
+            let checks: Vec<DiagnosticCheck> = report.checks
+                .iter()
+                .map(|check| DiagnosticCheck {
+                    status: check.status.to_string().into(),
+                    name: check.name.clone().into(),
+                    message: check.message.clone().into(),
+                    hint: check.hint.clone().unwrap_or_default().into(),
+                })
+                .collect();
+
+            window.set_diagnostics(ModelRc::new(VecModel::from(checks)));
+            window.set_diagnostics_report(report.to_json().into());
+            window.set_diagnostics_running(false);
+        })
+        .expect("Failed to upgrade weak ref in event loop while running diagnostics");
+    });
+}
 
 fn copy_text(text: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mut ctx = copypasta::ClipboardContext::new()?;
@@ -167,7 +577,7 @@ fn copy_text(text: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
 
 fn copy_text_handled(text: &str) {
     if is_wayland_session() {
-        println!("Copying text to clipboard is not supported on Wayland.");
+        info!("Copying text to clipboard is not supported on Wayland.");
     }
     if let Err(error) = copy_text(text) {
         error_window::open_safe(
@@ -184,7 +594,7 @@ fn is_wayland_session() -> bool {
 }
 
 fn open_link(url: slint::SharedString) {
-    println!("Opening link: {url}");
+    info!("Opening link: {url}");
     open::that(url.as_str()).unwrap_or_else(|_| {
         error_window::open_safe(
             None,
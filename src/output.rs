@@ -0,0 +1,44 @@
+use clap::ValueEnum;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+/// How commands print their results to stdout.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A single JSON document, for scripts
+    Json,
+}
+
+static FORMAT: OnceCell<OutputFormat> = OnceCell::new();
+
+pub fn set_format(format: OutputFormat) {
+    FORMAT.set(format).unwrap();
+}
+
+/// Whether `--format json` was passed.
+pub fn is_json() -> bool {
+    return FORMAT.get().copied().unwrap_or_default() == OutputFormat::Json;
+}
+
+/// Prints a result as pretty JSON to stdout.
+pub fn print_json(value: &impl Serialize) {
+    let json = serde_json::to_string_pretty(value)
+        .expect("Failed to serialize output");
+    println!("{json}");
+}
+
+/// Prints a status message for humans.
+///
+/// Messages go to stderr, so stdout only carries the results of a command,
+/// and are hidden by `--quiet`.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::dirs::{self, paths};
+use crate::game;
+use crate::git;
+use crate::output;
+use crate::refs;
+use crate::PatchAction;
+
+pub fn get_path(name: &str) -> PathBuf {
+    return paths::get_patches_dir_path().join(format!("{name}.patch"));
+}
+
+/// Splits a comma-separated list of patch names, as stored in the config.
+pub fn parse_list(list: &str) -> Vec<String> {
+    return list
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+}
+
+#[test]
+fn test_parse_list() {
+    assert_eq!(parse_list(" fix-crash,, debug.print "), vec!["fix-crash", "debug.print"]);
+    assert_eq!(parse_list(""), Vec::<String>::new());
+}
+
+/// Names of every stored patch, sorted.
+pub fn list() -> Vec<String> {
+    let entries = match fs::read_dir(paths::get_patches_dir_path()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![]
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            return file_name.strip_suffix(".patch").map(|name| name.to_string());
+        })
+        .collect();
+
+    names.sort();
+
+    return names;
+}
+
+/// Saves the uncommitted changes to tracked files as a new patch.
+/// New files are included once they are staged with `git add`.
+pub fn capture(repo_path: &str, name: &str) -> Result<PathBuf, String> {
+    dirs::validate_file_name("patch name", name)?;
+
+    let path = get_path(name);
+
+    if path.exists() {
+        return Err(format!("A patch named '{name}' already exists"));
+    }
+
+    let conf_lua = fs::read_to_string(Path::new(repo_path).join("conf.lua"))
+        .unwrap_or_default();
+
+    if game::is_injected(&conf_lua) {
+        return Err("conf.lua contains Boxedmino's sandboxing code. Restart Boxedmino to remove it first.".to_string());
+    }
+
+    let output = git::run_checked(repo_path, &["diff", "--binary", "HEAD"])?;
+
+    if output.stdout.is_empty() {
+        return Err("The game repository has no changes to capture".to_string());
+    }
+
+    fs::create_dir_all(paths::get_patches_dir_path())
+        .and_then(|_| fs::write(&path, &output.stdout))
+        .map_err(|e| format!("Failed to write {}: {e}", path.to_string_lossy()))?;
+
+    return Ok(path);
+}
+
+pub fn remove(name: &str) -> Result<(), String> {
+    dirs::validate_file_name("patch name", name)?;
+
+    return fs::remove_file(get_path(name))
+        .map_err(|e| format!("Failed to remove patch '{name}': {e}"));
+}
+
+fn get_existing_path(name: &str) -> Result<PathBuf, String> {
+    dirs::validate_file_name("patch name", name)?;
+
+    let path = get_path(name);
+
+    if !path.is_file() {
+        return Err(format!("There is no patch named '{name}'"));
+    }
+
+    return Ok(path);
+}
+
+/// Checks whether a patch applies to a commit, without checking it out.
+pub fn check(repo_path: &str, name: &str, commit: &str) -> Result<(), String> {
+    return check_file(repo_path, &get_existing_path(name)?, commit);
+}
+
+fn check_file(repo_path: &str, path: &Path, commit: &str) -> Result<(), String> {
+    // A throwaway index holding the commit's files stands in for the working tree
+    let index_file = std::env::temp_dir()
+        .join(format!("boxedmino_patch_index_{}", std::process::id()));
+
+    let result = git::run_checked_with_index(repo_path, &["read-tree", commit], &index_file)
+        .and_then(|_| git::run_checked_with_index(
+            repo_path,
+            &["apply", "--cached", "--check", &path.to_string_lossy()],
+            &index_file
+        ));
+
+    let _ = fs::remove_file(&index_file);
+
+    return result.map(|_| ());
+}
+
+/// Which patches were applied for a run.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ApplyReport {
+    pub applied: Vec<String>,
+    /// The names of patches that did not apply, and why
+    pub failed: Vec<(String, String)>,
+}
+
+fn apply_file(repo_path: &str, path: &Path, reverse: bool) -> Result<(), String> {
+    let path = path.to_string_lossy();
+
+    let mut args = vec!["apply", "--whitespace=nowarn"];
+
+    if reverse {
+        args.push("--reverse");
+    }
+
+    args.push(&path);
+
+    git::run_checked(repo_path, &args)?;
+
+    return Ok(());
+}
+
+/// Applies the patches to the working tree in order.
+/// A patch that fails is skipped, and the others are still applied.
+pub fn apply(repo_path: &str, names: &[String]) -> ApplyReport {
+    let mut report = ApplyReport::default();
+
+    for name in names {
+        match get_existing_path(name).and_then(|path| apply_file(repo_path, &path, false)) {
+            Ok(()) => report.applied.push(name.clone()),
+            Err(e) => report.failed.push((name.clone(), e)),
+        }
+    }
+
+    return report;
+}
+
+/// Undoes [`apply`], in reverse order.
+pub fn revert(repo_path: &str, applied: &[String]) -> Result<(), String> {
+    for name in applied.iter().rev() {
+        get_existing_path(name)
+            .and_then(|path| apply_file(repo_path, &path, true))
+            .map_err(|e| format!("Failed to revert patch '{name}': {e}"))?;
+    }
+
+    return Ok(());
+}
+
+#[test]
+fn test_apply_and_check() {
+    let scratch = git::ScratchRepo::new("patches");
+    let repo = &scratch.path;
+    let repo_path = scratch.path_str();
+
+    fs::write(repo.join("main.lua"), "print(1)\n").unwrap();
+    scratch.git(&["add", "."]);
+    scratch.git(&["commit", "--quiet", "--message", "first"]);
+    let first = git::resolve_commit(&repo_path, "HEAD").unwrap();
+    fs::write(repo.join("main.lua"), "print(2)\n").unwrap();
+    scratch.git(&["commit", "--quiet", "--all", "--message", "second"]);
+
+    // Stand-in for a captured patch, kept out of the real patches directory
+    fs::write(repo.join("main.lua"), "print(3)\n").unwrap();
+    let patch = scratch.root.join("print-3.patch");
+    fs::write(&patch, git::run_checked(&repo_path, &["diff", "HEAD"]).unwrap().stdout).unwrap();
+    scratch.git(&["checkout", "--quiet", "--", "main.lua"]);
+
+    assert!(check_file(&repo_path, &patch, "HEAD").is_ok());
+    assert!(check_file(&repo_path, &patch, &first).is_err());
+
+    apply_file(&repo_path, &patch, false).unwrap();
+    assert_eq!(fs::read_to_string(repo.join("main.lua")).unwrap(), "print(3)\n");
+    assert!(apply_file(&repo_path, &patch, false).is_err());
+
+    apply_file(&repo_path, &patch, true).unwrap();
+    assert_eq!(fs::read_to_string(repo.join("main.lua")).unwrap(), "print(2)\n");
+
+    let report = apply(&repo_path, &["boxedmino-missing-patch".to_string()]);
+    assert_eq!((report.applied.len(), report.failed.len()), (0, 1));
+}
+
+fn print_check_results(results: &[(String, Result<(), String>)]) {
+    if output::is_json() {
+        let results: Vec<serde_json::Value> = results
+            .iter()
+            .map(|(name, result)| serde_json::json!({
+                "name": name,
+                "applies": result.is_ok(),
+                "error": result.as_ref().err(),
+            }))
+            .collect();
+        output::print_json(&results);
+        return;
+    }
+
+    for (name, result) in results {
+        match result {
+            Ok(()) => println!("{name}: applies"),
+            Err(e) => println!("{name}: does not apply\n  {}", e.replace('\n', "\n  ")),
+        }
+    }
+}
+
+/// Runs a `boxedmino patch` subcommand, returning the exit code.
+pub fn run(action: &PatchAction, repo_path: &str) -> i32 {
+    match action {
+        PatchAction::Capture { name } => {
+            match capture(repo_path, name) {
+                Ok(path) => {
+                    println!("Saved patch '{name}' to {}", path.to_string_lossy());
+                    return 0;
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    return 1;
+                }
+            }
+        }
+        PatchAction::List => {
+            let names = list();
+
+            if output::is_json() {
+                output::print_json(&names);
+            } else {
+                for name in names {
+                    println!("{name}");
+                }
+            }
+            return 0;
+        }
+        PatchAction::Remove { name } => {
+            if let Err(e) = remove(name) {
+                eprintln!("{e}");
+                return 1;
+            }
+            return 0;
+        }
+        PatchAction::Check { names, version } => {
+            let commit = match refs::resolve(repo_path, version, true) {
+                Ok(resolution) => resolution.commit,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return 1;
+                }
+            };
+
+            let names = if names.is_empty() { list() } else { names.clone() };
+
+            let results: Vec<(String, Result<(), String>)> = names
+                .into_iter()
+                .map(|name| {
+                    let result = check(repo_path, &name, &commit);
+                    return (name, result);
+                })
+                .collect();
+
+            print_check_results(&results);
+
+            return if results.iter().all(|(_, result)| result.is_ok()) { 0 } else { 1 };
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::{self, IsTerminal, Write};
+use slint::SharedString;
+
+/// How progress of long-running operations is shown to the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressStyle {
+    /// A Slint window, for GUI mode
+    Window,
+    /// A progress bar on stderr, for CLI mode
+    Terminal,
+    /// Nothing at all, for scripts
+    Quiet,
+}
+
+impl ProgressStyle {
+    pub fn new(use_gui: bool) -> Self {
+        if use_gui {
+            return ProgressStyle::Window;
+        }
+
+        if crate::is_quiet() {
+            return ProgressStyle::Quiet;
+        }
+
+        return ProgressStyle::Terminal;
+    }
+}
+
+/// Receives progress updates from a download.
+pub trait ProgressReporter {
+    /// Sets the total amount of bytes, or `None` if it is unknown.
+    fn set_total(&mut self, total: Option<i32>);
+
+    /// Reports the amount of bytes done so far,
+    /// the overall rate in bytes per second and the ETA in seconds.
+    fn advance_to(&mut self, done: i32, rate: i32, eta: Option<i32>);
+
+    fn finish(&mut self);
+
+    /// Whether the user asked to cancel the operation.
+    fn is_interrupted(&self) -> bool {
+        return false;
+    }
+}
+
+/// Reports nothing.
+pub struct QuietProgress;
+
+impl ProgressReporter for QuietProgress {
+    fn set_total(&mut self, _total: Option<i32>) {}
+    fn advance_to(&mut self, _done: i32, _rate: i32, _eta: Option<i32>) {}
+    fn finish(&mut self) {}
+}
+
+/// Draws a progress bar on stderr.
+///
+/// If stderr is not a terminal, only the start and the end are reported.
+pub struct TerminalProgress {
+    label: String,
+    total: Option<i32>,
+    is_terminal: bool,
+    last_line_len: usize,
+}
+
+impl TerminalProgress {
+    pub fn new(label: &str) -> Self {
+        return Self {
+            label: label.to_string(),
+            total: None,
+            is_terminal: io::stderr().is_terminal(),
+            last_line_len: 0,
+        };
+    }
+}
+
+impl ProgressReporter for TerminalProgress {
+    fn set_total(&mut self, total: Option<i32>) {
+        self.total = total;
+
+        let size = match total {
+            Some(total) => format!(" ({})", format_bytes(total)),
+            None => "".to_string()
+        };
+
+        eprintln!("{}{size}...", self.label);
+    }
+
+    fn advance_to(&mut self, done: i32, rate: i32, eta: Option<i32>) {
+        if !self.is_terminal {
+            return;
+        }
+
+        let line = render_progress_line(done, self.total, rate, eta);
+        let padding = " ".repeat(self.last_line_len.saturating_sub(line.len()));
+        self.last_line_len = line.len();
+
+        eprint!("\r{line}{padding}");
+        io::stderr().flush().ok();
+    }
+
+    fn finish(&mut self) {
+        if self.is_terminal && self.last_line_len > 0 {
+            eprintln!();
+        }
+        eprintln!("{}: done", self.label);
+    }
+}
+
+const PROGRESS_BAR_WIDTH: usize = 24;
+
+/// Draws the inside of a progress bar, `ratio` being between 0 and 1.
+pub fn render_bar(ratio: f64) -> String {
+    let filled = (ratio.clamp(0.0, 1.0) * PROGRESS_BAR_WIDTH as f64).round() as usize;
+
+    return format!("{}{}", "#".repeat(filled), "-".repeat(PROGRESS_BAR_WIDTH - filled));
+}
+
+fn render_progress_line(done: i32, total: Option<i32>, rate: i32, eta: Option<i32>) -> String {
+    let rate = format_bytes(rate);
+
+    let total = match total {
+        Some(total) if total > 0 => total,
+        _ => return format!("{} downloaded | {rate}/s", format_bytes(done))
+    };
+
+    let ratio = (done as f64 / total as f64).clamp(0.0, 1.0);
+
+    let eta = match eta {
+        Some(secs) => format_time(secs),
+        None => "Unknown".to_string()
+    };
+
+    return format!(
+        "[{}] {:>3.0}% | {} / {} | {rate}/s | ETA: {eta}",
+        render_bar(ratio),
+        ratio * 100.0,
+        format_bytes(done),
+        format_bytes(total)
+    );
+}
+
+#[test]
+fn test_render_progress_line() {
+    let cases = [
+        (
+            500, Some(1000), 250, Some(2),
+            "[############------------]  50% | 500 bytes / 1.00 KB | 250 bytes/s | ETA: 2 seconds"
+        ),
+        (
+            1000, Some(1000), 1000, Some(0),
+            "[########################] 100% | 1.00 KB / 1.00 KB | 1.00 KB/s | ETA: 0 seconds"
+        ),
+        (
+            2000, None, 1000, None,
+            "2.00 KB downloaded | 1.00 KB/s"
+        ),
+    ];
+
+    for (done, total, rate, eta, expected) in cases {
+        assert_eq!(render_progress_line(done, total, rate, eta), expected);
+    }
+}
+
+pub fn format_bytes(bytes: i32) -> SharedString {
+    let bytes = bytes as f64;
+    if bytes < 1e3 {
+        return format!("{bytes:.0} bytes").into();
+    } else if bytes < 1e6 {
+        return format!("{:.2} KB", bytes / 1e3).into();
+    } else if bytes < 1e9 {
+        return format!("{:.2} MB", bytes / 1e6).into();
+    } else {
+        return format!("{:.2} GB", bytes / 1e9).into();
+    }
+}
+
+#[test]
+fn test_format_bytes() {
+    let cases = [
+        (0, "0 bytes"),
+        (999, "999 bytes"),
+        (1000, "1.00 KB"),
+        (1024, "1.02 KB"),
+        (1048575, "1.05 MB"),
+        (1048576, "1.05 MB"),
+        (1073741823, "1.07 GB"),
+        (1073741824, "1.07 GB"),
+        (2147483647, "2.15 GB"),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(format_bytes(input), expected);
+    }
+}
+
+pub fn format_time(secs: i32) -> String {
+    if secs < 60 {
+        return format!("{secs:.0} seconds");
+    } else if secs < 3600 {
+        return format!("{:.0}:{:02.0}", secs / 60, secs % 60)
+    } else {
+        return format!(
+            "{:.0}:{:02.0}:{:02.0}",
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        );
+    }
+}
+
+#[test]
+fn test_format_time() {
+    let cases = [
+        (0, "0 seconds"),
+        (59, "59 seconds"),
+        (60, "1:00"),
+        (61, "1:01"),
+        (3599, "59:59"),
+        (3600, "1:00:00"),
+        (3661, "1:01:01"),
+        (86399, "23:59:59"),
+        (86400, "24:00:00"),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(format_time(input), expected);
+    }
+}
@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use once_cell::sync::OnceCell;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use crate::conf::{schema, Config};
+use crate::dirs::paths;
+use crate::{archives, git, refs, CliInstruction, INSTRUCTION};
+
+/// Settings a recipe may set. Paths are left out,
+/// as they differ between the machines a recipe is shared with.
+pub const RECIPE_KEYS: [&str; 7] = [
+    "sandboxed",
+    "clear_temp_dir",
+    "import_save_on_play",
+    "import_categories",
+    "patches",
+    "use_cold_clear",
+    "cold_clear_version",
+];
+
+/// A complete description of a launch, saved as a TOML file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Recipe {
+    pub name: String,
+    /// The tag or commit as it was chosen
+    pub version: String,
+    /// The commit `version` pointed to when the recipe was saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Settings overriding the config, see [`RECIPE_KEYS`]
+    #[serde(default)]
+    pub settings: Map<String, Value>,
+}
+
+impl Recipe {
+    /// Creates a recipe from the chosen version and settings,
+    /// recording the commit the version points to.
+    pub fn new(name: &str, version: &str, cfg: &Config) -> Result<Self, String> {
+        if name.trim().is_empty() {
+            return Err("The recipe name cannot be empty.".to_string());
+        }
+
+        if version.is_empty() {
+            return Err("Choose a version first.".to_string());
+        }
+
+        // Pull requests are fetched here, so the recipe can record their commit.
+        // Archives have no commit to record.
+        let commit = match archives::is_archive_version(version) {
+            true => None,
+            false => Some(refs::resolve(&cfg.game_repo_path, version, true)?.commit),
+        };
+
+        let mut settings = cfg.to_object();
+        settings.retain(|key, _| RECIPE_KEYS.contains(&key.as_str()));
+
+        return Ok(Self {
+            name: name.trim().to_string(),
+            version: version.to_string(),
+            commit,
+            settings,
+        });
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read recipe {}: {e}", path.to_string_lossy()))?;
+
+        return Self::parse(&contents)
+            .map_err(|e| format!("Invalid recipe {}: {e}", path.to_string_lossy()));
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let recipe: Recipe = toml::from_str(contents)
+            .map_err(|e| e.to_string())?;
+
+        if let Some(key) = recipe.settings.keys().find(|key| !RECIPE_KEYS.contains(&key.as_str())) {
+            return Err(format!(
+                "`{key}` cannot be set in a recipe. Valid settings are: {}",
+                RECIPE_KEYS.join(", ")
+            ));
+        }
+
+        let checked = schema::check_values(recipe.settings.clone())
+            .map_err(|e| e.to_string())?;
+
+        if let Some(issue) = checked.issues.first() {
+            return Err(issue.to_string());
+        }
+
+        return Ok(recipe);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize recipe: {e}"))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {e}", parent.to_string_lossy()))?;
+        }
+
+        return fs::write(path, contents)
+            .map_err(|e| format!("Failed to write recipe {}: {e}", path.to_string_lossy()));
+    }
+
+    /// What to check out: the recorded commit if there is one, so the launch is repeatable.
+    pub fn revision(&self) -> &str {
+        return self.commit.as_deref().unwrap_or(&self.version);
+    }
+
+    /// Warns if the version now points to a different commit than when the recipe was saved.
+    pub fn check_version_moved(&self, repo_path: &str) {
+        let commit = match &self.commit {
+            Some(commit) => commit,
+            None => return
+        };
+
+        if let Some(current) = git::resolve_commit(repo_path, &self.version) {
+            if current != *commit {
+                eprintln!(
+                    "Note: '{}' now points to {current}; running {commit} as recorded in the recipe",
+                    self.version
+                );
+            }
+        }
+    }
+
+    /// The config with the recipe's settings applied.
+    pub fn apply(&self, cfg: &Config) -> Config {
+        return cfg.with_values(&self.settings);
+    }
+}
+
+#[test]
+fn test_recipe_round_trip() {
+    let recipe = Recipe::parse(r#"
+        name = "Sprint practice"
+        version = "v0.17.0"
+        commit = "0123456789abcdef0123456789abcdef01234567"
+
+        [settings]
+        import_save_on_play = true
+        import_categories = "keymap"
+        cold_clear_version = "11.4.2"
+    "#).unwrap();
+
+    assert_eq!(recipe.revision(), "0123456789abcdef0123456789abcdef01234567");
+
+    let cfg = recipe.apply(&Config::default());
+    assert!(cfg.import_save_on_play);
+    assert_eq!(cfg.import_categories, "keymap");
+
+    let saved = toml::to_string_pretty(&recipe).unwrap();
+    assert_eq!(Recipe::parse(&saved).unwrap(), recipe);
+
+    assert!(Recipe::parse("name = \"a\"\nversion = \"v1\"\n[settings]\ngame_repo_path = \"/x\"").is_err());
+    assert!(Recipe::parse("name = \"a\"\nversion = \"v1\"\n[settings]\nsandboxed = \"yes\"").is_err());
+}
+
+/// The file name a recipe is saved under in the recipes directory.
+pub fn get_path_for_name(name: &str) -> PathBuf {
+    let file_name: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "-_ ".contains(c) { c } else { '_' })
+        .collect();
+
+    return paths::get_recipes_dir_path().join(format!("{file_name}.toml"));
+}
+
+/// Every readable recipe in the recipes directory, sorted by name.
+pub fn list() -> Vec<(PathBuf, Recipe)> {
+    let entries = match fs::read_dir(paths::get_recipes_dir_path()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![]
+    };
+
+    let mut recipes: Vec<(PathBuf, Recipe)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .filter_map(|path| match Recipe::load(&path) {
+            Ok(recipe) => Some((path, recipe)),
+            Err(e) => {
+                eprintln!("{e}");
+                None
+            }
+        })
+        .collect();
+
+    recipes.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+    return recipes;
+}
+
+/// Finds a recipe in the recipes directory by its name.
+pub fn find(name: &str) -> Option<Recipe> {
+    return list()
+        .into_iter()
+        .map(|(_, recipe)| recipe)
+        .find(|recipe| recipe.name == name);
+}
+
+static CLI_RECIPE: OnceCell<Option<Recipe>> = OnceCell::new();
+
+/// The recipe passed with `run --recipe`. Exits if it cannot be read.
+pub fn get_cli_recipe() -> Option<&'static Recipe> {
+    return CLI_RECIPE.get_or_init(|| {
+        let instruction = INSTRUCTION.get()?.as_ref()?;
+
+        let path = match instruction {
+            CliInstruction::Run { recipe, .. } => recipe.as_ref()?,
+            _ => return None
+        };
+
+        let path = Path::new(path);
+
+        // Recipes in the recipes directory can be given by name
+        if !path.exists() {
+            if let Some(recipe) = find(&path.to_string_lossy()) {
+                return Some(recipe);
+            }
+        }
+
+        match Recipe::load(path) {
+            Ok(recipe) => Some(recipe),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+    }).as_ref();
+}
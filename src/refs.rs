@@ -0,0 +1,255 @@
+use std::fmt;
+use serde::Serialize;
+use crate::git;
+use crate::output;
+use crate::progress::ProgressStyle;
+use crate::transfer;
+
+/// The remote pull requests are fetched from.
+const PULL_REQUEST_REMOTE: &str = "origin";
+
+/// What kind of revision the user asked for.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RefKind {
+    Tag,
+    Branch,
+    RemoteBranch,
+    PullRequest,
+    Commit,
+    /// The last commit before a date, e.g. `main@{2022-03-01}`
+    Date,
+    /// Anything else git understands, e.g. `0.17.0~3`
+    Expression,
+}
+
+impl fmt::Display for RefKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            RefKind::Tag => write!(f, "tag"),
+            RefKind::Branch => write!(f, "branch"),
+            RefKind::RemoteBranch => write!(f, "remote branch"),
+            RefKind::PullRequest => write!(f, "pull request"),
+            RefKind::Commit => write!(f, "commit"),
+            RefKind::Date => write!(f, "date"),
+            RefKind::Expression => write!(f, "expression"),
+        };
+    }
+}
+
+/// The commit a revision points to.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Resolution {
+    pub input: String,
+    pub kind: RefKind,
+    pub commit: String,
+    pub subject: String,
+    /// The committer date, as `YYYY-MM-DD`
+    pub date: String,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "{} {} is commit {} \"{}\" ({})",
+            self.kind,
+            self.input,
+            &self.commit[..self.commit.len().min(10)],
+            self.subject,
+            self.date
+        );
+    }
+}
+
+/// Reads the number of a GitHub-style pull request ref, `pull/N/head` or `pull/N`.
+pub fn parse_pull_request(input: &str) -> Option<u32> {
+    let rest = input.strip_prefix("pull/")?;
+    let number = rest.strip_suffix("/head").unwrap_or(rest);
+
+    return number.parse().ok();
+}
+
+#[test]
+fn test_parse_pull_request() {
+    assert_eq!(parse_pull_request("pull/42/head"), Some(42));
+    assert_eq!(parse_pull_request("pull/42"), Some(42));
+    assert_eq!(parse_pull_request("pull/42/merge"), None);
+    assert_eq!(parse_pull_request("origin/pull"), None);
+}
+
+/// Splits a date expression such as `main@{2022-03-01}` or `@{yesterday}` into
+/// the revision, `HEAD` if none is given, and the date.
+///
+/// Git would read these from the reflog, which only knows where the local branch
+/// pointed on this machine, so a fresh clone would resolve them all to the latest commit.
+/// Reflog entries such as `@{1}`, `@{-1}` and `@{upstream}` are left alone.
+fn parse_date_expression(input: &str) -> Option<(&str, &str)> {
+    let (revision, rest) = input.split_once("@{")?;
+    let date = rest.strip_suffix('}')?.trim();
+
+    let is_reflog_entry = date.trim_start_matches('-').parse::<u32>().is_ok() ||
+        ["u", "upstream", "push"].contains(&date.to_lowercase().as_str());
+
+    if date.is_empty() || is_reflog_entry || date.contains(['{', '}']) {
+        return None;
+    }
+
+    let revision = if revision.is_empty() { "HEAD" } else { revision };
+
+    return Some((revision, date));
+}
+
+#[test]
+fn test_parse_date_expression() {
+    assert_eq!(parse_date_expression("main@{2022-03-01}"), Some(("main", "2022-03-01")));
+    assert_eq!(parse_date_expression("@{2 weeks ago}"), Some(("HEAD", "2 weeks ago")));
+    assert_eq!(parse_date_expression("main@{1}"), None);
+    assert_eq!(parse_date_expression("@{-1}"), None);
+    assert_eq!(parse_date_expression("dev@{upstream}"), None);
+    assert_eq!(parse_date_expression("main~1"), None);
+}
+
+/// Where a fetched pull request is kept, out of the way of the user's branches.
+fn get_pull_request_ref(number: u32) -> String {
+    return format!("refs/boxedmino/pull/{number}");
+}
+
+fn classify(repo_path: &str, input: &str, commit: &str) -> RefKind {
+    let full_name = git::full_ref_name(repo_path, input).unwrap_or_default();
+
+    if full_name.starts_with("refs/tags/") {
+        return RefKind::Tag;
+    }
+    if full_name.starts_with("refs/heads/") {
+        return RefKind::Branch;
+    }
+    if full_name.starts_with("refs/remotes/") {
+        return RefKind::RemoteBranch;
+    }
+    if input.len() >= 4 && commit.starts_with(&input.to_lowercase()) {
+        return RefKind::Commit;
+    }
+
+    return RefKind::Expression;
+}
+
+/// Finds the commit `input` points to.
+///
+/// Pull requests are fetched from the `origin` remote if `fetch` is set,
+/// and otherwise only resolved if they were fetched before.
+pub fn resolve(repo_path: &str, input: &str, fetch: bool) -> Result<Resolution, String> {
+    let input = input.trim();
+
+    if input.is_empty() {
+        return Err("No version given".to_string());
+    }
+
+    if let Some((revision, date)) = parse_date_expression(input) {
+        let commit = git::last_commit_before(repo_path, revision, date)
+            .ok_or(format!("'{revision}' has no commit before {date} in the game repository"))?;
+
+        let (subject, date) = git::commit_summary(repo_path, &commit)
+            .unwrap_or_default();
+
+        return Ok(Resolution {
+            input: input.to_string(),
+            kind: RefKind::Date,
+            commit,
+            subject,
+            date,
+        });
+    }
+
+    let (revision, kind) = match parse_pull_request(input) {
+        Some(number) => {
+            let local_ref = get_pull_request_ref(number);
+
+            if fetch {
+                let refspec = format!("+pull/{number}/head:{local_ref}");
+
+                transfer::fetch(repo_path, PULL_REQUEST_REMOTE, &[&refspec], ProgressStyle::new(false))
+                    .map_err(|e| format!("Failed to fetch pull request #{number} from {PULL_REQUEST_REMOTE}: {e}"))?;
+            }
+
+            (local_ref, Some(RefKind::PullRequest))
+        }
+        None => (input.to_string(), None),
+    };
+
+    let commit = match git::resolve_commit(repo_path, &revision) {
+        Some(commit) => commit,
+        None if kind == Some(RefKind::PullRequest) => {
+            return Err(format!("{input} has not been fetched from {PULL_REQUEST_REMOTE} yet"));
+        }
+        None => return Err(format!("'{input}' is not a tag, branch, commit or other revision in the game repository")),
+    };
+
+    let (subject, date) = git::commit_summary(repo_path, &commit)
+        .unwrap_or_default();
+
+    return Ok(Resolution {
+        input: input.to_string(),
+        kind: kind.unwrap_or_else(|| classify(repo_path, input, &commit)),
+        commit,
+        subject,
+        date,
+    });
+}
+
+/// Runs `boxedmino resolve`, returning the exit code.
+pub fn run(repo_path: &str, input: &str, fetch: bool) -> i32 {
+    let resolution = match resolve(repo_path, input, fetch) {
+        Ok(resolution) => resolution,
+        Err(e) if !fetch && parse_pull_request(input.trim()).is_some() => {
+            eprintln!("{e}. Pass --fetch to fetch it.");
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    if output::is_json() {
+        output::print_json(&resolution);
+    } else {
+        println!("{resolution}");
+    }
+
+    return 0;
+}
+
+#[test]
+fn test_resolve() {
+    let scratch = git::ScratchRepo::new("refs");
+    let repo = scratch.path_str();
+    // Commits are dated, so resolving dates does not depend on when the test runs
+    let git = |args: &[&str]| scratch.git_at("2023-01-01T00:00:00Z", args);
+
+    scratch.git_at("2022-01-01T00:00:00Z", &["commit", "--quiet", "--allow-empty", "--message", "first"]);
+    git(&["tag", "0.1.0"]);
+    git(&["commit", "--quiet", "--allow-empty", "--message", "second"]);
+    git(&["branch", "dev"]);
+
+    let first = git::resolve_commit(&repo, "0.1.0").unwrap();
+
+    let tag = resolve(&repo, "0.1.0", false).unwrap();
+    assert_eq!((tag.kind, tag.subject.as_str()), (RefKind::Tag, "first"));
+
+    assert_eq!(resolve(&repo, "dev", false).unwrap().kind, RefKind::Branch);
+    assert_eq!(resolve(&repo, &first[..8], false).unwrap().kind, RefKind::Commit);
+
+    let relative = resolve(&repo, "main~1", false).unwrap();
+    assert_eq!((relative.kind, relative.commit), (RefKind::Expression, first.clone()));
+
+    // The reflog only knows the commits made just now
+    let dated = resolve(&repo, "@{2022-03-01}", false).unwrap();
+    assert_eq!((dated.kind, dated.commit, dated.date.as_str()), (RefKind::Date, first.clone(), "2022-01-01"));
+    assert_eq!(resolve(&repo, "main@{2022-03-01}", false).unwrap().commit, first);
+    assert_eq!(resolve(&repo, "main@{2023-03-01}", false).unwrap().subject, "second");
+    assert!(resolve(&repo, "main@{2021-03-01}", false).is_err());
+
+    assert!(resolve(&repo, "pull/1/head", false).is_err());
+    assert!(resolve(&repo, "nothing", false).is_err());
+}
@@ -0,0 +1,30 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use crate::slint_types::RepoRepairDialog;
+use slint::ComponentHandle;
+
+/// Shows what is wrong with the repository at `repo_path`
+/// and asks whether to repair it.
+pub fn ask_repair(repo_path: &str, problems: &str) -> Result<bool, slint::PlatformError> {
+    let dialog = RepoRepairDialog::new()?;
+    let confirmed = Rc::new(Cell::new(false));
+
+    dialog.set_repo_path(repo_path.into());
+    dialog.set_problems(problems.into());
+
+    let weak = dialog.as_weak();
+    let confirmed_clone = confirmed.clone();
+    dialog.on_repair(move || {
+        confirmed_clone.set(true);
+        weak.unwrap().window().hide().unwrap();
+    });
+
+    let weak = dialog.as_weak();
+    dialog.on_ignore(move || {
+        weak.unwrap().window().hide().unwrap();
+    });
+
+    dialog.run()?;
+
+    return Ok(confirmed.get());
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::conf::Config;
+use crate::dirs::paths;
+use crate::error_window;
+use crate::game;
+use crate::git::{self, HeadRef};
+use crate::info;
+use crate::repair_window;
+
+/// What the game repository had checked out before a run switched versions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct RememberedHead {
+    repo_path: String,
+    head: HeadRef,
+}
+
+/// Records the HEAD to return to after a run, in case Boxedmino is interrupted.
+pub fn remember_head(repo_path: &str, head: &HeadRef) {
+    let path = paths::get_original_head_path();
+
+    let remembered = RememberedHead {
+        repo_path: repo_path.to_string(),
+        head: head.clone(),
+    };
+
+    let contents = serde_json::to_string_pretty(&remembered)
+        .expect("Failed to serialize original HEAD");
+
+    let result = fs::create_dir_all(path.parent().unwrap_or(&path))
+        .and_then(|_| fs::write(&path, contents));
+
+    if let Err(e) = result {
+        eprintln!("Failed to record the checked out version at {}: {e}", path.to_string_lossy());
+    }
+}
+
+/// Called once the run has returned to the remembered HEAD.
+pub fn forget_head() {
+    let _ = fs::remove_file(paths::get_original_head_path());
+}
+
+/// The HEAD a run of `repo_path` was meant to return to, if it never did.
+fn get_remembered_head(repo_path: &str) -> Option<HeadRef> {
+    let contents = fs::read_to_string(paths::get_original_head_path()).ok()?;
+    let remembered: RememberedHead = serde_json::from_str(&contents).ok()?;
+
+    if remembered.repo_path != repo_path {
+        return None;
+    }
+
+    return Some(remembered.head);
+}
+
+/// A git operation that stopped halfway, e.g. because of a conflict.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+}
+
+impl Operation {
+    const ALL: [Operation; 5] = [
+        Operation::Merge,
+        Operation::Rebase,
+        Operation::CherryPick,
+        Operation::Revert,
+        Operation::Bisect,
+    ];
+
+    /// Files in the `.git` directory that exist while the operation is in progress.
+    fn state_files(&self) -> &'static [&'static str] {
+        return match self {
+            Operation::Merge => &["MERGE_HEAD"],
+            Operation::Rebase => &["rebase-merge", "rebase-apply"],
+            Operation::CherryPick => &["CHERRY_PICK_HEAD"],
+            Operation::Revert => &["REVERT_HEAD"],
+            Operation::Bisect => &["BISECT_LOG"],
+        };
+    }
+
+    fn abort_args(&self) -> &'static [&'static str] {
+        return match self {
+            Operation::Merge => &["merge", "--abort"],
+            Operation::Rebase => &["rebase", "--abort"],
+            Operation::CherryPick => &["cherry-pick", "--abort"],
+            Operation::Revert => &["revert", "--abort"],
+            Operation::Bisect => &["bisect", "reset"],
+        };
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Operation::Merge => write!(f, "merge"),
+            Operation::Rebase => write!(f, "rebase"),
+            Operation::CherryPick => write!(f, "cherry-pick"),
+            Operation::Revert => write!(f, "revert"),
+            Operation::Bisect => write!(f, "bisect"),
+        };
+    }
+}
+
+/// Something that would get in the way of switching versions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// `conf.lua` still contains the sandboxing code
+    Injected,
+    /// Other changes, as listed by `git status --porcelain`
+    UncommittedChanges(Vec<String>),
+    /// A run switched versions and never returned to this HEAD
+    InterruptedRun(HeadRef),
+    /// The HEAD is detached at a commit, and the default branch can be checked out instead
+    DetachedHead {
+        commit: String,
+        branch: String,
+    },
+    OperationInProgress(Operation),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Problem::Injected => write!(
+                f,
+                "conf.lua still contains Boxedmino's sandboxing code, as if the game was interrupted. It will be removed."
+            ),
+            Problem::UncommittedChanges(files) => write!(
+                f,
+                "There are uncommitted changes:\n  {}\nThey will be stashed; use `git stash pop` to get them back.",
+                files.join("\n  ")
+            ),
+            Problem::InterruptedRun(head) => write!(
+                f,
+                "A previous run switched versions and did not switch back. The repository will return to {head}."
+            ),
+            Problem::DetachedHead { commit, branch } => write!(
+                f,
+                "The repository is on commit {} instead of a branch. The `{branch}` branch will be checked out.",
+                &commit[..commit.len().min(10)]
+            ),
+            Problem::OperationInProgress(operation) => write!(
+                f,
+                "A {operation} is in progress. It will be aborted."
+            ),
+        };
+    }
+}
+
+/// Whether the only change to `conf.lua` is the injected code.
+fn is_only_injected(repo_path: &str) -> bool {
+    let contents = match fs::read_to_string(Path::new(repo_path).join("conf.lua")) {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+
+    let original = git::show_file(repo_path, "HEAD", "conf.lua");
+
+    return original.is_some() && game::remove_injection(&contents) == original;
+}
+
+/// Looks for anything left behind by an interrupted run or by the user.
+pub fn check(repo_path: &str) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = Vec::new();
+
+    if let Some(git_dir) = git::git_dir(repo_path) {
+        for operation in Operation::ALL {
+            let in_progress = operation
+                .state_files()
+                .iter()
+                .any(|file| git_dir.join(file).exists());
+
+            if in_progress {
+                problems.push(Problem::OperationInProgress(operation));
+            }
+        }
+    }
+
+    let conf_lua = fs::read_to_string(Path::new(repo_path).join("conf.lua"))
+        .unwrap_or_default();
+
+    let injected = game::is_injected(&conf_lua);
+
+    if injected {
+        problems.push(Problem::Injected);
+    }
+
+    let only_injected = injected && is_only_injected(repo_path);
+
+    let changes: Vec<String> = git::changed_files(repo_path)
+        .unwrap_or_default()
+        .into_iter()
+        // `git status --porcelain` lines look like ` M conf.lua`
+        .filter(|line| !(only_injected && line.get(3..) == Some("conf.lua")))
+        .collect();
+
+    if !changes.is_empty() {
+        problems.push(Problem::UncommittedChanges(changes));
+    }
+
+    let head = git::head_ref(repo_path);
+
+    match (get_remembered_head(repo_path), head) {
+        // Interrupted after switching back
+        (Some(remembered), Some(head)) if remembered == head => {}
+        (Some(remembered), _) => problems.push(Problem::InterruptedRun(remembered)),
+        (None, Some(HeadRef::Detached(commit))) => {
+            if let Some(branch) = git::default_branch(repo_path) {
+                problems.push(Problem::DetachedHead { commit, branch });
+            }
+        }
+        (None, _) => {}
+    }
+
+    return problems;
+}
+
+/// Removes the injected code from `conf.lua`, keeping the user's own changes if possible.
+fn remove_injection(repo_path: &str) -> Result<(), String> {
+    let path = Path::new(repo_path).join("conf.lua");
+
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read conf.lua: {e}"))?;
+
+    return match game::remove_injection(&contents) {
+        Some(original) => fs::write(&path, original)
+            .map_err(|e| format!("Failed to write conf.lua: {e}")),
+        // Injected by another version, so there is no telling where it ends
+        None => git::run_checked(repo_path, &["restore", "--", "conf.lua"]).map(|_| ()),
+    };
+}
+
+/// Fixes the given problems, in an order that keeps the user's work.
+/// Returns a description of each step taken.
+pub fn repair(repo_path: &str, problems: &[Problem]) -> Result<Vec<String>, String> {
+    let mut steps: Vec<String> = Vec::new();
+
+    for problem in problems {
+        if let Problem::OperationInProgress(operation) = problem {
+            git::run_checked(repo_path, operation.abort_args())?;
+            steps.push(format!("Aborted the {operation}"));
+        }
+    }
+
+    if problems.contains(&Problem::Injected) {
+        remove_injection(repo_path)?;
+        steps.push("Removed the sandboxing code from conf.lua".to_string());
+    }
+
+    if problems.iter().any(|problem| matches!(problem, Problem::UncommittedChanges(_))) {
+        let message = format!(
+            "Boxedmino: changes found on {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M")
+        );
+        git::run_checked(repo_path, &["stash", "push", "--include-untracked", "--message", &message])?;
+        steps.push(format!("Stashed your changes as \"{message}\""));
+    }
+
+    for problem in problems {
+        match problem {
+            Problem::InterruptedRun(head) => {
+                git::run_checked(repo_path, &["checkout", head.revision()])?;
+                forget_head();
+                steps.push(format!("Returned to {head}"));
+            }
+            Problem::DetachedHead { branch, .. } => {
+                git::run_checked(repo_path, &["checkout", branch])?;
+                steps.push(format!("Checked out the `{branch}` branch"));
+            }
+            _ => {}
+        }
+    }
+
+    return Ok(steps);
+}
+
+/// Asks on the terminal whether to repair. Defaults to no.
+fn ask_in_terminal() -> bool {
+    if !io::stdin().is_terminal() {
+        eprintln!("Not repairing, as there is no terminal to ask in.");
+        return false;
+    }
+
+    eprint!("Repair the repository? [y/N] ");
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    return matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+}
+
+/// Checks the game repository on startup and offers to repair it.
+pub fn check_and_repair(cfg: &Config) {
+    let repo_path = &cfg.game_repo_path;
+
+    // The last run was interrupted after switching back
+    if let (Some(remembered), Some(head)) = (get_remembered_head(repo_path), git::head_ref(repo_path)) {
+        if remembered == head {
+            forget_head();
+        }
+    }
+
+    let problems = check(repo_path);
+
+    if problems.is_empty() {
+        return;
+    }
+
+    let description = problems
+        .iter()
+        .map(|problem| format!("- {problem}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let confirmed = if cfg.use_gui {
+        repair_window::ask_repair(repo_path, &description)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to open repair dialog: {e}");
+                false
+            })
+    } else {
+        eprintln!("The game repository at {repo_path} needs attention:\n{description}");
+        ask_in_terminal()
+    };
+
+    if !confirmed {
+        info!("Leaving the repository as it is; switching versions may fail.");
+        return;
+    }
+
+    match repair(repo_path, &problems) {
+        Ok(steps) => {
+            for step in steps {
+                info!("{step}");
+            }
+        }
+        Err(e) if cfg.use_gui => {
+            error_window::open_safe(
+                Some("Boxedmino - Repository repair".to_string()),
+                Some("Failed to repair the game repository".to_string()),
+                Some(e)
+            );
+        }
+        Err(e) => {
+            eprintln!("Failed to repair the game repository: {e}");
+        }
+    }
+}
+
+#[test]
+fn test_check_and_repair() {
+    let scratch = git::ScratchRepo::new("repo_health");
+    let root = &scratch.path;
+    let repo = scratch.path_str();
+
+    fs::write(root.join("conf.lua"), "original\n").unwrap();
+    scratch.git(&["add", "."]);
+    scratch.git(&["commit", "--quiet", "--message", "first"]);
+    scratch.git(&["commit", "--quiet", "--allow-empty", "--message", "second"]);
+    scratch.git(&["checkout", "--quiet", "HEAD~1"]);
+
+    assert_eq!(check(&repo), vec![Problem::DetachedHead {
+        commit: git::resolve_commit(&repo, "HEAD").unwrap(),
+        branch: "main".to_string(),
+    }]);
+
+    // An interrupted run
+    fs::write(root.join("conf.lua"), format!("{}original\n", game::injected_script(true, None))).unwrap();
+    fs::write(root.join("notes.txt"), "mine").unwrap();
+
+    let problems = check(&repo);
+    assert!(problems.contains(&Problem::Injected));
+    assert!(problems.contains(&Problem::UncommittedChanges(vec!["?? notes.txt".to_string()])));
+
+    repair(&repo, &problems).unwrap();
+
+    assert_eq!(check(&repo), vec![]);
+    assert_eq!(fs::read_to_string(root.join("conf.lua")).unwrap(), "original\n");
+    assert_eq!(git::current_branch(&repo).as_deref(), Some("main"));
+    assert!(!root.join("notes.txt").exists());
+}
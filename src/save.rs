@@ -0,0 +1,8 @@
+//! Techmino save directories: finding them and reading them.
+
+pub mod cli;
+pub mod convert;
+pub mod data;
+pub mod format;
+pub mod import;
+pub mod location;
@@ -0,0 +1,114 @@
+use std::path::Path;
+use serde_json::{json, Value};
+use crate::output;
+use crate::save::format::{self, Change, FileDiff};
+use crate::SaveAction;
+
+fn show(file: &Path) -> i32 {
+    let value = match format::read_file(file) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    if output::is_json() {
+        output::print_json(&value);
+        return 0;
+    }
+
+    for (key, value) in format::flatten(&value) {
+        println!("{key} = {value}");
+    }
+
+    return 0;
+}
+
+fn print_changes(changes: &[Change]) {
+    for change in changes {
+        println!("  {change}");
+    }
+}
+
+fn change_to_json(change: &Change) -> Value {
+    return match change {
+        Change::Added(key, value) => json!({ "change": "added", "key": key, "new": value }),
+        Change::Removed(key, value) => json!({ "change": "removed", "key": key, "old": value }),
+        Change::Changed(key, old, new) => json!({ "change": "changed", "key": key, "old": old, "new": new }),
+    };
+}
+
+fn file_diff_to_json(file: &Path, file_diff: &FileDiff) -> Value {
+    let file = file.to_string_lossy();
+
+    return match file_diff {
+        FileDiff::OnlyInOld => json!({ "file": file, "status": "only_in_old" }),
+        FileDiff::OnlyInNew => json!({ "file": file, "status": "only_in_new" }),
+        FileDiff::Binary => json!({ "file": file, "status": "binary" }),
+        FileDiff::Changed(changes) => json!({
+            "file": file,
+            "status": "changed",
+            "changes": changes.iter().map(change_to_json).collect::<Vec<Value>>(),
+        }),
+    };
+}
+
+fn diff(old: &Path, new: &Path) -> i32 {
+    if old.is_dir() && new.is_dir() {
+        let diffs = format::diff_dirs(old, new);
+
+        if output::is_json() {
+            let files: Vec<Value> = diffs
+                .iter()
+                .map(|(file, file_diff)| file_diff_to_json(file, file_diff))
+                .collect();
+            output::print_json(&files);
+            return 0;
+        }
+
+        for (file, file_diff) in &diffs {
+            let file = file.to_string_lossy();
+
+            match file_diff {
+                FileDiff::OnlyInOld => println!("Only in {}: {file}", old.to_string_lossy()),
+                FileDiff::OnlyInNew => println!("Only in {}: {file}", new.to_string_lossy()),
+                FileDiff::Binary => println!("{file}: contents differ"),
+                FileDiff::Changed(changes) => {
+                    println!("{file}:");
+                    print_changes(changes);
+                }
+            }
+        }
+
+        return 0;
+    }
+
+    let (old_value, new_value) = match (format::read_file(old), format::read_file(new)) {
+        (Ok(old_value), Ok(new_value)) => (old_value, new_value),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    let changes = format::diff(&old_value, &new_value);
+
+    if output::is_json() {
+        let changes: Vec<Value> = changes.iter().map(change_to_json).collect();
+        output::print_json(&changes);
+        return 0;
+    }
+
+    print_changes(&changes);
+
+    return 0;
+}
+
+/// Runs a `boxedmino save` subcommand, returning the exit code.
+pub fn run(action: &SaveAction) -> i32 {
+    return match action {
+        SaveAction::Show { file } => show(Path::new(file)),
+        SaveAction::Diff { old, new } => diff(Path::new(old), Path::new(new)),
+    };
+}
@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+use crate::save::data::SaveData;
+use crate::save::format::{self, Encoding};
+
+/// A game version, as in the tag `v0.17.0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GameVersion(pub u32, pub u32, pub u32);
+
+impl GameVersion {
+    /// Parses a tag such as `v0.17.0`, `0.15.1` or `V0.16.0-beta`.
+    pub fn parse(tag: &str) -> Option<GameVersion> {
+        let tag = tag.trim().trim_start_matches(['v', 'V']);
+        let tag = tag.split(['-', '+', '_']).next()?;
+
+        let mut parts = tag.split('.').map(|part| part.parse::<u32>());
+
+        let major = parts.next()?.ok()?;
+        let minor = parts.next()?.ok()?;
+        let patch = match parts.next() {
+            Some(patch) => patch.ok()?,
+            None => 0
+        };
+
+        return Some(GameVersion(major, minor, patch));
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}.{}.{}", self.0, self.1, self.2);
+    }
+}
+
+#[test]
+fn test_parse_game_version() {
+    assert_eq!(GameVersion::parse("v0.17.0"), Some(GameVersion(0, 17, 0)));
+    assert_eq!(GameVersion::parse("0.15"), Some(GameVersion(0, 15, 0)));
+    assert_eq!(GameVersion::parse("V0.16.2-beta"), Some(GameVersion(0, 16, 2)));
+    assert_eq!(GameVersion::parse("pre-release"), None);
+}
+
+/// What a conversion did to a save.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConversionReport {
+    /// Names of the transformers that changed the save, and in which direction
+    pub applied: Vec<String>,
+    /// Files and fields that the target version has no place for, e.g. `conf/settings: skinSet`
+    pub dropped: Vec<String>,
+}
+
+/// Save data being converted, with its data files parsed when a transformer first needs them.
+struct SaveModel<'a> {
+    data: &'a mut SaveData,
+    /// The parsed files by path, as they were read and as they are now
+    parsed: BTreeMap<PathBuf, (Value, Value, Encoding)>,
+}
+
+impl<'a> SaveModel<'a> {
+    fn new(data: &'a mut SaveData) -> Self {
+        return Self {
+            data,
+            parsed: BTreeMap::new(),
+        };
+    }
+
+    /// The top-level table of a data file, or `None` if the file is missing or unreadable.
+    fn table(&mut self, path: &str) -> Option<&mut Map<String, Value>> {
+        let path = Path::new(path);
+
+        if !self.parsed.contains_key(path) {
+            let contents = std::str::from_utf8(self.data.files.get(path)?).ok()?;
+            let (value, encoding) = format::parse_with_encoding(contents).ok()?;
+            self.parsed.insert(path.to_path_buf(), (value.clone(), value, encoding));
+        }
+
+        return match self.parsed.get_mut(path) {
+            Some((_, Value::Object(table), _)) => Some(table),
+            _ => None
+        };
+    }
+
+    /// Writes back the files that were changed. Untouched files keep their exact contents.
+    fn finish(self) {
+        for (path, (original, value, encoding)) in self.parsed {
+            if value != original {
+                self.data.files.insert(path, format::serialize(&value, encoding).into_bytes());
+            }
+        }
+    }
+}
+
+/// A change to the save format made by a game version.
+///
+/// Both directions only touch data still in the format they convert from, and return
+/// whether they changed anything, so running them on a save already converted does nothing.
+struct Transformer {
+    name: &'static str,
+    /// The first game version using the new format
+    since: GameVersion,
+    upgrade: fn(&mut SaveModel, &mut Vec<String>) -> bool,
+    downgrade: fn(&mut SaveModel, &mut Vec<String>) -> bool,
+}
+
+/// Every save format change, oldest first.
+///
+/// `TRANSFORMERS[n]` upgrades a save from format `n` to `n + 1`.
+/// They mirror the updates the game itself makes to older data on startup,
+/// which older versions cannot undo. To support another change, add a transformer at the end.
+const TRANSFORMERS: [Transformer; 5] = [
+    Transformer {
+        name: "conf directory",
+        since: GameVersion(0, 15, 0),
+        upgrade: upgrade_conf_dir,
+        downgrade: downgrade_conf_dir,
+    },
+    Transformer {
+        name: "background setting",
+        since: GameVersion(0, 16, 0),
+        upgrade: upgrade_background,
+        downgrade: downgrade_background,
+    },
+    Transformer {
+        name: "voice pack setting",
+        since: GameVersion(0, 16, 0),
+        upgrade: upgrade_voice_pack,
+        downgrade: downgrade_voice_pack,
+    },
+    Transformer {
+        name: "skin set setting",
+        since: GameVersion(0, 16, 0),
+        upgrade: upgrade_skin_set,
+        downgrade: downgrade_skin_set,
+    },
+    Transformer {
+        name: "10-line sprint rank",
+        since: GameVersion(0, 17, 0),
+        upgrade: upgrade_sprint_rank,
+        downgrade: downgrade_sprint_rank,
+    },
+];
+
+/// Before 0.15, data files were kept at the top level as `*.dat`.
+const CONF_DIR_RENAMES: [(&str, &str); 5] = [
+    ("settings.dat", "conf/settings"),
+    ("key.dat", "conf/key"),
+    ("virtualkey.dat", "conf/virtualkey"),
+    ("unlock.dat", "conf/unlock"),
+    ("data.dat", "conf/data"),
+];
+
+/// Files introduced with the `conf` directory that older versions have no equivalent for.
+const CONF_DIR_ONLY_FILES: [&str; 2] = ["conf/vkSave1", "conf/vkSave2"];
+
+const SETTINGS_PATH: &str = "conf/settings";
+const RANKS_PATH: &str = "conf/unlock";
+
+fn upgrade_conf_dir(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    let mut changed = false;
+
+    for (old, new) in CONF_DIR_RENAMES {
+        // A leftover from before the game moved its files is older than the moved file
+        if model.data.files.contains_key(Path::new(new)) {
+            changed |= model.data.remove(old);
+        } else {
+            changed |= model.data.rename(old, new);
+        }
+    }
+
+    return changed;
+}
+
+fn downgrade_conf_dir(model: &mut SaveModel, dropped: &mut Vec<String>) -> bool {
+    let mut changed = false;
+
+    for (old, new) in CONF_DIR_RENAMES {
+        changed |= model.data.rename(new, old);
+    }
+
+    for path in CONF_DIR_ONLY_FILES {
+        if model.data.remove(path) {
+            dropped.push(path.to_string());
+            changed = true;
+        }
+    }
+
+    return changed;
+}
+
+/// `bg` went from on or off to `'on'`, `'off'` or `'custom'`.
+fn upgrade_background(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    let settings = match model.table(SETTINGS_PATH) {
+        Some(settings) => settings,
+        None => return false
+    };
+
+    return match settings.get("bg") {
+        Some(Value::Bool(on)) => {
+            let bg = if *on { "on" } else { "off" };
+            settings.insert("bg".to_string(), Value::from(bg));
+            true
+        }
+        _ => false
+    };
+}
+
+fn downgrade_background(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    let settings = match model.table(SETTINGS_PATH) {
+        Some(settings) => settings,
+        None => return false
+    };
+
+    return match settings.get("bg") {
+        // A custom background is still a background
+        Some(Value::String(bg)) => {
+            let on = bg != "off";
+            settings.insert("bg".to_string(), Value::Bool(on));
+            true
+        }
+        _ => false
+    };
+}
+
+/// Moves a field of a data file, returning whether it existed.
+fn rename_field(model: &mut SaveModel, path: &str, from: &str, to: &str) -> bool {
+    let table = match model.table(path) {
+        Some(table) => table,
+        None => return false
+    };
+
+    return match table.remove(from) {
+        Some(value) => {
+            table.insert(to.to_string(), value);
+            true
+        }
+        None => false
+    };
+}
+
+/// `cv` was renamed to `vocPack`.
+fn upgrade_voice_pack(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    return rename_field(model, SETTINGS_PATH, "cv", "vocPack");
+}
+
+fn downgrade_voice_pack(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    return rename_field(model, SETTINGS_PATH, "vocPack", "cv");
+}
+
+/// The skin set used to be chosen by number, and is now chosen by name.
+/// The game resets numbered sets to the default one.
+const DEFAULT_SKIN_SET: &str = "crystal_scf";
+
+fn upgrade_skin_set(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    let settings = match model.table(SETTINGS_PATH) {
+        Some(settings) => settings,
+        None => return false
+    };
+
+    if !settings.get("skinSet").is_some_and(Value::is_number) {
+        return false;
+    }
+
+    settings.insert("skinSet".to_string(), Value::from(DEFAULT_SKIN_SET));
+    return true;
+}
+
+/// Names have no matching number, so older versions fall back to their default set.
+fn downgrade_skin_set(model: &mut SaveModel, dropped: &mut Vec<String>) -> bool {
+    let settings = match model.table(SETTINGS_PATH) {
+        Some(settings) => settings,
+        None => return false
+    };
+
+    if !settings.get("skinSet").is_some_and(Value::is_string) {
+        return false;
+    }
+
+    settings.remove("skinSet");
+    dropped.push(format!("{SETTINGS_PATH}: skinSet"));
+    return true;
+}
+
+/// The 10-line sprint rank moved from `sprint_10` to `sprint_10l`.
+fn upgrade_sprint_rank(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    return rename_field(model, RANKS_PATH, "sprint_10", "sprint_10l");
+}
+
+fn downgrade_sprint_rank(model: &mut SaveModel, _: &mut Vec<String>) -> bool {
+    return rename_field(model, RANKS_PATH, "sprint_10l", "sprint_10");
+}
+
+/// The save format a game version uses.
+fn get_format(version: GameVersion) -> usize {
+    return TRANSFORMERS
+        .iter()
+        .take_while(|transformer| transformer.since <= version)
+        .count();
+}
+
+/// Runs one direction of a transformer, saving the files it changed.
+fn apply(
+    data: &mut SaveData,
+    step: fn(&mut SaveModel, &mut Vec<String>) -> bool,
+    dropped: &mut Vec<String>
+) -> bool {
+    let mut model = SaveModel::new(data);
+    let changed = step(&mut model, dropped);
+    model.finish();
+
+    return changed;
+}
+
+/// Upgrades or downgrades save data to the format the target version expects.
+///
+/// The format of the save is not known up front, since only some of its files may be imported.
+/// Newer formats are undone first, then older ones are brought up to date, and only the
+/// transformers that found something to change are reported.
+pub fn convert(data: &mut SaveData, target: GameVersion) -> ConversionReport {
+    let mut report = ConversionReport::default();
+    let target_format = get_format(target);
+
+    for transformer in TRANSFORMERS[target_format..].iter().rev() {
+        if apply(data, transformer.downgrade, &mut report.dropped) {
+            report.applied.push(format!("downgraded from the {} format of {}", transformer.name, transformer.since));
+        }
+    }
+
+    for transformer in &TRANSFORMERS[..target_format] {
+        if apply(data, transformer.upgrade, &mut report.dropped) {
+            report.applied.push(format!("upgraded to the {} format of {}", transformer.name, transformer.since));
+        }
+    }
+
+    return report;
+}
+
+#[test]
+fn test_convert() {
+    let mut data = SaveData::default();
+    data.files.insert("conf/settings".into(), b"{}".to_vec());
+    data.files.insert("conf/vkSave1".into(), b"{}".to_vec());
+    data.files.insert("record/sprint_40l.rec".into(), b"{}".to_vec());
+
+    let original = data.clone();
+
+    let report = convert(&mut data, GameVersion(0, 17, 0));
+    assert_eq!(report, ConversionReport::default());
+    assert_eq!(data, original);
+
+    let report = convert(&mut data, GameVersion(0, 14, 5));
+    assert_eq!(report.applied.len(), 1);
+    assert_eq!(report.dropped, vec!["conf/vkSave1"]);
+    assert!(data.files.contains_key(Path::new("settings.dat")));
+    assert!(data.files.contains_key(Path::new("record/sprint_40l.rec")));
+
+    convert(&mut data, GameVersion(0, 15, 0));
+    assert!(data.files.contains_key(Path::new("conf/settings")));
+    assert!(!data.files.contains_key(Path::new("settings.dat")));
+
+    // Without any settings or unlocks, there is nothing to tell the format by
+    let mut records = SaveData::default();
+    records.files.insert("record/sprint_40l.rec".into(), b"{}".to_vec());
+    assert_eq!(convert(&mut records, GameVersion(0, 17, 0)), ConversionReport::default());
+    assert_eq!(convert(&mut records, GameVersion(0, 14, 0)), ConversionReport::default());
+}
+
+#[test]
+fn test_convert_settings() {
+    let old_settings = include_str!("fixtures/settings_v0.14.dat");
+
+    let mut data = SaveData::default();
+    data.files.insert("settings.dat".into(), old_settings.as_bytes().to_vec());
+    data.files.insert("unlock.dat".into(), b"return{sprint_10=3,sprint_40l=1,}".to_vec());
+
+    let report = convert(&mut data, GameVersion(0, 17, 0));
+    assert_eq!(report.applied.len(), 5);
+    assert_eq!(report.dropped, Vec::<String>::new());
+    assert!(!data.files.contains_key(Path::new("settings.dat")));
+
+    let read = |data: &SaveData, path: &str| format::parse(
+        std::str::from_utf8(&data.files[Path::new(path)]).unwrap()
+    ).unwrap();
+
+    let settings = read(&data, "conf/settings");
+    assert_eq!(settings["bg"], "on");
+    assert_eq!(settings["vocPack"], "miya");
+    assert_eq!(settings["skinSet"], DEFAULT_SKIN_SET);
+    assert!(settings.get("cv").is_none());
+    assert_eq!(settings["das"], 10);
+    assert_eq!(settings["VKAlpha"], 0.3);
+    assert_eq!(read(&data, "conf/unlock")["sprint_10l"], 3);
+
+    let report = convert(&mut data, GameVersion(0, 14, 0));
+    assert_eq!(report.applied.len(), 5);
+    assert_eq!(report.dropped, vec!["conf/settings: skinSet"]);
+
+    let settings = read(&data, "settings.dat");
+    let mut expected = format::parse(old_settings).unwrap();
+    expected.as_object_mut().unwrap().remove("skinSet");
+    assert_eq!(settings, expected);
+    assert_eq!(read(&data, "unlock.dat")["sprint_10"], 3);
+}
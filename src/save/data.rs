@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Files read from a save directory, kept in memory so they can be
+/// converted before being written to another save directory.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SaveData {
+    /// File contents by path relative to the save directory
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+fn read_recursive(root: &Path, relative: &Path, files: &mut BTreeMap<PathBuf, Vec<u8>>) -> io::Result<()> {
+    let path = root.join(relative);
+
+    if path.is_dir() {
+        for entry in fs::read_dir(&path)? {
+            read_recursive(root, &relative.join(entry?.file_name()), files)?;
+        }
+    } else if path.is_file() {
+        files.insert(relative.to_path_buf(), fs::read(&path)?);
+    }
+
+    return Ok(());
+}
+
+fn remove_recursive(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        return fs::remove_dir_all(path);
+    }
+
+    if path.exists() {
+        return fs::remove_file(path);
+    }
+
+    return Ok(());
+}
+
+impl SaveData {
+    /// Reads the given files and directories, relative to `root`.
+    /// Paths that do not exist are skipped.
+    pub fn read(root: &Path, paths: &[&str]) -> io::Result<Self> {
+        let mut files: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+
+        for path in paths {
+            read_recursive(root, Path::new(path), &mut files)?;
+        }
+
+        return Ok(Self { files });
+    }
+
+    /// Writes every file into `root`, after removing the given files and directories from it.
+    /// Returns the written files.
+    pub fn write(&self, root: &Path, replaced_paths: &[&str]) -> io::Result<Vec<PathBuf>> {
+        for path in replaced_paths {
+            remove_recursive(&root.join(path))?;
+        }
+
+        for (relative, contents) in &self.files {
+            let path = root.join(relative);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(path, contents)?;
+        }
+
+        return Ok(self.files.keys().cloned().collect());
+    }
+
+    /// Moves a file, returning whether it existed.
+    pub fn rename(&mut self, from: &str, to: &str) -> bool {
+        return match self.files.remove(Path::new(from)) {
+            Some(contents) => {
+                self.files.insert(PathBuf::from(to), contents);
+                true
+            }
+            None => false
+        };
+    }
+
+    /// Removes a file, returning whether it existed.
+    pub fn remove(&mut self, path: &str) -> bool {
+        return self.files.remove(Path::new(path)).is_some();
+    }
+}
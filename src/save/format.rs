@@ -0,0 +1,685 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+
+/// Reads a Lua table literal as written by Techmino, e.g. `return {sfx=8,["key"]={1,2}}`.
+///
+/// Tables with only the keys `1..n` become arrays, and other tables become objects.
+struct LuaParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl LuaParser {
+    fn new(contents: &str) -> Self {
+        return Self {
+            chars: contents.chars().collect(),
+            pos: 0,
+        };
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.pos).copied();
+    }
+
+    fn error(&self, message: &str) -> String {
+        return format!("{message} at character {}", self.pos);
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            while self.peek().is_some_and(char::is_whitespace) {
+                self.pos += 1;
+            }
+
+            if !self.chars[self.pos..].starts_with(&['-', '-']) {
+                return;
+            }
+
+            self.pos += 2;
+
+            if self.long_bracket_level().is_some() {
+                // An unterminated comment runs to the end of the file
+                if self.parse_long_string().is_err() {
+                    self.pos = self.chars.len();
+                }
+            } else {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            return true;
+        }
+
+        return false;
+    }
+
+    fn parse_word(&mut self) -> String {
+        let start = self.pos;
+
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+
+        return self.chars[start..self.pos].iter().collect();
+    }
+
+    /// The level of a long bracket starting here, e.g. 2 for `[==[`.
+    fn long_bracket_level(&self) -> Option<usize> {
+        if self.peek() != Some('[') {
+            return None;
+        }
+
+        let level = self.chars[self.pos + 1..]
+            .iter()
+            .take_while(|c| **c == '=')
+            .count();
+
+        return match self.chars.get(self.pos + 1 + level) {
+            Some('[') => Some(level),
+            _ => None
+        };
+    }
+
+    /// Reads a long string such as `[[text]]` or `[==[text]==]`.
+    fn parse_long_string(&mut self) -> Result<String, String> {
+        let level = self.long_bracket_level()
+            .ok_or_else(|| self.error("Expected a long string"))?;
+        self.pos += level + 2;
+
+        // A newline right after the opening bracket is not part of the string
+        if self.peek() == Some('\r') {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.pos += 1;
+        }
+
+        let closing: Vec<char> = std::iter::once(']')
+            .chain(std::iter::repeat_n('=', level))
+            .chain(std::iter::once(']'))
+            .collect();
+
+        let start = self.pos;
+
+        while !self.chars[self.pos..].starts_with(&closing) {
+            if self.pos >= self.chars.len() {
+                return Err(self.error("Unterminated long string"));
+            }
+            self.pos += 1;
+        }
+
+        let string = self.chars[start..self.pos].iter().collect();
+        self.pos += closing.len();
+
+        return Ok(string);
+    }
+
+    /// Reads up to `max_digits` digits in `radix`.
+    fn parse_digits(&mut self, radix: u32, max_digits: usize) -> Option<u32> {
+        let mut value: Option<u32> = None;
+
+        for _ in 0..max_digits {
+            let digit = match self.peek().and_then(|c| c.to_digit(radix)) {
+                Some(digit) => digit,
+                None => break
+            };
+
+            value = Some(value.unwrap_or(0) * radix + digit);
+            self.pos += 1;
+        }
+
+        return value;
+    }
+
+    /// Reads a quoted string. Lua strings are bytes, so escapes such as `\200`
+    /// are collected as bytes and only turned into text at the end.
+    fn parse_string(&mut self) -> Result<String, String> {
+        let quote = self.peek().ok_or_else(|| self.error("Expected a string"))?;
+        self.pos += 1;
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("Unterminated string"))?;
+            self.pos += 1;
+
+            match c {
+                c if c == quote => return Ok(String::from_utf8_lossy(&bytes).to_string()),
+                '\n' => return Err(self.error("Unterminated string")),
+                '\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("Unterminated string"))?;
+                    self.pos += 1;
+
+                    let byte = match escaped {
+                        'a' => 0x07,
+                        'b' => 0x08,
+                        'f' => 0x0c,
+                        'n' => b'\n',
+                        'r' => b'\r',
+                        't' => b'\t',
+                        'v' => 0x0b,
+                        // `\ddd`, as `%q` writes control characters
+                        '0'..='9' => {
+                            self.pos -= 1;
+                            let value = self.parse_digits(10, 3).unwrap_or(0);
+                            u8::try_from(value)
+                                .map_err(|_| self.error("Decimal escape too large"))?
+                        }
+                        'x' => {
+                            let start = self.pos;
+                            match self.parse_digits(16, 2) {
+                                Some(value) if self.pos == start + 2 => value as u8,
+                                _ => return Err(self.error("Invalid hexadecimal escape"))
+                            }
+                        }
+                        'u' => {
+                            if self.peek() != Some('{') {
+                                return Err(self.error("Expected `{` in Unicode escape"));
+                            }
+                            self.pos += 1;
+                            let c = self.parse_digits(16, 8)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("Invalid Unicode escape"))?;
+                            if self.peek() != Some('}') {
+                                return Err(self.error("Expected `}` in Unicode escape"));
+                            }
+                            self.pos += 1;
+                            bytes.extend(c.to_string().as_bytes());
+                            continue;
+                        }
+                        // Skips the following whitespace, including line breaks
+                        'z' => {
+                            while self.peek().is_some_and(char::is_whitespace) {
+                                self.pos += 1;
+                            }
+                            continue;
+                        }
+                        other => {
+                            // Includes escaped quotes, backslashes and line breaks
+                            bytes.extend(other.to_string().as_bytes());
+                            continue;
+                        }
+                    };
+
+                    bytes.push(byte);
+                }
+                c => bytes.extend(c.to_string().as_bytes())
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || ".-+".contains(c)) {
+            self.pos += 1;
+        }
+
+        let text: String = self.chars[start..self.pos].iter().collect();
+
+        if let Ok(integer) = text.parse::<i64>() {
+            return Ok(Value::from(integer));
+        }
+
+        return text.parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| self.error(&format!("Invalid number {text:?}")));
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        return match self.peek() {
+            Some('{') => self.parse_table(),
+            Some('"' | '\'') => Ok(Value::String(self.parse_string()?)),
+            Some('[') => Ok(Value::String(self.parse_long_string()?)),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '.' => self.parse_number(),
+            Some(c) if c.is_alphabetic() => match self.parse_word().as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "nil" => Ok(Value::Null),
+                word => Err(self.error(&format!("Unexpected {word:?}")))
+            },
+            Some(c) => Err(self.error(&format!("Unexpected {c:?}"))),
+            None => Err(self.error("Unexpected end of file"))
+        };
+    }
+
+    fn parse_table(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+
+        let mut entries: Vec<(String, Value)> = Vec::new();
+        let mut next_index = 1;
+
+        loop {
+            if self.eat('}') {
+                break;
+            }
+
+            self.skip_whitespace();
+
+            let key = if self.long_bracket_level().is_none() && self.eat('[') {
+                self.skip_whitespace();
+                let key = match self.parse_value()? {
+                    Value::String(key) => key,
+                    other => other.to_string()
+                };
+                if !self.eat(']') || !self.eat('=') {
+                    return Err(self.error("Expected `]=` after table key"));
+                }
+                key
+            } else {
+                let start = self.pos;
+                let word = self.parse_word();
+
+                if !word.is_empty() && self.eat('=') {
+                    word
+                } else {
+                    // A positional value
+                    self.pos = start;
+                    next_index += 1;
+                    (next_index - 1).to_string()
+                }
+            };
+
+            let value = self.parse_value()?;
+            entries.push((key, value));
+
+            if !self.eat(',') && !self.eat(';') {
+                if !self.eat('}') {
+                    return Err(self.error("Expected `,` or `}` in table"));
+                }
+                break;
+            }
+        }
+
+        let is_array = entries
+            .iter()
+            .enumerate()
+            .all(|(i, (key, _))| *key == (i + 1).to_string());
+
+        if is_array && !entries.is_empty() {
+            return Ok(Value::Array(entries.into_iter().map(|(_, value)| value).collect()));
+        }
+
+        return Ok(Value::Object(entries.into_iter().collect()));
+    }
+
+    fn parse(mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        if self.chars[self.pos..].starts_with(&['r', 'e', 't', 'u', 'r', 'n']) {
+            self.pos += "return".len();
+        }
+
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+
+        if self.pos < self.chars.len() {
+            return Err(self.error("Unexpected data after the table"));
+        }
+
+        return Ok(value);
+    }
+}
+
+/// How a data file is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    /// A Lua table returned by the file, as older versions write
+    Lua,
+}
+
+/// Parses a Techmino data file, which is either JSON or a Lua table.
+pub fn parse(contents: &str) -> Result<Value, String> {
+    return parse_with_encoding(contents).map(|(value, _)| value);
+}
+
+/// Like [`parse`], also telling how the file was written so it can be written back the same way.
+pub fn parse_with_encoding(contents: &str) -> Result<(Value, Encoding), String> {
+    if let Ok(value) = serde_json::from_str(contents) {
+        return Ok((value, Encoding::Json));
+    }
+
+    return LuaParser::new(contents)
+        .parse()
+        .map(|value| (value, Encoding::Lua));
+}
+
+/// Writes a data file in the given encoding, the inverse of [`parse_with_encoding`].
+pub fn serialize(value: &Value, encoding: Encoding) -> String {
+    return match encoding {
+        Encoding::Json => value.to_string(),
+        Encoding::Lua => {
+            let mut lua = String::from("return");
+            write_lua(value, 0, &mut lua);
+            lua
+        }
+    };
+}
+
+const LUA_KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if",
+    "in", "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+fn write_lua_string(string: &str, lua: &mut String) {
+    lua.push('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => lua.push_str("\\\""),
+            '\\' => lua.push_str("\\\\"),
+            '\n' => lua.push_str("\\n"),
+            '\r' => lua.push_str("\\r"),
+            // Three digits, so a following digit is not read as part of the escape
+            c if c.is_ascii_control() => lua.push_str(&format!("\\{:03}", c as u32)),
+            c => lua.push(c)
+        }
+    }
+
+    lua.push('"');
+}
+
+fn write_lua_key(key: &str, lua: &mut String) {
+    let is_name = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') &&
+        key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') &&
+        !LUA_KEYWORDS.contains(&key);
+
+    if is_name {
+        lua.push_str(key);
+    } else if key.parse::<i64>().is_ok() {
+        // Numeric keys such as `[5]` are read as strings
+        lua.push_str(&format!("[{key}]"));
+    } else {
+        lua.push('[');
+        write_lua_string(key, lua);
+        lua.push(']');
+    }
+}
+
+/// Writes a value like Techmino's `TABLE.dump`: one entry per line, indented with tabs.
+fn write_lua(value: &Value, depth: usize, lua: &mut String) {
+    match value {
+        Value::Null => lua.push_str("nil"),
+        Value::Bool(boolean) => lua.push_str(&boolean.to_string()),
+        Value::Number(number) => lua.push_str(&number.to_string()),
+        Value::String(string) => write_lua_string(string, lua),
+        Value::Array(array) => {
+            lua.push('{');
+            for (i, child) in array.iter().enumerate() {
+                if i > 0 {
+                    lua.push(',');
+                }
+                write_lua(child, depth + 1, lua);
+            }
+            lua.push('}');
+        }
+        Value::Object(object) => {
+            lua.push_str("{\n");
+            for (key, child) in object {
+                lua.push_str(&"\t".repeat(depth + 1));
+                write_lua_key(key, lua);
+                lua.push('=');
+                write_lua(child, depth + 1, lua);
+                lua.push_str(",\n");
+            }
+            lua.push_str(&"\t".repeat(depth));
+            lua.push('}');
+        }
+    }
+}
+
+pub fn read_file(path: &Path) -> Result<Value, String> {
+    let contents = fs::read(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.to_string_lossy()))?;
+
+    let contents = String::from_utf8(contents)
+        .map_err(|_| format!("{} is not a text file", path.to_string_lossy()))?;
+
+    return parse(&contents)
+        .map_err(|e| format!("{} is not a readable data file: {e}", path.to_string_lossy()));
+}
+
+#[test]
+fn test_parse_lua_table() {
+    let parsed = parse(r#"return {
+        -- Audio
+        sfx = 8, bgm=0.5,
+        ["skin"] = {1, 2, 3},
+        name = 'MrZ\'s',
+        locked = false;
+        [5] = nil,
+    }"#).unwrap();
+
+    assert_eq!(parsed, serde_json::json!({
+        "sfx": 8,
+        "bgm": 0.5,
+        "skin": [1, 2, 3],
+        "name": "MrZ's",
+        "locked": false,
+        "5": null,
+    }));
+
+    assert_eq!(parse("{\"sfx\":8}").unwrap(), serde_json::json!({ "sfx": 8 }));
+    assert!(parse("return {sfx = }").is_err());
+}
+
+#[test]
+fn test_parse_lua_strings() {
+    let cases = [
+        // As written by `string.format("%q", ...)`
+        (r#""line\10next""#, "line\nnext"),
+        (r#""\0\1\0012""#, "\0\u{1}\u{1}2"),
+        (r#""a\
+b""#, "a\nb"),
+        (r#""\x41\u{E9}\z
+            c""#, "Aéc"),
+        (r#""\226\156\147""#, "✓"),
+        (r#"'\a\b\f\v\\\"'"#, "\u{7}\u{8}\u{c}\u{b}\\\""),
+        ("[[plain \\n]]", "plain \\n"),
+        ("[==[\nfirst]]\nsecond]==]", "first]]\nsecond"),
+    ];
+
+    for (lua, expected) in cases {
+        assert_eq!(parse(lua), Ok(Value::String(expected.to_string())), "{lua}");
+    }
+
+    assert_eq!(
+        parse("return {--[[ long\ncomment ]] [[a]], [ [[b]] ] = 1}").unwrap(),
+        serde_json::json!({ "1": "a", "b": 1 })
+    );
+
+    let value = serde_json::json!({
+        "name": "tab\there \"quoted\" \\ é\u{1}2",
+        "skin": [1, 2.5, -3],
+        "keys": { "left": "a", "end": true, "two words": null, "5": {} },
+    });
+    let (parsed, encoding) = parse_with_encoding(&serialize(&value, Encoding::Lua)).unwrap();
+    assert_eq!(encoding, Encoding::Lua);
+    assert_eq!(parsed, value);
+    assert_eq!(parse_with_encoding(&serialize(&value, Encoding::Json)), Ok((value, Encoding::Json)));
+
+    assert!(parse(r#""\256""#).is_err());
+    assert!(parse(r#""\xZ1""#).is_err());
+    assert!(parse("[[unterminated").is_err());
+}
+
+/// Lists every leaf of a value with its path, e.g. `("keys.left", 37)`.
+pub fn flatten(value: &Value) -> Vec<(String, Value)> {
+    let mut entries: Vec<(String, Value)> = Vec::new();
+    flatten_into(value, "", &mut entries);
+    return entries;
+}
+
+fn flatten_into(value: &Value, prefix: &str, entries: &mut Vec<(String, Value)>) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(object) => object
+            .iter()
+            .map(|(key, child)| (join_key(prefix, key), child))
+            .collect(),
+        Value::Array(array) => array
+            .iter()
+            .enumerate()
+            .map(|(i, child)| (format!("{prefix}[{}]", i + 1), child))
+            .collect(),
+        leaf => {
+            entries.push((prefix.to_string(), leaf.clone()));
+            return;
+        }
+    };
+
+    if children.is_empty() {
+        entries.push((prefix.to_string(), value.clone()));
+    }
+
+    for (key, child) in children {
+        flatten_into(child, &key, entries);
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        return key.to_string();
+    }
+
+    return format!("{prefix}.{key}");
+}
+
+/// A difference between two versions of a data file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Change::Added(key, value) => write!(f, "+ {key} = {value}"),
+            Change::Removed(key, value) => write!(f, "- {key} = {value}"),
+            Change::Changed(key, old, new) => write!(f, "~ {key}: {old} -> {new}"),
+        };
+    }
+}
+
+/// Compares two data files leaf by leaf.
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let old: Map<String, Value> = flatten(old).into_iter().collect();
+    let new: Map<String, Value> = flatten(new).into_iter().collect();
+
+    let mut changes: Vec<Change> = Vec::new();
+
+    for (key, old_value) in &old {
+        match new.get(key) {
+            None => changes.push(Change::Removed(key.clone(), old_value.clone())),
+            Some(new_value) if new_value != old_value =>
+                changes.push(Change::Changed(key.clone(), old_value.clone(), new_value.clone())),
+            _ => {}
+        }
+    }
+
+    for (key, new_value) in &new {
+        if !old.contains_key(key) {
+            changes.push(Change::Added(key.clone(), new_value.clone()));
+        }
+    }
+
+    return changes;
+}
+
+#[test]
+fn test_diff() {
+    let old = parse("return {sfx=8,keys={37,39},old=true}").unwrap();
+    let new = parse("return {sfx=10,keys={37,40},new='yes'}").unwrap();
+
+    let changes: Vec<String> = diff(&old, &new)
+        .iter()
+        .map(Change::to_string)
+        .collect();
+
+    assert_eq!(changes, vec![
+        "~ keys[2]: 39 -> 40",
+        "- old = true",
+        "~ sfx: 8 -> 10",
+        "+ new = \"yes\"",
+    ]);
+}
+
+/// How a file differs between two save directories.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileDiff {
+    OnlyInOld,
+    OnlyInNew,
+    /// Both files are data files
+    Changed(Vec<Change>),
+    /// At least one file could not be parsed, and their contents differ
+    Binary,
+}
+
+/// Compares every file in two save directories.
+/// Files that are the same in both are left out.
+pub fn diff_dirs(old: &Path, new: &Path) -> Vec<(PathBuf, FileDiff)> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    list_files(old, Path::new(""), &mut files);
+    list_files(new, Path::new(""), &mut files);
+    files.sort();
+    files.dedup();
+
+    let mut diffs: Vec<(PathBuf, FileDiff)> = Vec::new();
+
+    for relative in files {
+        let old_path = old.join(&relative);
+        let new_path = new.join(&relative);
+
+        let file_diff = match (old_path.is_file(), new_path.is_file()) {
+            (true, false) => FileDiff::OnlyInOld,
+            (false, true) => FileDiff::OnlyInNew,
+            _ => {
+                if fs::read(&old_path).ok() == fs::read(&new_path).ok() {
+                    continue;
+                }
+
+                match (read_file(&old_path), read_file(&new_path)) {
+                    (Ok(old_value), Ok(new_value)) => FileDiff::Changed(diff(&old_value, &new_value)),
+                    _ => FileDiff::Binary
+                }
+            }
+        };
+
+        diffs.push((relative, file_diff));
+    }
+
+    return diffs;
+}
+
+fn list_files(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+
+    for entry in entries.flatten() {
+        let relative = relative.join(entry.file_name());
+
+        if entry.path().is_dir() {
+            list_files(&entry.path(), &relative, files);
+        } else {
+            files.push(relative);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::save::convert::{self, ConversionReport, GameVersion};
+use crate::save::data::SaveData;
+
+/// A part of a Techmino save that can be imported on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SaveCategory {
+    Settings,
+    Keymap,
+    Unlocks,
+    Records,
+    Replays,
+}
+
+impl SaveCategory {
+    pub const ALL: [SaveCategory; 5] = [
+        SaveCategory::Settings,
+        SaveCategory::Keymap,
+        SaveCategory::Unlocks,
+        SaveCategory::Records,
+        SaveCategory::Replays,
+    ];
+
+    /// The name used in the config file and on the command line.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            SaveCategory::Settings => "settings",
+            SaveCategory::Keymap => "keymap",
+            SaveCategory::Unlocks => "unlocks",
+            SaveCategory::Records => "records",
+            SaveCategory::Replays => "replays",
+        };
+    }
+
+    pub fn description(&self) -> &'static str {
+        return match self {
+            SaveCategory::Settings => "Game, audio and video settings",
+            SaveCategory::Keymap => "Keyboard, gamepad and touch controls",
+            SaveCategory::Unlocks => "Unlocked modes, ranks and statistics",
+            SaveCategory::Records => "Leaderboards of every mode",
+            SaveCategory::Replays => "Saved replays",
+        };
+    }
+
+    pub fn parse(name: &str) -> Option<SaveCategory> {
+        return SaveCategory::ALL
+            .into_iter()
+            .find(|category| category.name() == name.trim().to_lowercase());
+    }
+
+    /// Files and directories of the category, relative to the save directory.
+    ///
+    /// Versions before 0.15 kept their files at the top level as `*.dat`,
+    /// later versions keep them in `conf`.
+    fn paths(&self) -> &'static [&'static str] {
+        return match self {
+            SaveCategory::Settings => &[
+                "conf/settings",
+                "settings.dat",
+            ],
+            SaveCategory::Keymap => &[
+                "conf/key",
+                "conf/virtualkey",
+                "conf/vkSave1",
+                "conf/vkSave2",
+                "key.dat",
+                "virtualkey.dat",
+            ],
+            SaveCategory::Unlocks => &[
+                "conf/unlock",
+                "conf/data",
+                "unlock.dat",
+                "data.dat",
+            ],
+            SaveCategory::Records => &["record"],
+            SaveCategory::Replays => &["replay"],
+        };
+    }
+}
+
+impl fmt::Display for SaveCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.name());
+    }
+}
+
+/// Parses a comma-separated list of categories, such as `settings,keymap`.
+/// `all` stands for every category.
+pub fn parse_categories(list: &str) -> Result<Vec<SaveCategory>, String> {
+    if list.trim() == "all" {
+        return Ok(SaveCategory::ALL.to_vec());
+    }
+
+    let mut categories: Vec<SaveCategory> = Vec::new();
+
+    for name in list.split(',').filter(|name| !name.trim().is_empty()) {
+        match SaveCategory::parse(name) {
+            Some(category) => categories.push(category),
+            None => return Err(format!(
+                "Unknown save category {:?}. Valid categories are: all, {}",
+                name.trim(),
+                SaveCategory::ALL.map(|category| category.name()).join(", ")
+            ))
+        }
+    }
+
+    categories.sort();
+    categories.dedup();
+
+    return Ok(categories);
+}
+
+/// The inverse of [`parse_categories`].
+pub fn format_categories(categories: &[SaveCategory]) -> String {
+    if SaveCategory::ALL.iter().all(|category| categories.contains(category)) {
+        return "all".to_string();
+    }
+
+    return categories
+        .iter()
+        .map(|category| category.name())
+        .collect::<Vec<&str>>()
+        .join(",");
+}
+
+#[test]
+fn test_parse_categories() {
+    assert_eq!(
+        parse_categories("keymap, settings,keymap"),
+        Ok(vec![SaveCategory::Settings, SaveCategory::Keymap])
+    );
+    assert_eq!(parse_categories("all"), Ok(SaveCategory::ALL.to_vec()));
+    assert_eq!(parse_categories(""), Ok(vec![]));
+    assert!(parse_categories("settings,keybinds").is_err());
+
+    assert_eq!(format_categories(&SaveCategory::ALL), "all");
+    assert_eq!(format_categories(&[SaveCategory::Keymap]), "keymap");
+}
+
+/// The outcome of an import.
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    /// Imported files, relative to the save directory
+    pub imported: Vec<PathBuf>,
+    /// What was changed to suit the target version, if it was known
+    pub conversion: Option<ConversionReport>,
+}
+
+/// Copies the given categories from one save directory to another,
+/// converting them to the format of the `target` version if it is known.
+///
+/// Files of those categories already in the destination are replaced,
+/// and everything else in it is left alone.
+pub fn import(
+    source: &Path,
+    destination: &Path,
+    categories: &[SaveCategory],
+    target: Option<GameVersion>
+) -> io::Result<ImportReport> {
+    let paths: Vec<&str> = categories
+        .iter()
+        .flat_map(|category| category.paths().iter().copied())
+        .collect();
+
+    let mut data = SaveData::read(source, &paths)?;
+
+    let conversion = target.map(|target| convert::convert(&mut data, target));
+
+    return Ok(ImportReport {
+        imported: data.write(destination, &paths)?,
+        conversion,
+    });
+}
+
+#[test]
+fn test_import() {
+    use std::fs;
+
+    let root = std::env::temp_dir()
+        .join(format!("boxedmino_test_import_{}", std::process::id()));
+    let source = root.join("source");
+    let destination = root.join("destination");
+
+    fs::create_dir_all(source.join("conf")).unwrap();
+    fs::create_dir_all(source.join("record")).unwrap();
+    fs::create_dir_all(destination.join("conf")).unwrap();
+    fs::write(source.join("conf/key"), "keys").unwrap();
+    fs::write(source.join("conf/settings"), "settings").unwrap();
+    fs::write(source.join("record/sprint_40l.rec"), "record").unwrap();
+    fs::write(destination.join("conf/key"), "old keys").unwrap();
+    fs::write(destination.join("conf/unlock"), "fresh").unwrap();
+
+    let report = import(&source, &destination, &[SaveCategory::Keymap], None).unwrap();
+
+    assert_eq!(report.imported, vec![PathBuf::from("conf/key")]);
+    assert_eq!(fs::read_to_string(destination.join("conf/key")).unwrap(), "keys");
+    assert_eq!(fs::read_to_string(destination.join("conf/unlock")).unwrap(), "fresh");
+    assert!(!destination.join("conf/settings").exists());
+    assert!(!destination.join("record").exists());
+
+    import(&source, &destination, &[SaveCategory::Records], None).unwrap();
+    assert!(destination.join("record/sprint_40l.rec").exists());
+
+    // Importing into an old version moves the files out of `conf`
+    let report = import(&source, &destination, &[SaveCategory::Settings], Some(GameVersion(0, 14, 0))).unwrap();
+    assert_eq!(report.imported, vec![PathBuf::from("settings.dat")]);
+    assert_eq!(fs::read_to_string(destination.join("settings.dat")).unwrap(), "settings");
+
+    fs::remove_dir_all(&root).unwrap();
+}
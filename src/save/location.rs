@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use chrono::{DateTime, Local};
+use crate::conf::Config;
+use crate::dirs::paths;
+use crate::game;
+use crate::git;
+
+/// The identity used when a version's `conf.lua` cannot be read.
+pub const DEFAULT_IDENTITY: &str = "Techmino";
+
+/// How a save directory was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveKind {
+    /// The game was run through LÖVE, e.g. from source
+    Love,
+    /// The game was run from a fused build, such as the official releases
+    Fused,
+}
+
+impl fmt::Display for SaveKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SaveKind::Love => "LÖVE",
+            SaveKind::Fused => "fused build",
+        };
+
+        return write!(f, "{name}");
+    }
+}
+
+/// A directory that may contain a Techmino save.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveCandidate {
+    pub path: PathBuf,
+    pub kind: SaveKind,
+    pub identity: String,
+    /// When anything directly inside the directory was last changed
+    pub modified: Option<SystemTime>,
+}
+
+impl SaveCandidate {
+    fn new(path: PathBuf, kind: SaveKind, identity: &str) -> Self {
+        let modified = get_last_modified(&path);
+
+        return Self {
+            path,
+            kind,
+            identity: identity.to_string(),
+            modified,
+        };
+    }
+
+    pub fn format_modified(&self) -> String {
+        return match self.modified {
+            Some(time) => DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "Unknown".to_string()
+        };
+    }
+}
+
+/// The latest modification time of a directory and the files directly in it,
+/// since writing a file does not always touch the directory itself.
+fn get_last_modified(path: &Path) -> Option<SystemTime> {
+    let mut latest = fs::metadata(path).ok()?.modified().ok();
+
+    for entry in fs::read_dir(path).ok()?.flatten() {
+        let modified = entry.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        if modified > latest {
+            latest = modified;
+        }
+    }
+
+    return latest;
+}
+
+/// Finds the save identity set in a `conf.lua` file,
+/// either through `t.identity` or `love.filesystem.setIdentity`.
+pub fn read_identity(conf_lua: &str) -> Option<String> {
+    for line in conf_lua.lines() {
+        let line = line.trim();
+
+        if line.starts_with("--") {
+            continue;
+        }
+
+        let rest = match line.to_ascii_lowercase().find("identity") {
+            Some(index) => line[index + "identity".len()..].trim_start(),
+            None => continue
+        };
+
+        // `t.identity = "..."` or `setIdentity("...")`
+        let rest = match rest.strip_prefix('=').or(rest.strip_prefix('(')) {
+            Some(rest) => rest.trim_start(),
+            None => continue
+        };
+
+        let quote = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => continue
+        };
+
+        let rest = &rest[1..];
+        let end = match rest.find(quote) {
+            Some(end) => end,
+            None => continue
+        };
+
+        let identity = &rest[..end];
+
+        if !identity.is_empty() {
+            return Some(identity.to_string());
+        }
+    }
+
+    return None;
+}
+
+#[test]
+fn test_read_identity() {
+    let cases = [
+        ("function love.conf(t)\n    t.identity='Techmino'\nend", Some("Techmino")),
+        ("t.identity = \"Techmino_dev\" -- for testing", Some("Techmino_dev")),
+        ("love.filesystem.setIdentity(\"_tmp_boxedmino\");", Some("_tmp_boxedmino")),
+        ("-- t.identity = 'commented'\nt.window.title = 'Techmino'", None),
+        ("t.identity = nil", None),
+    ];
+
+    for (conf_lua, expected) in cases {
+        assert_eq!(read_identity(conf_lua).as_deref(), expected, "{conf_lua}");
+    }
+}
+
+/// The identity a version of the game saves under, read from its `conf.lua`.
+pub fn get_identity(repo_path: &str, version: &str) -> Option<String> {
+    let conf_lua = git::show_file(repo_path, version, "conf.lua")?;
+
+    // Boxedmino's own injection must not be mistaken for the game's identity
+    let conf_lua = game::remove_injection(&conf_lua).unwrap_or(conf_lua);
+
+    return read_identity(&conf_lua);
+}
+
+/// The identity set by each commit already read, or `None` if it sets none.
+/// A commit never changes, so this is kept across runs instead of reading every tag again.
+type IdentityCache = BTreeMap<String, Option<String>>;
+
+fn load_identity_cache() -> IdentityCache {
+    return fs::read_to_string(paths::get_identity_cache_path())
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+}
+
+fn save_identity_cache(cache: &IdentityCache) -> io::Result<()> {
+    let path = paths::get_identity_cache_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    return fs::write(path, serde_json::to_string(cache)?);
+}
+
+/// Every identity used by a tagged version of the game, plus the default one.
+pub fn get_identities(repo_path: &str) -> Vec<String> {
+    let mut identities: BTreeSet<String> = BTreeSet::new();
+    identities.insert(DEFAULT_IDENTITY.to_string());
+
+    if repo_path.is_empty() {
+        return identities.into_iter().collect();
+    }
+
+    let mut cache = load_identity_cache();
+    let mut changed = false;
+
+    for details in git::tag_details(repo_path) {
+        let identity = cache
+            .entry(details.commit.clone())
+            .or_insert_with(|| {
+                changed = true;
+                get_identity(repo_path, &details.commit)
+            });
+
+        if let Some(identity) = identity {
+            identities.insert(identity.clone());
+        }
+    }
+
+    if changed {
+        if let Err(e) = save_identity_cache(&cache) {
+            eprintln!("Failed to cache the save identities of the game versions: {e}");
+        }
+    }
+
+    return identities.into_iter().collect();
+}
+
+/// The directories holding a save directory per identity.
+/// In portable mode, only the portable one is searched, so nothing is read from the host machine.
+fn get_search_roots() -> Vec<(PathBuf, SaveKind)> {
+    if let Some(root) = paths::get_portable_love_save_root() {
+        return vec![(root, SaveKind::Love)];
+    }
+
+    return vec![
+        (paths::get_love_save_root_path(), SaveKind::Love),
+        (paths::get_love_appdata_path(), SaveKind::Fused),
+    ];
+}
+
+/// Looks for existing save directories of every identity the game has used,
+/// both for LÖVE and for fused builds. The most recently used comes first.
+pub fn find_candidates(repo_path: &str) -> Vec<SaveCandidate> {
+    let roots = get_search_roots();
+
+    let mut candidates: Vec<SaveCandidate> = Vec::new();
+
+    for identity in get_identities(repo_path) {
+        for (root, kind) in &roots {
+            let path = root.join(&identity);
+
+            if path.is_dir() {
+                candidates.push(SaveCandidate::new(path, *kind, &identity));
+            }
+        }
+    }
+
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.modified));
+
+    return candidates;
+}
+
+/// The save directory to import from: the one chosen in the settings,
+/// or else the most recently used candidate.
+pub fn find_save_path(cfg: &Config) -> Result<PathBuf, String> {
+    if !cfg.source_save_path.is_empty() {
+        let path = PathBuf::from(&cfg.source_save_path);
+
+        if !path.is_dir() {
+            return Err(format!(
+                "The save directory chosen in the settings does not exist: {}",
+                path.to_string_lossy()
+            ));
+        }
+
+        return Ok(path);
+    }
+
+    if let Some(candidate) = find_candidates(&cfg.game_repo_path).into_iter().next() {
+        return Ok(candidate.path);
+    }
+
+    let roots: Vec<String> = get_search_roots()
+        .iter()
+        .map(|(root, _)| root.to_string_lossy().to_string())
+        .collect();
+
+    return Err(format!(
+        "Could not find a Techmino save directory. Looked in {} for the identities: {}\nChoose the save directory in the settings instead.",
+        roots.join(" and "),
+        get_identities(&cfg.game_repo_path).join(", ")
+    ));
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+use crate::save::format::{self, FileDiff};
+use crate::slint_types::{SaveDiffLine, SaveViewerWindow};
+use rfd::FileDialog;
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+fn line(file: &str, change: String) -> SaveDiffLine {
+    return SaveDiffLine {
+        file: file.into(),
+        change: change.into(),
+    };
+}
+
+/// Compares two save directories or two save files, one line per change.
+fn compare(old: &Path, new: &Path) -> Vec<SaveDiffLine> {
+    if old.is_dir() && new.is_dir() {
+        let mut lines: Vec<SaveDiffLine> = Vec::new();
+
+        for (file, file_diff) in format::diff_dirs(old, new) {
+            let file = file.to_string_lossy();
+
+            match file_diff {
+                FileDiff::OnlyInOld => lines.push(line(&file, "Only in old save".to_string())),
+                FileDiff::OnlyInNew => lines.push(line(&file, "Only in new save".to_string())),
+                FileDiff::Binary => lines.push(line(&file, "Contents differ".to_string())),
+                FileDiff::Changed(changes) => lines.extend(
+                    changes.iter().map(|change| line(&file, change.to_string()))
+                ),
+            }
+        }
+
+        return lines;
+    }
+
+    return match (format::read_file(old), format::read_file(new)) {
+        (Ok(old_value), Ok(new_value)) => format::diff(&old_value, &new_value)
+            .iter()
+            .map(|change| line("", change.to_string()))
+            .collect(),
+        (Err(e), _) | (_, Err(e)) => vec![line("Error", e)]
+    };
+}
+
+/// Opens a window comparing two saves, such as the sandbox and the main save.
+pub fn open(sandbox_path: &Path, main_save_path: Option<&Path>) -> Result<(), slint::PlatformError> {
+    let window = SaveViewerWindow::new()?;
+
+    let sandbox_path = sandbox_path.to_string_lossy().to_string();
+    let main_save_path = main_save_path
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    window.set_sandbox_path(sandbox_path.clone().into());
+    window.set_main_save_path(main_save_path.clone().into());
+    window.set_old_path(main_save_path.into());
+    window.set_new_path(sandbox_path.into());
+
+    window.on_compare(|old, new| {
+        let lines = compare(Path::new(old.as_str()), Path::new(new.as_str()));
+        return ModelRc::new(VecModel::from(lines));
+    });
+
+    window.on_browse(|current_path| {
+        return FileDialog::new()
+            .set_title("Choose a save directory")
+            .pick_folder()
+            .map(|path| SharedString::from(path.to_string_lossy().as_ref()))
+            .unwrap_or(current_path);
+    });
+
+    window.run()?;
+
+    return Ok(());
+}
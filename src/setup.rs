@@ -1,10 +1,16 @@
+use crate::archives;
+use crate::bundle;
 use crate::error_window;
 use crate::slint_types::SetupWindow;
 use crate::git;
 use crate::conf;
 use crate::dirs;
+use crate::progress::ProgressStyle;
+use crate::transfer::{self, CloneMode};
+use clap::ValueEnum;
 use rfd::FileDialog;
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
 use slint::ComponentHandle;
 
@@ -32,14 +38,66 @@ pub fn run_setup() -> Result<(), slint::PlatformError> {
         window_clone.set_dir_empty(empty);
     });
 
-    setup_window.on_clone_repo(|path| {
-        if let Err(e) = git::clone(path) {
-            error_window::open_safe(
+    setup_window.on_clone_repo(|path, mode| {
+        let mode = CloneMode::value_variants()
+            .get(mode as usize)
+            .copied()
+            .unwrap_or_default();
+
+        match transfer::clone(Path::new(path.as_str()), mode, ProgressStyle::Window) {
+            Ok(()) => {}
+            Err(e) if e == transfer::CANCELLED_MESSAGE => {}
+            Err(e) => error_window::open_safe(
                 None,
                 Some("Failed to clone repository".to_string()),
                 Some(format!("Error: {}", e))
-            );
+            ),
+        }
+    });
+
+    setup_window.on_init_from_bundle(|path| {
+        let bundle_path = FileDialog::new()
+            .set_title("Choose a bundle made with `boxedmino repo export-bundle`")
+            .add_filter("Boxedmino bundle", &["zip"])
+            .pick_file();
+
+        let bundle_path = match bundle_path {
+            Some(bundle_path) => bundle_path,
+            None => return "".into()
+        };
+
+        return match bundle::init_repo(&bundle_path, path.as_str(), ProgressStyle::Window) {
+            Ok(_) => "".into(),
+            Err(e) if e == transfer::CANCELLED_MESSAGE => "".into(),
+            Err(e) => e.into(),
+        };
+    });
+
+    // Set if an archive was registered instead of setting up a repository
+    let archive_chosen = Rc::new(RefCell::new(false));
+
+    let window_clone = setup_window.clone();
+    let archive_chosen_clone = archive_chosen.clone();
+    setup_window.on_use_archive(move || {
+        let path = FileDialog::new()
+            .add_filter("Game archive", &["love", "zip"])
+            .pick_file();
+
+        let path = match path {
+            Some(path) => path,
+            None => return "".into()
+        };
+
+        if let Err(e) = archives::register(&path.to_string_lossy(), None) {
+            return format!("Failed to register archive: {e}").into();
         }
+
+        *archive_chosen_clone.borrow_mut() = true;
+        window_clone.as_weak().unwrap().hide().expect(
+            "Failed to close setup window"
+        );
+
+        return "".into();
     });
 
     let window_clone = setup_window.clone();
@@ -53,12 +111,17 @@ pub fn run_setup() -> Result<(), slint::PlatformError> {
 
     setup_window.run()?;
 
+    // The repository can still be set up later in the settings
+    if *archive_chosen.borrow() {
+        return Ok(());
+    }
+
     // Check if setup finished properly
     if *setup_finished.borrow() {
         let mut config = conf::Config::load();
         config.repo_initialized = true;
         config.game_repo_path = setup_window.get_game_repo_path().to_string();
-        config.save();
+        config.save_keys(&["repo_initialized", "game_repo_path"]);
         Ok(())
     } else {
         panic!("Setup closed prematurely");
@@ -1 +1 @@
-slint::include_modules!();
\ No newline at end of file
+slint::include_modules!();
@@ -0,0 +1,127 @@
+use std::path::Path;
+use serde::Serialize;
+use crate::cold_clear::install::InstallMarker;
+use crate::conf::Config;
+use crate::dirs::paths;
+use crate::git;
+use crate::history::{self, RunRecord};
+use crate::output;
+use crate::save;
+
+#[derive(Serialize, Debug)]
+struct RepoStatus {
+    path: String,
+    valid: bool,
+    commit: Option<String>,
+    /// The closest tag to the checked out commit
+    version: Option<String>,
+    /// `None` if the HEAD is detached
+    branch: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ColdClearStatus {
+    enabled: bool,
+    version: String,
+    /// The version in the sandbox, if any
+    installed_version: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Status {
+    boxedmino_version: &'static str,
+    portable: bool,
+    config_path: String,
+    config_dir: String,
+    cache_dir: String,
+    state_dir: String,
+    sandbox_path: String,
+    /// The main save that is imported into the sandbox
+    save_path: Option<String>,
+    repo: RepoStatus,
+    cold_clear: ColdClearStatus,
+    last_run: Option<RunRecord>,
+}
+
+fn path_string(path: &Path) -> String {
+    return path.to_string_lossy().to_string();
+}
+
+fn get_status(cfg: &Config) -> Status {
+    let repo_path = &cfg.game_repo_path;
+    let valid = git::is_repo_valid(repo_path);
+
+    return Status {
+        boxedmino_version: env!("CARGO_PKG_VERSION"),
+        portable: paths::get_portable_dir_path().is_some(),
+        config_path: path_string(&paths::get_config_path()),
+        config_dir: path_string(&paths::get_conf_dir_path()),
+        cache_dir: path_string(&paths::get_cache_dir_path()),
+        state_dir: path_string(&paths::get_state_dir_path()),
+        sandbox_path: path_string(&paths::get_sandboxed_save_path()),
+        save_path: save::location::find_save_path(cfg)
+            .ok()
+            .map(|path| path_string(&path)),
+        repo: RepoStatus {
+            path: repo_path.clone(),
+            valid,
+            commit: valid.then(|| git::resolve_commit(repo_path, "HEAD")).flatten(),
+            version: valid.then(|| git::describe_tag(repo_path)).flatten(),
+            branch: valid.then(|| git::current_branch(repo_path)).flatten(),
+        },
+        cold_clear: ColdClearStatus {
+            enabled: cfg.use_cold_clear,
+            version: cfg.cold_clear_version.clone(),
+            installed_version: InstallMarker::load(&paths::get_sandboxed_lib_path())
+                .map(|marker| marker.version),
+        },
+        last_run: history::load().pop(),
+    };
+}
+
+fn print_text(status: &Status) {
+    let none = "none".to_string();
+
+    println!("Boxedmino version: {}", status.boxedmino_version);
+    println!("Portable: {}", status.portable);
+    println!("Config file: {}", status.config_path);
+    println!("Config directory: {}", status.config_dir);
+    println!("Cache directory: {}", status.cache_dir);
+    println!("State directory: {}", status.state_dir);
+    println!("Sandbox: {}", status.sandbox_path);
+    println!("Main save: {}", status.save_path.as_ref().unwrap_or(&none));
+    println!("Game repository: {}", status.repo.path);
+
+    if status.repo.valid {
+        println!("  Commit: {}", status.repo.commit.as_ref().unwrap_or(&none));
+        println!("  Version: {}", status.repo.version.as_ref().unwrap_or(&none));
+        println!("  Branch: {}", status.repo.branch.as_deref().unwrap_or("detached HEAD"));
+    } else {
+        println!("  Not a valid Techmino repository");
+    }
+
+    println!("Cold Clear: {}", if status.cold_clear.enabled { "enabled" } else { "disabled" });
+    println!("  Version: {}", status.cold_clear.version);
+    println!("  Installed: {}", status.cold_clear.installed_version.as_ref().unwrap_or(&none));
+
+    match &status.last_run {
+        Some(run) => {
+            print!("Last run: ");
+            run.print();
+        }
+        None => println!("Last run: never"),
+    }
+}
+
+/// Runs `boxedmino status`, returning the exit code.
+pub fn run(cfg: &Config) -> i32 {
+    let status = get_status(cfg);
+
+    if output::is_json() {
+        output::print_json(&status);
+    } else {
+        print_text(&status);
+    }
+
+    return 0;
+}
@@ -0,0 +1,510 @@
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use clap::ValueEnum;
+use slint::{ComponentHandle, SharedString};
+use crate::conf::Config;
+use crate::dirs;
+use crate::git;
+use crate::info;
+use crate::output;
+use crate::progress::{render_bar, ProgressStyle};
+use crate::slint_types::GitTransferWindow;
+
+/// The error returned when the user cancels a clone or fetch.
+pub const CANCELLED_MESSAGE: &str = "Cancelled by the user";
+
+/// How much of the game repository to download.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CloneMode {
+    /// Every version with all of its files
+    #[default]
+    Full,
+    /// Every version, but the files of old versions are downloaded when they are first opened
+    Partial,
+    /// Only the latest version of the default branch, without the older versions
+    Shallow,
+}
+
+impl CloneMode {
+    fn get_args(self) -> &'static [&'static str] {
+        return match self {
+            CloneMode::Full => &[],
+            CloneMode::Partial => &["--filter=blob:none"],
+            CloneMode::Shallow => &["--depth", "1"],
+        };
+    }
+}
+
+/// A progress update printed by git with `--progress`, e.g.
+/// `Receiving objects:  45% (1234/2742), 12.30 MiB | 2.05 MiB/s`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GitProgress {
+    pub phase: String,
+    pub percent: u32,
+    pub done: u64,
+    pub total: u64,
+    /// Amount of data received so far, as formatted by git
+    pub received: Option<String>,
+    pub throughput: Option<String>,
+}
+
+fn parse_progress_line(line: &str) -> Option<GitProgress> {
+    let line = line.trim();
+    let line = line.strip_prefix("remote:").unwrap_or(line).trim_start();
+
+    let (phase, rest) = line.split_once(':')?;
+    let (percent, rest) = rest.trim_start().split_once('%')?;
+    let (counts, rest) = rest.trim_start().strip_prefix('(')?.split_once(')')?;
+    let (done, total) = counts.split_once('/')?;
+
+    let rest = rest.trim_end_matches(", done.");
+    let (received, throughput) = match rest.strip_prefix(", ").and_then(|rest| rest.split_once(" | ")) {
+        Some((received, throughput)) => (Some(received.trim().to_string()), Some(throughput.trim().to_string())),
+        None => (None, None),
+    };
+
+    return Some(GitProgress {
+        phase: phase.trim().to_string(),
+        percent: percent.trim().parse().ok()?,
+        done: done.parse().ok()?,
+        total: total.parse().ok()?,
+        received,
+        throughput,
+    });
+}
+
+/// Reads git's stderr until it is closed, passing progress updates to `on_progress`.
+/// Returns the other lines, which hold git's messages and errors.
+fn read_output(mut stderr: impl Read, mut on_progress: impl FnMut(GitProgress)) -> Vec<String> {
+    let mut messages = vec![];
+    let mut line: Vec<u8> = vec![];
+    let mut buffer = [0u8; 4096];
+
+    let mut end_line = |line: &mut Vec<u8>, messages: &mut Vec<String>| {
+        let text = String::from_utf8_lossy(line).trim().to_string();
+        line.clear();
+
+        if text.is_empty() {
+            return;
+        }
+
+        match parse_progress_line(&text) {
+            Some(progress) => on_progress(progress),
+            None => messages.push(text),
+        }
+    };
+
+    loop {
+        let read = match stderr.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+
+        // Progress lines are redrawn with '\r', finished ones end with '\n'
+        for &byte in &buffer[..read] {
+            match byte {
+                b'\r' | b'\n' => end_line(&mut line, &mut messages),
+                _ => line.push(byte),
+            }
+        }
+    }
+
+    end_line(&mut line, &mut messages);
+
+    return messages;
+}
+
+fn render_progress_line(progress: &GitProgress) -> String {
+    let mut line = format!(
+        "[{}] {:>3}% | {} ({}/{})",
+        render_bar(progress.percent as f64 / 100.0),
+        progress.percent,
+        progress.phase,
+        progress.done,
+        progress.total
+    );
+
+    if let (Some(received), Some(throughput)) = (&progress.received, &progress.throughput) {
+        line.push_str(&format!(" | {received} | {throughput}"));
+    }
+
+    return line;
+}
+
+#[test]
+fn test_read_output() {
+    let stderr = concat!(
+        "Cloning into 'Techmino'...\n",
+        "remote: Enumerating objects: 2742, done.\n",
+        "remote: Counting objects:  50% (1/2)\rremote: Counting objects: 100% (2/2), done.\n",
+        "Receiving objects:  45% (1234/2742), 12.30 MiB | 2.05 MiB/s\r",
+        "Receiving objects: 100% (2742/2742), 27.01 MiB | 2.10 MiB/s, done.\n",
+        "Resolving deltas: 100% (1500/1500), done.\n",
+        "fatal: unable to write file",
+    );
+
+    let mut updates = vec![];
+    let messages = read_output(stderr.as_bytes(), |progress| updates.push(progress));
+
+    assert_eq!(messages, vec![
+        "Cloning into 'Techmino'...",
+        "remote: Enumerating objects: 2742, done.",
+        "fatal: unable to write file",
+    ]);
+    assert_eq!(updates.len(), 5);
+
+    assert_eq!(updates[0].phase, "Counting objects");
+    assert_eq!(updates[2], GitProgress {
+        phase: "Receiving objects".to_string(),
+        percent: 45,
+        done: 1234,
+        total: 2742,
+        received: Some("12.30 MiB".to_string()),
+        throughput: Some("2.05 MiB/s".to_string()),
+    });
+    assert_eq!(updates[3].throughput.as_deref(), Some("2.10 MiB/s"));
+    assert_eq!(updates[4].received, None);
+
+    assert_eq!(
+        render_progress_line(&updates[2]),
+        "[###########-------------]  45% | Receiving objects (1234/2742) | 12.30 MiB | 2.05 MiB/s"
+    );
+}
+
+/// Draws git's progress on stderr, one line per phase.
+///
+/// If stderr is not a terminal, only the phases are listed.
+struct TerminalProgress {
+    phase: String,
+    is_terminal: bool,
+    last_line_len: usize,
+}
+
+impl TerminalProgress {
+    fn new() -> Self {
+        return Self {
+            phase: String::new(),
+            is_terminal: io::stderr().is_terminal(),
+            last_line_len: 0,
+        };
+    }
+
+    fn update(&mut self, progress: &GitProgress) {
+        if progress.phase != self.phase {
+            self.end_line();
+            self.phase = progress.phase.clone();
+
+            if !self.is_terminal {
+                eprintln!("{}...", progress.phase);
+            }
+        }
+
+        if !self.is_terminal {
+            return;
+        }
+
+        let line = render_progress_line(progress);
+        let padding = " ".repeat(self.last_line_len.saturating_sub(line.len()));
+        self.last_line_len = line.len();
+
+        eprint!("\r{line}{padding}");
+        io::stderr().flush().ok();
+    }
+
+    fn end_line(&mut self) {
+        if self.is_terminal && self.last_line_len > 0 {
+            eprintln!();
+        }
+        self.last_line_len = 0;
+    }
+}
+
+fn spawn(args: &[&str], dir: &Path) -> Result<(Child, ChildStderr), String> {
+    let mut child = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        // Without a terminal to answer in, a credential prompt would wait forever
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run `git {}`: {e}", args.join(" ")))?;
+
+    let stderr = child.stderr.take()
+        .expect("stderr of git should be piped");
+
+    return Ok((child, stderr));
+}
+
+fn wait(child: &mut Child, messages: &[String]) -> Result<(), String> {
+    let status = child.wait()
+        .map_err(|e| format!("Failed to wait for git: {e}"))?;
+
+    if !status.success() {
+        // The errors come last, after messages such as "Cloning into ..."
+        let start = messages.len().saturating_sub(5);
+        return Err(messages[start..].join("\n"));
+    }
+
+    return Ok(());
+}
+
+/// Runs git on the current thread, drawing its progress on stderr unless `show_progress` is unset.
+/// Stopping it is left to Ctrl+C, which git handles by cleaning up after itself.
+fn run_in_terminal(args: &[&str], dir: &Path, show_progress: bool) -> Result<(), String> {
+    let (mut child, stderr) = spawn(args, dir)?;
+
+    let mut progress = TerminalProgress::new();
+
+    let messages = read_output(stderr, |update| {
+        if show_progress {
+            progress.update(&update);
+        }
+    });
+
+    progress.end_line();
+
+    return wait(&mut child, &messages);
+}
+
+/// Runs git while showing its progress in a `GitTransferWindow`, which can cancel it.
+fn run_in_window(label: &str, args: &[&str], dir: &Path) -> Result<(), String> {
+    let window = GitTransferWindow::new()
+        .map_err(|e| format!("Failed to open the progress window: {e}"))?;
+
+    window.set_operation(label.into());
+
+    let (child, stderr) = spawn(args, dir)?;
+    let child = Arc::new(Mutex::new(child));
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let child_clone = child.clone();
+    let interrupted_clone = interrupted.clone();
+    let window_weak = window.as_weak();
+    window.on_interrupt(move || {
+        interrupted_clone.store(true, Ordering::Relaxed);
+        let _ = child_clone.lock().unwrap().kill();
+        window_weak
+            .unwrap().window().hide()
+            .expect("Failed to hide git progress window");
+    });
+
+    let window_weak = window.as_weak();
+    let finished_clone = finished.clone();
+    let output_thread = thread::spawn(move || {
+        let progress_weak = window_weak.clone();
+
+        let messages = read_output(stderr, move |progress| {
+            let counts: SharedString = format!("{} / {}", progress.done, progress.total).into();
+            let throughput: SharedString = match (progress.received, progress.throughput) {
+                (Some(received), Some(throughput)) => format!("{received} | {throughput}").into(),
+                _ => "".into()
+            };
+
+            let _ = progress_weak.upgrade_in_event_loop(move |window| {
+                window.set_phase(progress.phase.into());
+                window.set_progress(progress.percent as f32 / 100.0);
+                window.set_indeterminate(false);
+                window.set_counts(counts);
+                window.set_throughput(throughput);
+            });
+        });
+
+        finished_clone.store(true, Ordering::Relaxed);
+
+        window_weak.upgrade_in_event_loop(|window| {
+            window.set_finished(true);
+            window.hide().expect("Failed to hide git progress window");
+        }).expect("Error upgrading weak ref on event loop while finishing");
+
+        return messages;
+    });
+
+    window.run().expect("Failed to show git progress window");
+
+    // The window may also have been closed by the user
+    if !finished.load(Ordering::Relaxed) {
+        interrupted.store(true, Ordering::Relaxed);
+        let _ = child.lock().unwrap().kill();
+    }
+
+    let messages = output_thread.join().expect("Failed to join git output thread");
+    let mut child = child.lock().unwrap();
+
+    if interrupted.load(Ordering::Relaxed) {
+        let _ = child.wait();
+        return Err(CANCELLED_MESSAGE.to_string());
+    }
+
+    return wait(&mut child, &messages);
+}
+
+fn run(label: &str, args: &[&str], dir: &Path, style: ProgressStyle) -> Result<(), String> {
+    match style {
+        ProgressStyle::Window => return run_in_window(label, args, dir),
+        ProgressStyle::Terminal => {
+            eprintln!("{label}...");
+            return run_in_terminal(args, dir, true);
+        }
+        ProgressStyle::Quiet => return run_in_terminal(args, dir, false),
+    }
+}
+
+/// Removes what a failed clone left behind.
+/// The directory itself is kept if it existed before the clone.
+fn remove_partial_clone(path: &Path, existed: bool) {
+    if !existed {
+        let _ = fs::remove_dir_all(path);
+        return;
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+
+    for entry in entries.flatten() {
+        let _ = match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => fs::remove_dir_all(entry.path()),
+            _ => fs::remove_file(entry.path()),
+        };
+    }
+}
+
+/// Clones any repository, such as a local git bundle, like [`clone`].
+pub fn clone_from(url: &str, path: &Path, mode: CloneMode, style: ProgressStyle) -> Result<(), String> {
+    let existed = path.exists();
+
+    if existed && !dirs::is_dir_empty(&path.to_string_lossy()) {
+        return Err(format!("Cannot download into {}, as it is not an empty directory", path.to_string_lossy()));
+    }
+
+    let target = path.to_string_lossy();
+
+    let mut args = vec!["clone", "--progress"];
+    args.extend(mode.get_args());
+    args.extend([url, &target]);
+
+    let result = run("Downloading the game repository", &args, Path::new("."), style);
+
+    if result.is_err() {
+        remove_partial_clone(path, existed);
+    }
+
+    return result;
+}
+
+/// Clones the game repository into `path`, which must be an empty directory or not exist yet.
+/// If the clone fails or is cancelled, whatever was downloaded is removed.
+pub fn clone(path: &Path, mode: CloneMode, style: ProgressStyle) -> Result<(), String> {
+    return clone_from(git::REPO_LINK, path, mode, style);
+}
+
+#[test]
+fn test_clone_from() {
+    let scratch = git::ScratchRepo::new("transfer");
+    let root = &scratch.root;
+
+    fs::write(scratch.path.join("conf.lua"), "return {}\n").unwrap();
+    scratch.git(&["add", "."]);
+    scratch.git(&["commit", "--quiet", "--message", "first"]);
+
+    let url = format!("file://{}", scratch.path_str());
+
+    let copy = root.join("copy");
+    clone_from(&url, &copy, CloneMode::Partial, ProgressStyle::Quiet).unwrap();
+    assert!(git::is_repo_valid(&copy.to_string_lossy()));
+    assert!(clone_from(&url, &copy, CloneMode::Full, ProgressStyle::Quiet).is_err());
+
+    let missing_url = format!("file://{}", root.join("missing").to_string_lossy());
+
+    let new_dir = root.join("new");
+    assert!(clone_from(&missing_url, &new_dir, CloneMode::Full, ProgressStyle::Quiet).is_err());
+    assert!(!new_dir.exists());
+
+    let empty_dir = root.join("empty");
+    fs::create_dir_all(&empty_dir).unwrap();
+    assert!(clone_from(&missing_url, &empty_dir, CloneMode::Shallow, ProgressStyle::Quiet).is_err());
+    assert!(dirs::is_dir_empty(&empty_dir.to_string_lossy()));
+}
+
+/// Fetches from a remote of the game repository, with any extra arguments such as refspecs.
+pub fn fetch(repo_path: &str, remote: &str, args: &[&str], style: ProgressStyle) -> Result<(), String> {
+    let mut fetch_args = vec!["fetch", "--progress", remote];
+    fetch_args.extend(args);
+
+    return run(&format!("Fetching from {remote}"), &fetch_args, Path::new(repo_path), style);
+}
+
+/// Fetches the new tags and branches from `origin`.
+/// Returns the new tags.
+pub fn fetch_versions(repo_path: &str, style: ProgressStyle) -> Result<Vec<String>, String> {
+    let old_tags = git::tags(repo_path);
+
+    fetch(repo_path, "origin", &["--tags"], style)?;
+
+    return Ok(git::tags(repo_path)
+        .into_iter()
+        .filter(|tag| !old_tags.contains(tag))
+        .collect());
+}
+
+/// Runs `boxedmino repo clone`, returning the exit code.
+/// The clone becomes the game repository in the config.
+pub fn run_clone(path: &str, mode: CloneMode, style: ProgressStyle) -> i32 {
+    if let Err(e) = clone(Path::new(path), mode, style) {
+        eprintln!("Failed to download the game repository: {e}");
+        return 1;
+    }
+
+    let path = fs::canonicalize(path)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or(path.to_string());
+
+    let mut config = Config::load();
+    config.repo_initialized = true;
+    config.game_repo_path = path.clone();
+    config.save_keys(&["repo_initialized", "game_repo_path"]);
+
+    if output::is_json() {
+        output::print_json(&serde_json::json!({ "path": path }));
+    } else {
+        println!("Downloaded the game repository to {path}");
+        info!("It is now the game repository in the settings.");
+    }
+
+    return 0;
+}
+
+/// Runs `boxedmino repo fetch`, returning the exit code.
+pub fn run_fetch(repo_path: &str, style: ProgressStyle) -> i32 {
+    let new_tags = match fetch_versions(repo_path, style) {
+        Ok(new_tags) => new_tags,
+        Err(e) => {
+            eprintln!("Failed to fetch new versions: {e}");
+            return 1;
+        }
+    };
+
+    if output::is_json() {
+        output::print_json(&serde_json::json!({ "new_tags": new_tags }));
+        return 0;
+    }
+
+    match new_tags.is_empty() {
+        true => println!("No new tags"),
+        false => println!("New tags: {}", new_tags.join(", ")),
+    }
+
+    return 0;
+}